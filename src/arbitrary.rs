@@ -0,0 +1,186 @@
+//! Proptest strategies for random filesystem trees, enabled by the `proptest`
+//! feature.
+//!
+//! `MockFS` implements `Arbitrary`, so `any::<MockFS>()` produces random
+//! filesystems using the default `TreeConfig`, and `any_with::<MockFS>(config)`
+//! allows the shape of the tree to be controlled.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::Path;
+//! use proptest::prelude::*;
+//! use inscenerator_xfs::arbitrary::TreeConfig;
+//! use inscenerator_xfs::mockfs::MockFS;
+//! use inscenerator_xfs::XfsReadOnly;
+//!
+//! proptest! {
+//!     fn root_is_a_directory(fs in any_with::<MockFS>(TreeConfig {
+//!         max_depth: 2,
+//!         ..TreeConfig::default()
+//!     })) {
+//!         prop_assert!(fs.is_dir(Path::new("/")));
+//!     }
+//! }
+//!
+//! root_is_a_directory();
+//! ```
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use proptest::prelude::*;
+
+use crate::mockfs::MockFS;
+use crate::Xfs;
+
+/// Controls the shape of generated filesystem trees.
+#[derive(Debug, Clone)]
+pub struct TreeConfig {
+    /// The maximum number of directory levels below the root.
+    pub max_depth: u32,
+    /// The maximum number of entries in any directory.
+    pub max_fan_out: usize,
+    /// The characters used to build entry names.
+    pub name_alphabet: Vec<char>,
+    /// The maximum length, in characters, of an entry name.
+    pub max_name_len: usize,
+    /// Whether some names should be invalid UTF-8. Only honoured on Unix,
+    /// where such names can be represented.
+    pub non_utf8_names: bool,
+    /// The maximum size, in bytes, of a file.
+    pub max_file_size: usize,
+}
+
+impl Default for TreeConfig {
+    fn default() -> Self {
+        TreeConfig {
+            max_depth: 3,
+            max_fan_out: 4,
+            name_alphabet: "abcdefghijklmnopqrstuvwxyz0123456789_-.".chars().collect(),
+            max_name_len: 8,
+            non_utf8_names: false,
+            max_file_size: 64,
+        }
+    }
+}
+
+/// A generated filesystem tree, before it is loaded into a filesystem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsTree {
+    File(Vec<u8>),
+    Directory(BTreeMap<OsString, FsTree>),
+}
+
+impl FsTree {
+    /// Writes the tree into `fs` at `root`, which must be a directory if the
+    /// tree is one.
+    pub fn write_to(&self, fs: &mut dyn Xfs, root: &Path) -> crate::Result<()> {
+        match self {
+            FsTree::File(data) => {
+                let mut w = fs.writer(root)?;
+                w.write_all(data).map_err(|e| crate::XfsError::IoError {
+                    path: root.to_path_buf(),
+                    source: e,
+                })
+            }
+            FsTree::Directory(entries) => {
+                fs.create_dir_all(root)?;
+                for (name, child) in entries {
+                    child.write_to(fs, &root.join(name))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Builds a `MockFS` whose root directory holds this tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree is a single file rather than a directory.
+    pub fn to_mockfs(&self) -> MockFS {
+        assert!(
+            matches!(self, FsTree::Directory(_)),
+            "the root of a MockFS must be a directory"
+        );
+        let mut fs = MockFS::new();
+        self.write_to(&mut fs, &PathBuf::from("/")).unwrap();
+        fs
+    }
+}
+
+fn utf8_name(config: &TreeConfig) -> BoxedStrategy<OsString> {
+    let alphabet = config.name_alphabet.clone();
+    assert!(!alphabet.is_empty(), "name alphabet must not be empty");
+    prop::collection::vec(
+        prop::sample::select(alphabet),
+        1..=config.max_name_len.max(1),
+    )
+    .prop_map(|chars| chars.into_iter().collect::<String>())
+    .prop_filter("names must not be . or ..", |s| s != "." && s != "..")
+    .prop_map(OsString::from)
+    .boxed()
+}
+
+#[cfg(unix)]
+fn name(config: &TreeConfig) -> BoxedStrategy<OsString> {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let utf8 = utf8_name(config);
+    if !config.non_utf8_names {
+        return utf8;
+    }
+    // Splice a byte that can never appear in UTF-8 into a valid name.
+    let non_utf8 = (utf8_name(config), any::<prop::sample::Index>())
+        .prop_map(|(name, index)| {
+            let mut bytes = name.as_bytes().to_vec();
+            bytes.insert(index.index(bytes.len() + 1), 0xff);
+            OsString::from_vec(bytes)
+        })
+        .boxed();
+    prop_oneof![3 => utf8, 1 => non_utf8].boxed()
+}
+
+#[cfg(not(unix))]
+fn name(config: &TreeConfig) -> BoxedStrategy<OsString> {
+    utf8_name(config)
+}
+
+fn directory(config: &TreeConfig, child: BoxedStrategy<FsTree>) -> impl Strategy<Value = FsTree> {
+    prop::collection::btree_map(name(config), child, 0..=config.max_fan_out)
+        .prop_map(FsTree::Directory)
+}
+
+/// A strategy for trees whose root is a directory.
+pub fn tree(config: TreeConfig) -> BoxedStrategy<FsTree> {
+    let file = prop::collection::vec(any::<u8>(), 0..=config.max_file_size)
+        .prop_map(FsTree::File)
+        .boxed();
+    if config.max_depth == 0 {
+        return directory(&config, file).boxed();
+    }
+    let fan_out = config.max_fan_out as u32;
+    let desired_size = fan_out.saturating_pow(config.max_depth).min(256);
+    let inner_config = config.clone();
+    let node = file.prop_recursive(config.max_depth, desired_size, fan_out, move |inner| {
+        directory(&inner_config, inner)
+    });
+    directory(&config, node.boxed()).boxed()
+}
+
+/// A strategy for `MockFS` instances populated with a random tree.
+pub fn mockfs(config: TreeConfig) -> BoxedStrategy<MockFS> {
+    tree(config).prop_map(|t| t.to_mockfs()).boxed()
+}
+
+impl Arbitrary for MockFS {
+    type Parameters = TreeConfig;
+    type Strategy = BoxedStrategy<MockFS>;
+
+    fn arbitrary_with(config: TreeConfig) -> Self::Strategy {
+        mockfs(config)
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+/// A source of the current time, used by `MockFS` to stamp entries.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// A clock that reports the real system time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that always reports the same time.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub SystemTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+/// A clock that only moves when told to.
+///
+/// Clones share the same time, so a test can keep one handle and advance it
+/// while the filesystem holds another.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::{Duration, SystemTime};
+/// use inscenerator_xfs::clock::{Clock, ManualClock};
+///
+/// let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+/// let shared: Arc<dyn Clock> = Arc::new(clock.clone());
+/// clock.advance(Duration::from_secs(5));
+/// assert_eq!(shared.now(), SystemTime::UNIX_EPOCH + Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<RwLock<SystemTime>>,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> ManualClock {
+        ManualClock {
+            now: Arc::new(RwLock::new(start)),
+        }
+    }
+
+    /// Moves the clock forward by `d`.
+    pub fn advance(&self, d: Duration) {
+        let mut now = self.now.write().unwrap();
+        *now += d;
+    }
+
+    /// Sets the clock to an arbitrary time, which may be in the past.
+    pub fn set(&self, t: SystemTime) {
+        *self.now.write().unwrap() = t;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.read().unwrap()
+    }
+}
@@ -0,0 +1,779 @@
+//! A conformance suite for `Xfs` implementations, enabled by the
+//! `conformance` feature.
+//!
+//! Every check takes a fresh `Fixture` and exercises one aspect of the `Xfs`
+//! contract, including the error variant expected for each failure. The
+//! built-in backends are verified with this suite, and custom backends can be
+//! verified against the same contract with the `xfs_conformance_tests!` macro:
+//!
+//! ```
+//! mod mockfs_conformance {
+//!     use inscenerator_xfs::conformance::Fixture;
+//!     use inscenerator_xfs::mockfs::MockFS;
+//!
+//!     inscenerator_xfs::xfs_conformance_tests!(|| Fixture::new(
+//!         Box::new(MockFS::new()),
+//!         "/".into()
+//!     ));
+//! }
+//! ```
+//!
+//! Alternatively `run_all` runs every check in turn and reports all failures
+//! together.
+
+use std::any::Any;
+use std::io::{Read, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::{Overwrite, Xfs, XfsError, XfsWrite};
+
+/// A filesystem under test, together with the directory the checks work in.
+///
+/// The root directory must exist and be empty.
+pub struct Fixture {
+    pub fs: Box<dyn Xfs>,
+    pub root: PathBuf,
+    /// Another way of writing `root` that names the same directory, used by
+    /// the checks that refer to one entry through two different paths.
+    pub alias: PathBuf,
+    _guard: Option<Box<dyn Any>>,
+}
+
+impl Fixture {
+    pub fn new(fs: Box<dyn Xfs>, root: PathBuf) -> Fixture {
+        Fixture {
+            fs,
+            alias: root.join("."),
+            root,
+            _guard: None,
+        }
+    }
+
+    /// Sets another way of writing the root directory, such as a relative
+    /// path or a symbolic link, that the filesystem resolves to the same
+    /// directory. By default the root followed by `.` is used.
+    pub fn with_alias(mut self, alias: PathBuf) -> Fixture {
+        self.alias = alias;
+        self
+    }
+
+    /// Keeps `guard` alive for as long as the fixture, e.g. a temporary
+    /// directory that is deleted on drop.
+    pub fn with_guard<G: Any>(mut self, guard: G) -> Fixture {
+        self._guard = Some(Box::new(guard));
+        self
+    }
+
+    /// Returns the path of `rel` within the fixture root.
+    pub fn path(&self, rel: &str) -> PathBuf {
+        self.root.join(rel)
+    }
+
+    /// Returns the path of `rel` within the fixture root, written through
+    /// the alias of the root.
+    pub fn alias_path(&self, rel: &str) -> PathBuf {
+        self.alias.join(rel)
+    }
+
+    fn write(&mut self, rel: &str, data: &[u8]) {
+        let p = self.path(rel);
+        let mut w = self.fs.writer(&p).unwrap();
+        w.write_all(data).unwrap();
+        w.flush().unwrap();
+    }
+
+    fn read(&self, rel: &str) -> Vec<u8> {
+        let mut data = vec![];
+        self.fs
+            .reader(&self.path(rel))
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        data
+    }
+
+    fn mkdir(&mut self, rel: &str) {
+        let p = self.path(rel);
+        self.fs.create_dir_all(&p).unwrap();
+    }
+}
+
+macro_rules! assert_err {
+    ($e:expr, $pattern:pat) => {
+        match $e {
+            Err($pattern) => {}
+            Err(e) => panic!(
+                "expected {} to fail with {}, got error: {:?}",
+                stringify!($e),
+                stringify!($pattern),
+                e
+            ),
+            Ok(_) => panic!(
+                "expected {} to fail with {}, but it succeeded",
+                stringify!($e),
+                stringify!($pattern)
+            ),
+        }
+    };
+}
+
+pub fn writer_creates_file(fx: &mut Fixture) {
+    fx.write("a.txt", b"hello");
+    assert!(fx.fs.is_file(&fx.path("a.txt")));
+    assert_eq!(fx.read("a.txt"), b"hello");
+}
+
+pub fn writer_truncates_existing(fx: &mut Fixture) {
+    fx.write("a.txt", b"original content");
+    fx.write("a.txt", b"new");
+    assert_eq!(fx.read("a.txt"), b"new");
+}
+
+pub fn writer_missing_parent_is_not_found(fx: &mut Fixture) {
+    let p = fx.path("missing/a.txt");
+    assert_err!(fx.fs.writer(&p), XfsError::NotFound { .. });
+}
+
+pub fn writer_parent_is_file_is_not_a_directory(fx: &mut Fixture) {
+    fx.write("file", b"");
+    let p = fx.path("file/a.txt");
+    assert_err!(fx.fs.writer(&p), XfsError::NotADirectory { .. });
+}
+
+pub fn writer_on_directory_is_not_a_file(fx: &mut Fixture) {
+    fx.mkdir("dir");
+    let p = fx.path("dir");
+    assert_err!(fx.fs.writer(&p), XfsError::NotAFile { .. });
+}
+
+pub fn reader_reads_contents(fx: &mut Fixture) {
+    fx.write("a.bin", &[0, 1, 2, 255]);
+    assert_eq!(fx.read("a.bin"), vec![0, 1, 2, 255]);
+}
+
+pub fn reader_missing_is_not_found(fx: &mut Fixture) {
+    assert_err!(fx.fs.reader(&fx.path("missing")), XfsError::NotFound { .. });
+}
+
+pub fn reader_on_directory_is_not_a_file(fx: &mut Fixture) {
+    fx.mkdir("dir");
+    assert_err!(fx.fs.reader(&fx.path("dir")), XfsError::NotAFile { .. });
+}
+
+pub fn read_all_lines_splits_lines(fx: &mut Fixture) {
+    fx.write("a.txt", b"one\ntwo\r\nthree");
+    let lines = fx.fs.read_all_lines(&fx.path("a.txt")).unwrap();
+    assert_eq!(lines, vec!["one", "two", "three"]);
+}
+
+pub fn read_all_lines_invalid_utf8(fx: &mut Fixture) {
+    fx.write("a.bin", &[b'a', b'\n', 0xff, 0xfe]);
+    assert_err!(
+        fx.fs.read_all_lines(&fx.path("a.bin")),
+        XfsError::InvalidUtf8 { .. }
+    );
+}
+
+pub fn read_all_lines_missing_is_not_found(fx: &mut Fixture) {
+    assert_err!(
+        fx.fs.read_all_lines(&fx.path("missing")),
+        XfsError::NotFound { .. }
+    );
+}
+
+pub fn read_dir_lists_entries(fx: &mut Fixture) {
+    fx.mkdir("dir/sub");
+    fx.write("dir/a.txt", b"a");
+
+    let mut entries = vec![];
+    for de in fx.fs.read_dir(&fx.path("dir")).unwrap() {
+        let de = de.unwrap();
+        entries.push((de.path(), de.metadata().unwrap().is_dir()));
+    }
+    entries.sort();
+    assert_eq!(
+        entries,
+        vec![(fx.path("dir/a.txt"), false), (fx.path("dir/sub"), true)]
+    );
+}
+
+pub fn read_dir_missing_is_not_found(fx: &mut Fixture) {
+    assert_err!(
+        fx.fs.read_dir(&fx.path("missing")),
+        XfsError::NotFound { .. }
+    );
+}
+
+pub fn read_dir_on_file_is_not_a_directory(fx: &mut Fixture) {
+    fx.write("a.txt", b"");
+    assert_err!(
+        fx.fs.read_dir(&fx.path("a.txt")),
+        XfsError::NotADirectory { .. }
+    );
+}
+
+pub fn metadata_reports_kind(fx: &mut Fixture) {
+    fx.write("a.txt", b"");
+    fx.mkdir("dir");
+
+    let md = fx.fs.metadata(&fx.path("a.txt")).unwrap();
+    assert!(md.is_file() && !md.is_dir());
+    let md = fx.fs.metadata(&fx.path("dir")).unwrap();
+    assert!(md.is_dir() && !md.is_file());
+
+    assert!(fx.fs.exists(&fx.path("a.txt")));
+    assert!(fx.fs.is_file(&fx.path("a.txt")));
+    assert!(!fx.fs.is_dir(&fx.path("a.txt")));
+    assert!(fx.fs.is_dir(&fx.path("dir")));
+    assert!(!fx.fs.exists(&fx.path("missing")));
+}
+
+pub fn metadata_reports_size(fx: &mut Fixture) {
+    fx.write("a.txt", b"hello");
+    fx.write("empty.txt", b"");
+    assert_eq!(fx.fs.metadata(&fx.path("a.txt")).unwrap().size(), 5);
+    assert_eq!(fx.fs.metadata(&fx.path("empty.txt")).unwrap().size(), 0);
+}
+
+pub fn metadata_missing_is_not_found(fx: &mut Fixture) {
+    assert_err!(
+        fx.fs.metadata(&fx.path("missing")),
+        XfsError::NotFound { .. }
+    );
+}
+
+pub fn create_dir_creates(fx: &mut Fixture) {
+    let p = fx.path("dir");
+    fx.fs.create_dir(&p).unwrap();
+    assert!(fx.fs.is_dir(&p));
+}
+
+pub fn create_dir_existing_is_already_exists(fx: &mut Fixture) {
+    fx.mkdir("dir");
+    fx.write("a.txt", b"");
+    let dir = fx.path("dir");
+    assert_err!(fx.fs.create_dir(&dir), XfsError::AlreadyExists { .. });
+    let file = fx.path("a.txt");
+    assert_err!(fx.fs.create_dir(&file), XfsError::AlreadyExists { .. });
+}
+
+pub fn create_dir_missing_parent_is_not_found(fx: &mut Fixture) {
+    let p = fx.path("missing/dir");
+    assert_err!(fx.fs.create_dir(&p), XfsError::NotFound { .. });
+}
+
+pub fn create_dir_all_creates_parents(fx: &mut Fixture) {
+    let p = fx.path("a/b/c");
+    fx.fs.create_dir_all(&p).unwrap();
+    assert!(fx.fs.is_dir(&fx.path("a/b")));
+    assert!(fx.fs.is_dir(&p));
+    // Creating again is not an error.
+    fx.fs.create_dir_all(&p).unwrap();
+}
+
+pub fn create_dir_all_through_file_fails(fx: &mut Fixture) {
+    fx.write("a.txt", b"");
+    let p = fx.path("a.txt/b");
+    assert_err!(fx.fs.create_dir_all(&p), XfsError::NotADirectory { .. });
+}
+
+pub fn remove_file_removes(fx: &mut Fixture) {
+    fx.write("a.txt", b"");
+    let p = fx.path("a.txt");
+    fx.fs.remove_file(&p).unwrap();
+    assert!(!fx.fs.exists(&p));
+}
+
+pub fn remove_file_missing_is_not_found(fx: &mut Fixture) {
+    let p = fx.path("missing");
+    assert_err!(fx.fs.remove_file(&p), XfsError::NotFound { .. });
+}
+
+pub fn remove_file_on_directory_is_not_a_file(fx: &mut Fixture) {
+    fx.mkdir("dir");
+    let p = fx.path("dir");
+    assert_err!(fx.fs.remove_file(&p), XfsError::NotAFile { .. });
+    assert!(fx.fs.is_dir(&p));
+}
+
+pub fn remove_dir_all_removes_tree(fx: &mut Fixture) {
+    fx.mkdir("dir/sub");
+    fx.write("dir/a.txt", b"a");
+    fx.write("dir/sub/b.txt", b"b");
+    let p = fx.path("dir");
+    fx.fs.remove_dir_all(&p).unwrap();
+    assert!(!fx.fs.exists(&p));
+}
+
+pub fn remove_dir_all_missing_is_not_found(fx: &mut Fixture) {
+    let p = fx.path("missing");
+    assert_err!(fx.fs.remove_dir_all(&p), XfsError::NotFound { .. });
+}
+
+pub fn remove_dir_all_on_file_is_not_a_directory(fx: &mut Fixture) {
+    fx.write("a.txt", b"");
+    let p = fx.path("a.txt");
+    assert_err!(fx.fs.remove_dir_all(&p), XfsError::NotADirectory { .. });
+    assert!(fx.fs.is_file(&p));
+}
+
+pub fn rename_moves_file(fx: &mut Fixture) {
+    fx.write("a.txt", b"content");
+    fx.mkdir("dir");
+    let (from, to) = (fx.path("a.txt"), fx.path("dir/b.txt"));
+    fx.fs.rename(&from, &to).unwrap();
+    assert!(!fx.fs.exists(&from));
+    assert_eq!(fx.read("dir/b.txt"), b"content");
+}
+
+pub fn rename_moves_directory(fx: &mut Fixture) {
+    fx.mkdir("dir/sub");
+    fx.write("dir/sub/a.txt", b"a");
+    let (from, to) = (fx.path("dir"), fx.path("moved"));
+    fx.fs.rename(&from, &to).unwrap();
+    assert!(!fx.fs.exists(&from));
+    assert_eq!(fx.read("moved/sub/a.txt"), b"a");
+}
+
+pub fn rename_replaces_file(fx: &mut Fixture) {
+    fx.write("a.txt", b"new");
+    fx.write("b.txt", b"old");
+    let (from, to) = (fx.path("a.txt"), fx.path("b.txt"));
+    fx.fs.rename(&from, &to).unwrap();
+    assert!(!fx.fs.exists(&from));
+    assert_eq!(fx.read("b.txt"), b"new");
+}
+
+pub fn rename_replaces_empty_directory(fx: &mut Fixture) {
+    fx.mkdir("a");
+    fx.write("a/x.txt", b"x");
+    fx.mkdir("b");
+    let (from, to) = (fx.path("a"), fx.path("b"));
+    fx.fs.rename(&from, &to).unwrap();
+    assert!(!fx.fs.exists(&from));
+    assert_eq!(fx.read("b/x.txt"), b"x");
+}
+
+pub fn rename_to_same_path_is_noop(fx: &mut Fixture) {
+    fx.write("a.txt", b"a");
+    let p = fx.path("a.txt");
+    fx.fs.rename(&p, &p).unwrap();
+    assert_eq!(fx.read("a.txt"), b"a");
+}
+
+pub fn rename_missing_is_not_found(fx: &mut Fixture) {
+    let (from, to) = (fx.path("missing"), fx.path("b"));
+    assert_err!(fx.fs.rename(&from, &to), XfsError::NotFound { .. });
+}
+
+pub fn rename_missing_target_parent_is_not_found(fx: &mut Fixture) {
+    fx.write("a.txt", b"");
+    let (from, to) = (fx.path("a.txt"), fx.path("missing/b.txt"));
+    assert_err!(fx.fs.rename(&from, &to), XfsError::NotFound { .. });
+    assert!(fx.fs.is_file(&from));
+}
+
+pub fn rename_file_over_directory_is_not_a_file(fx: &mut Fixture) {
+    fx.write("a.txt", b"");
+    fx.mkdir("dir");
+    let (from, to) = (fx.path("a.txt"), fx.path("dir"));
+    assert_err!(fx.fs.rename(&from, &to), XfsError::NotAFile { .. });
+    assert!(fx.fs.is_file(&from));
+    assert!(fx.fs.is_dir(&to));
+}
+
+pub fn rename_directory_over_file_is_not_a_directory(fx: &mut Fixture) {
+    fx.mkdir("dir");
+    fx.write("a.txt", b"");
+    let (from, to) = (fx.path("dir"), fx.path("a.txt"));
+    assert_err!(fx.fs.rename(&from, &to), XfsError::NotADirectory { .. });
+    assert!(fx.fs.is_dir(&from));
+    assert!(fx.fs.is_file(&to));
+}
+
+pub fn rename_directory_over_non_empty_directory_fails(fx: &mut Fixture) {
+    fx.mkdir("a");
+    fx.mkdir("b");
+    fx.write("b/x.txt", b"x");
+    let (from, to) = (fx.path("a"), fx.path("b"));
+    assert_err!(fx.fs.rename(&from, &to), XfsError::DirectoryNotEmpty { .. });
+    assert_eq!(fx.read("b/x.txt"), b"x");
+}
+
+pub fn rename_directory_into_itself_fails(fx: &mut Fixture) {
+    fx.mkdir("a");
+    let (from, to) = (fx.path("a"), fx.path("a/b"));
+    assert!(fx.fs.rename(&from, &to).is_err());
+    assert!(fx.fs.is_dir(&from));
+}
+
+pub fn set_permissions_updates_mode(fx: &mut Fixture) {
+    fx.write("a.txt", b"");
+    let p = fx.path("a.txt");
+    fx.fs.set_permissions(&p, 0o444).unwrap();
+    assert_eq!(fx.fs.metadata(&p).unwrap().mode() & 0o222, 0);
+    fx.fs.set_permissions(&p, 0o644).unwrap();
+    assert_eq!(fx.fs.metadata(&p).unwrap().mode() & 0o200, 0o200);
+}
+
+pub fn set_permissions_missing_is_not_found(fx: &mut Fixture) {
+    let p = fx.path("missing");
+    assert_err!(fx.fs.set_permissions(&p, 0o644), XfsError::NotFound { .. });
+}
+
+pub fn set_times_updates_times(fx: &mut Fixture) {
+    fx.write("a.txt", b"");
+    let p = fx.path("a.txt");
+    let accessed = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000);
+    fx.fs.set_times(&p, Some(accessed), Some(modified)).unwrap();
+    let md = fx.fs.metadata(&p).unwrap();
+    assert_eq!(md.accessed(), Some(accessed));
+    assert_eq!(md.modified(), Some(modified));
+}
+
+pub fn set_times_on_write_only_file_and_directory(fx: &mut Fixture) {
+    fx.write("a.txt", b"");
+    fx.mkdir("d");
+    let (file, dir) = (fx.path("a.txt"), fx.path("d"));
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000);
+    fx.fs.set_permissions(&file, 0o200).unwrap();
+    fx.fs.set_times(&file, None, Some(modified)).unwrap();
+    fx.fs.set_times(&dir, None, Some(modified)).unwrap();
+    assert_eq!(fx.fs.metadata(&file).unwrap().modified(), Some(modified));
+    assert_eq!(fx.fs.metadata(&dir).unwrap().modified(), Some(modified));
+    fx.fs.set_permissions(&file, 0o644).unwrap();
+}
+
+pub fn set_times_missing_is_not_found(fx: &mut Fixture) {
+    let p = fx.path("missing");
+    assert_err!(
+        fx.fs.set_times(&p, None, Some(SystemTime::UNIX_EPOCH)),
+        XfsError::NotFound { .. }
+    );
+}
+
+pub fn sync_file_and_directory(fx: &mut Fixture) {
+    fx.write("a.txt", b"synced");
+    let (file, dir) = (fx.path("a.txt"), fx.root.clone());
+    fx.fs.sync_data(&file).unwrap();
+    fx.fs.sync_all(&file).unwrap();
+    fx.fs.sync_all(&dir).unwrap();
+    assert_eq!(fx.read("a.txt"), b"synced");
+}
+
+pub fn sync_writer_makes_data_visible(fx: &mut Fixture) {
+    let p = fx.path("a.txt");
+    let mut w = fx.fs.writer(&p).unwrap();
+    w.write_all(b"first").unwrap();
+    w.sync_data().unwrap();
+    assert_eq!(fx.read("a.txt"), b"first");
+    w.write_all(b" second").unwrap();
+    w.sync_all().unwrap();
+    assert_eq!(fx.read("a.txt"), b"first second");
+    drop(w);
+    assert_eq!(fx.read("a.txt"), b"first second");
+}
+
+pub fn sync_missing_is_not_found(fx: &mut Fixture) {
+    let p = fx.path("missing");
+    assert_err!(fx.fs.sync_all(&p), XfsError::NotFound { .. });
+    assert_err!(fx.fs.sync_data(&p), XfsError::NotFound { .. });
+}
+
+pub fn copy_file_copies_contents_and_mode(fx: &mut Fixture) {
+    fx.write("a.txt", b"data");
+    let (from, to) = (fx.path("a.txt"), fx.path("b.txt"));
+    fx.fs.set_permissions(&from, 0o444).unwrap();
+    fx.fs.copy_file(&from, &to, Overwrite::Error).unwrap();
+    assert_eq!(fx.read("b.txt"), b"data");
+    assert_eq!(fx.read("a.txt"), b"data");
+    assert_eq!(fx.fs.metadata(&to).unwrap().mode() & 0o222, 0);
+}
+
+pub fn copy_file_overwrite_policies(fx: &mut Fixture) {
+    fx.write("a.txt", b"new");
+    fx.write("b.txt", b"old");
+    fx.mkdir("dir");
+    let (from, to, dir) = (fx.path("a.txt"), fx.path("b.txt"), fx.path("dir"));
+    assert_err!(
+        fx.fs.copy_file(&from, &to, Overwrite::Error),
+        XfsError::AlreadyExists { .. }
+    );
+    fx.fs.copy_file(&from, &to, Overwrite::Skip).unwrap();
+    assert_eq!(fx.read("b.txt"), b"old");
+    fx.fs.copy_file(&from, &to, Overwrite::Merge).unwrap();
+    assert_eq!(fx.read("b.txt"), b"new");
+    assert_err!(
+        fx.fs.copy_file(&from, &dir, Overwrite::Merge),
+        XfsError::NotAFile { .. }
+    );
+    fx.fs.copy_file(&from, &dir, Overwrite::Replace).unwrap();
+    assert_eq!(fx.read("dir"), b"new");
+    fx.fs.copy_file(&from, &from, Overwrite::Replace).unwrap();
+    assert_eq!(fx.read("a.txt"), b"new");
+}
+
+pub fn copy_file_missing_is_not_found(fx: &mut Fixture) {
+    let (from, to) = (fx.path("missing"), fx.path("b.txt"));
+    assert_err!(
+        fx.fs.copy_file(&from, &to, Overwrite::Error),
+        XfsError::NotFound { .. }
+    );
+}
+
+pub fn copy_recursive_copies_tree(fx: &mut Fixture) {
+    fx.mkdir("a/b");
+    fx.write("a/x.txt", b"x");
+    fx.write("a/b/y.txt", b"y");
+    fx.mkdir("a/empty");
+    let (from, to) = (fx.path("a"), fx.path("c"));
+    fx.fs.copy_recursive(&from, &to, Overwrite::Error).unwrap();
+    assert_eq!(fx.read("c/x.txt"), b"x");
+    assert_eq!(fx.read("c/b/y.txt"), b"y");
+    assert!(fx.fs.is_dir(&fx.path("c/empty")));
+    assert_eq!(fx.read("a/b/y.txt"), b"y");
+}
+
+pub fn copy_recursive_overwrite_policies(fx: &mut Fixture) {
+    fx.mkdir("a");
+    fx.mkdir("b");
+    fx.write("a/x.txt", b"new x");
+    fx.write("a/y.txt", b"new y");
+    fx.write("b/x.txt", b"old x");
+    fx.write("b/z.txt", b"old z");
+    let (from, to) = (fx.path("a"), fx.path("b"));
+    assert_err!(
+        fx.fs.copy_recursive(&from, &to, Overwrite::Error),
+        XfsError::AlreadyExists { .. }
+    );
+    fx.fs.copy_recursive(&from, &to, Overwrite::Skip).unwrap();
+    assert_eq!(fx.read("b/x.txt"), b"old x");
+    assert_eq!(fx.read("b/y.txt"), b"new y");
+    fx.fs.copy_recursive(&from, &to, Overwrite::Merge).unwrap();
+    assert_eq!(fx.read("b/x.txt"), b"new x");
+    assert_eq!(fx.read("b/z.txt"), b"old z");
+    fx.fs
+        .copy_recursive(&from, &to, Overwrite::Replace)
+        .unwrap();
+    assert!(!fx.fs.exists(&fx.path("b/z.txt")));
+    assert_eq!(fx.read("b/x.txt"), b"new x");
+}
+
+pub fn copy_recursive_into_itself_fails(fx: &mut Fixture) {
+    fx.mkdir("a");
+    fx.write("a/x.txt", b"x");
+    let (from, inside) = (fx.path("a"), fx.path("a/b"));
+    assert!(fx
+        .fs
+        .copy_recursive(&from, &inside, Overwrite::Error)
+        .is_err());
+    assert!(!fx.fs.exists(&inside));
+    let (from, outside) = (fx.path("a/x.txt"), fx.path("a"));
+    assert!(fx
+        .fs
+        .copy_recursive(&from, &outside, Overwrite::Replace)
+        .is_err());
+    assert_eq!(fx.read("a/x.txt"), b"x");
+}
+
+pub fn move_recursive_moves_tree(fx: &mut Fixture) {
+    fx.mkdir("a/b");
+    fx.write("a/x.txt", b"x");
+    fx.write("a/b/y.txt", b"y");
+    let (from, to) = (fx.path("a"), fx.path("c"));
+    fx.fs.move_recursive(&from, &to, Overwrite::Error).unwrap();
+    assert!(!fx.fs.exists(&from));
+    assert_eq!(fx.read("c/x.txt"), b"x");
+    assert_eq!(fx.read("c/b/y.txt"), b"y");
+}
+
+pub fn move_recursive_overwrite_policies(fx: &mut Fixture) {
+    fx.mkdir("a");
+    fx.mkdir("b");
+    fx.write("a/x.txt", b"new x");
+    fx.write("a/y.txt", b"new y");
+    fx.write("b/x.txt", b"old x");
+    let (from, to) = (fx.path("a"), fx.path("b"));
+    assert_err!(
+        fx.fs.move_recursive(&from, &to, Overwrite::Error),
+        XfsError::AlreadyExists { .. }
+    );
+    fx.fs.move_recursive(&from, &to, Overwrite::Skip).unwrap();
+    assert_eq!(fx.read("b/x.txt"), b"old x");
+    assert_eq!(fx.read("b/y.txt"), b"new y");
+    assert_eq!(fx.read("a/x.txt"), b"new x");
+    assert!(!fx.fs.exists(&fx.path("a/y.txt")));
+    fx.fs.move_recursive(&from, &to, Overwrite::Merge).unwrap();
+    assert_eq!(fx.read("b/x.txt"), b"new x");
+    assert!(!fx.fs.exists(&from));
+}
+
+pub fn copy_and_move_to_same_path_written_differently(fx: &mut Fixture) {
+    fx.write("x.txt", b"x");
+    fx.mkdir("a");
+    fx.write("a/y.txt", b"y");
+    let (file, file_alias) = (fx.path("x.txt"), fx.alias_path("x.txt"));
+    let (dir, dir_alias) = (fx.path("a"), fx.alias_path("a"));
+    for overwrite in [Overwrite::Skip, Overwrite::Replace, Overwrite::Merge] {
+        fx.fs.copy_file(&file, &file_alias, overwrite).unwrap();
+        assert_eq!(fx.read("x.txt"), b"x");
+        fx.fs.copy_recursive(&dir, &dir_alias, overwrite).unwrap();
+        assert_eq!(fx.read("a/y.txt"), b"y");
+        fx.fs.move_recursive(&file, &file_alias, overwrite).unwrap();
+        assert_eq!(fx.read("x.txt"), b"x");
+        fx.fs.move_recursive(&dir, &dir_alias, overwrite).unwrap();
+        assert_eq!(fx.read("a/y.txt"), b"y");
+    }
+}
+
+pub fn copy_and_move_into_itself_written_differently_fails(fx: &mut Fixture) {
+    fx.mkdir("a");
+    fx.write("a/x.txt", b"x");
+    let (from, inside) = (fx.path("a"), fx.alias_path("a/b"));
+    assert!(fx
+        .fs
+        .copy_recursive(&from, &inside, Overwrite::Error)
+        .is_err());
+    assert!(fx
+        .fs
+        .move_recursive(&from, &inside, Overwrite::Error)
+        .is_err());
+    assert!(!fx.fs.exists(&fx.path("a/b")));
+    assert_eq!(fx.read("a/x.txt"), b"x");
+}
+
+pub fn clones_share_state(fx: &mut Fixture) {
+    let mut writable = fx.fs.unsafe_clone_mut();
+    let readable = fx.fs.unsafe_clone();
+    let p = fx.path("a.txt");
+    writable.writer(&p).unwrap().write_all(b"shared").unwrap();
+    assert!(readable.is_file(&p));
+    assert_eq!(fx.read("a.txt"), b"shared");
+}
+
+/// A single conformance check.
+pub type Check = fn(&mut Fixture);
+
+/// Builds `CHECKS` from the names passed by `__xfs_conformance_checks!`.
+macro_rules! checks_table {
+    ($($name:ident,)*) => {
+        &[$((stringify!($name), $name as Check),)*]
+    };
+}
+
+/// Every check in the suite, by name.
+pub const CHECKS: &[(&str, Check)] = crate::__xfs_conformance_checks!({ checks_table });
+
+/// Runs every check against a fresh fixture from `factory`.
+///
+/// # Panics
+///
+/// Panics after running all checks if any of them failed, listing the names
+/// of the failed checks.
+pub fn run_all(factory: &dyn Fn() -> Fixture) {
+    let mut failed = vec![];
+    for (name, check) in CHECKS {
+        let mut fixture = factory();
+        if catch_unwind(AssertUnwindSafe(|| check(&mut fixture))).is_err() {
+            failed.push(*name);
+        }
+    }
+    if !failed.is_empty() {
+        panic!("conformance checks failed: {}", failed.join(", "));
+    }
+}
+
+/// Invokes the macro named in braces with the given arguments followed by
+/// the name of every check, so that `CHECKS` and `xfs_conformance_tests!`
+/// are generated from this one list.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __xfs_conformance_checks {
+    ({ $($macro:tt)* } $($args:tt)*) => {
+        $($macro)*! {
+            $($args)*
+            writer_creates_file,
+            writer_truncates_existing,
+            writer_missing_parent_is_not_found,
+            writer_parent_is_file_is_not_a_directory,
+            writer_on_directory_is_not_a_file,
+            reader_reads_contents,
+            reader_missing_is_not_found,
+            reader_on_directory_is_not_a_file,
+            read_all_lines_splits_lines,
+            read_all_lines_invalid_utf8,
+            read_all_lines_missing_is_not_found,
+            read_dir_lists_entries,
+            read_dir_missing_is_not_found,
+            read_dir_on_file_is_not_a_directory,
+            metadata_reports_kind,
+            metadata_reports_size,
+            metadata_missing_is_not_found,
+            create_dir_creates,
+            create_dir_existing_is_already_exists,
+            create_dir_missing_parent_is_not_found,
+            create_dir_all_creates_parents,
+            create_dir_all_through_file_fails,
+            remove_file_removes,
+            remove_file_missing_is_not_found,
+            remove_file_on_directory_is_not_a_file,
+            remove_dir_all_removes_tree,
+            remove_dir_all_missing_is_not_found,
+            remove_dir_all_on_file_is_not_a_directory,
+            rename_moves_file,
+            rename_moves_directory,
+            rename_replaces_file,
+            rename_replaces_empty_directory,
+            rename_to_same_path_is_noop,
+            rename_missing_is_not_found,
+            rename_missing_target_parent_is_not_found,
+            rename_file_over_directory_is_not_a_file,
+            rename_directory_over_file_is_not_a_directory,
+            rename_directory_over_non_empty_directory_fails,
+            rename_directory_into_itself_fails,
+            set_permissions_updates_mode,
+            set_permissions_missing_is_not_found,
+            set_times_updates_times,
+            set_times_on_write_only_file_and_directory,
+            set_times_missing_is_not_found,
+            sync_file_and_directory,
+            sync_writer_makes_data_visible,
+            sync_missing_is_not_found,
+            copy_file_copies_contents_and_mode,
+            copy_file_overwrite_policies,
+            copy_file_missing_is_not_found,
+            copy_recursive_copies_tree,
+            copy_recursive_overwrite_policies,
+            copy_recursive_into_itself_fails,
+            move_recursive_moves_tree,
+            move_recursive_overwrite_policies,
+            copy_and_move_to_same_path_written_differently,
+            copy_and_move_into_itself_written_differently_fails,
+            clones_share_state,
+        }
+    };
+}
+
+/// Generates one `#[test]` per conformance check, each using a fresh fixture
+/// from the given factory expression.
+///
+/// Invoke it inside its own module, as the generated tests are named after
+/// the checks.
+#[macro_export]
+macro_rules! xfs_conformance_tests {
+    ($factory:expr) => {
+        $crate::__xfs_conformance_checks!({ $crate::xfs_conformance_tests } @tests $factory;);
+    };
+    (@tests $factory:expr; $($name:ident,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let mut fixture: $crate::conformance::Fixture = ($factory)();
+                $crate::conformance::$name(&mut fixture);
+            }
+        )*
+    };
+}
@@ -0,0 +1,308 @@
+//! Copying and moving files and directory trees within a filesystem.
+//!
+//! These are the implementations of `Xfs::copy_file`, `Xfs::copy_recursive`
+//! and `Xfs::move_recursive`, built only from the other `Xfs` operations so
+//! that they work on every backend and through every wrapper.
+
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{
+    AlreadyExistsSnafu, GeneralSnafu, NotADirectorySnafu, NotAFileSnafu, Result, Xfs, XfsError,
+    XfsReadOnly,
+};
+
+/// What to do when the destination of a copy or move already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Overwrite {
+    /// Fail with `AlreadyExists`.
+    Error,
+    /// Keep existing files, merging directories. Source files that would
+    /// replace a file are left where they are by a move.
+    Skip,
+    /// Remove the destination, whether a file or a directory, first.
+    Replace,
+    /// Merge directories, replacing existing files.
+    Merge,
+}
+
+/// Resolves `.` and `..` lexically, so that equivalent paths compare equal.
+pub(crate) fn normalize(p: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for c in p.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !result.pop() {
+                    result.push("..");
+                }
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Returns true if `p`, an entry of the directory whose canonical path is
+/// `canonical_dir`, resolves somewhere other than inside that directory, as a
+/// symbolic link does. Walks do not follow such entries into directories, so
+/// that links pointing back up the tree cannot make them loop.
+pub(crate) fn is_link<F: XfsReadOnly + ?Sized>(fs: &F, canonical_dir: &Path, p: &Path) -> bool {
+    p.file_name()
+        .is_some_and(|name| fs.canonical_path(p) != canonical_dir.join(name))
+}
+
+/// Returns whether `p` is a directory, or `None` if it does not exist.
+fn kind<F: Xfs + ?Sized>(fs: &F, p: &Path) -> Result<Option<bool>> {
+    match fs.metadata(p) {
+        Ok(md) => Ok(Some(md.is_dir())),
+        Err(XfsError::NotFound { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn remove<F: Xfs + ?Sized>(fs: &mut F, p: &Path, is_dir: bool) -> Result<()> {
+    if is_dir {
+        fs.remove_dir_all(p)
+    } else {
+        fs.remove_file(p)
+    }
+}
+
+/// Returns true if `p` is `base` or below it, comparing the paths the way
+/// `fs` resolves them.
+fn is_inside<F: Xfs + ?Sized>(fs: &F, p: &Path, base: &Path) -> bool {
+    fs.canonical_path(p).starts_with(fs.canonical_path(base))
+}
+
+/// Returns true if `a` and `b` name the same entry of `fs`.
+fn is_same<F: Xfs + ?Sized>(fs: &F, a: &Path, b: &Path) -> bool {
+    fs.canonical_path(a) == fs.canonical_path(b)
+}
+
+/// Fails if `to` is `from` or inside it, as a directory cannot be copied or
+/// moved into itself.
+fn check_not_inside<F: Xfs + ?Sized>(fs: &F, from: &Path, to: &Path, verb: &str) -> Result<()> {
+    if is_inside(fs, to, from) {
+        return GeneralSnafu {
+            message: format!(
+                "cannot {} directory {} inside itself to {}",
+                verb,
+                from.display(),
+                to.display()
+            ),
+        }
+        .fail();
+    }
+    Ok(())
+}
+
+/// Removes `to` so that it can be replaced, failing if `from` is inside it.
+fn remove_for_replace<F: Xfs + ?Sized>(
+    fs: &mut F,
+    from: &Path,
+    to: &Path,
+    is_dir: bool,
+) -> Result<()> {
+    if is_inside(fs, from, to) {
+        return GeneralSnafu {
+            message: format!(
+                "cannot replace {} with {} inside it",
+                to.display(),
+                from.display()
+            ),
+        }
+        .fail();
+    }
+    remove(fs, to, is_dir)
+}
+
+/// Checks the source and destination of `copy_file` and applies `overwrite`
+/// to an existing destination. Returns false if the copy should be skipped.
+pub(crate) fn prepare_copy_file<F: Xfs + ?Sized>(
+    fs: &mut F,
+    from: &Path,
+    to: &Path,
+    overwrite: Overwrite,
+) -> Result<bool> {
+    if fs.metadata(from)?.is_dir() {
+        return NotAFileSnafu { path: from }.fail();
+    }
+    let existing = match kind(fs, to)? {
+        Some(is_dir) => is_dir,
+        None => return Ok(true),
+    };
+    match overwrite {
+        Overwrite::Error => AlreadyExistsSnafu { path: to }.fail(),
+        Overwrite::Skip => Ok(false),
+        // Copying a file onto itself would truncate it.
+        _ if is_same(fs, from, to) => Ok(false),
+        Overwrite::Replace => {
+            remove_for_replace(fs, from, to, existing)?;
+            Ok(true)
+        }
+        Overwrite::Merge if existing => NotAFileSnafu { path: to }.fail(),
+        Overwrite::Merge => Ok(true),
+    }
+}
+
+/// Copies the contents and permissions of a file through a reader and a
+/// writer.
+pub(crate) fn copy_file<F: Xfs + ?Sized>(
+    fs: &mut F,
+    from: &Path,
+    to: &Path,
+    overwrite: Overwrite,
+) -> Result<()> {
+    if !prepare_copy_file(fs, from, to, overwrite)? {
+        return Ok(());
+    }
+    let mode = fs.metadata(from)?.mode();
+    let mut r = fs.reader(from)?;
+    {
+        let mut w = fs.writer(to)?;
+        std::io::copy(&mut r, &mut w)
+            .and_then(|_| w.flush())
+            .map_err(|e| XfsError::IoError {
+                path: to.to_path_buf(),
+                source: e,
+            })?;
+    }
+    fs.set_permissions(to, mode)
+}
+
+pub(crate) fn copy_recursive<F: Xfs + ?Sized>(
+    fs: &mut F,
+    from: &Path,
+    to: &Path,
+    overwrite: Overwrite,
+) -> Result<()> {
+    copy_tree(fs, from, to, overwrite, true)
+}
+
+/// Copies `from` to `to`, leaving out the contents of a directory unless
+/// `descend` is set.
+fn copy_tree<F: Xfs + ?Sized>(
+    fs: &mut F,
+    from: &Path,
+    to: &Path,
+    overwrite: Overwrite,
+    descend: bool,
+) -> Result<()> {
+    let md = fs.metadata(from)?;
+    if !md.is_dir() {
+        return fs.copy_file(from, to, overwrite);
+    }
+    let existing = kind(fs, to)?;
+    if existing.is_some() && is_same(fs, from, to) {
+        return match overwrite {
+            Overwrite::Error => AlreadyExistsSnafu { path: to }.fail(),
+            _ => Ok(()),
+        };
+    }
+    check_not_inside(fs, from, to, "copy")?;
+    let created = match (existing, overwrite) {
+        (None, _) => true,
+        (Some(_), Overwrite::Error) => return AlreadyExistsSnafu { path: to }.fail(),
+        (Some(is_dir), Overwrite::Replace) => {
+            remove_for_replace(fs, from, to, is_dir)?;
+            true
+        }
+        (Some(true), _) => false,
+        (Some(false), Overwrite::Skip) => return Ok(()),
+        (Some(false), Overwrite::Merge) => return NotADirectorySnafu { path: to }.fail(),
+    };
+    // List the children before creating `to`, so that the copy cannot find
+    // itself even on a backend that resolves paths differently to
+    // `canonical_path`.
+    let children: Vec<PathBuf> = if descend {
+        fs.read_dir(from)?
+            .map(|de| de.map(|de| de.path()))
+            .collect::<Result<_>>()?
+    } else {
+        vec![]
+    };
+    if created {
+        fs.create_dir(to)?;
+    }
+    // Links to directories are copied as empty directories.
+    let canonical_from = fs.canonical_path(from);
+    for child in children {
+        if let Some(name) = child.file_name() {
+            let descend = !is_link(fs, &canonical_from, &child);
+            copy_tree(fs, &child, &to.join(name), overwrite, descend)?;
+        }
+    }
+    // Set the mode last, so that a read-only source does not stop the copy.
+    if created {
+        fs.set_permissions(to, md.mode())?;
+    }
+    Ok(())
+}
+
+/// Moves a path by renaming it, or by copying it with `overwrite` and then
+/// removing it if it cannot be renamed across devices. Any file at `to` is
+/// replaced.
+fn rename_or_copy<F: Xfs + ?Sized>(
+    fs: &mut F,
+    from: &Path,
+    to: &Path,
+    is_dir: bool,
+    overwrite: Overwrite,
+) -> Result<()> {
+    match fs.rename(from, to) {
+        Err(XfsError::CrossesDevices { .. }) => {
+            copy_recursive(fs, from, to, overwrite)?;
+            remove(fs, from, is_dir)
+        }
+        result => result,
+    }
+}
+
+pub(crate) fn move_recursive<F: Xfs + ?Sized>(
+    fs: &mut F,
+    from: &Path,
+    to: &Path,
+    overwrite: Overwrite,
+) -> Result<()> {
+    let is_dir = fs.metadata(from)?.is_dir();
+    if is_same(fs, from, to) {
+        return Ok(());
+    }
+    if is_dir {
+        check_not_inside(fs, from, to, "move")?;
+    }
+    let existing = match kind(fs, to)? {
+        Some(existing) => existing,
+        None => return rename_or_copy(fs, from, to, is_dir, Overwrite::Error),
+    };
+    match overwrite {
+        Overwrite::Error => AlreadyExistsSnafu { path: to }.fail(),
+        Overwrite::Replace => {
+            remove_for_replace(fs, from, to, existing)?;
+            rename_or_copy(fs, from, to, is_dir, Overwrite::Error)
+        }
+        _ if is_dir && existing => {
+            let children: Vec<PathBuf> = fs
+                .read_dir(from)?
+                .map(|de| de.map(|de| de.path()))
+                .collect::<Result<_>>()?;
+            for child in children {
+                if let Some(name) = child.file_name() {
+                    move_recursive(fs, &child, &to.join(name), overwrite)?;
+                }
+            }
+            // Skipped entries stay behind in the source.
+            if fs.read_dir(from)?.next().is_none() {
+                fs.remove_dir_all(from)?;
+            }
+            Ok(())
+        }
+        Overwrite::Skip => Ok(()),
+        Overwrite::Merge if is_dir => NotADirectorySnafu { path: to }.fail(),
+        Overwrite::Merge if existing => NotAFileSnafu { path: to }.fail(),
+        // Renaming replaces the file, so the destination is kept if the
+        // move fails.
+        Overwrite::Merge => rename_or_copy(fs, from, to, is_dir, Overwrite::Merge),
+    }
+}
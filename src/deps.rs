@@ -0,0 +1,406 @@
+//! Tracking of what a computation reads, for incremental rebuilds.
+//!
+//! A `ReadTrackingFs` records every file read, path inspected and directory
+//! listed through it, including lookups of paths that do not exist, as a
+//! `DependencySet`. Metadata, whether looked up directly or through the
+//! entries of a listing, records the kind of entry and any size, permissions
+//! or modification time read from it. If every dependency in the set still
+//! holds, for example on the next run, the computation would read exactly the
+//! same data and its previous result can be reused.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::Path;
+//! use inscenerator_xfs::deps::ReadTrackingFs;
+//! use inscenerator_xfs::mockfs::MockFS;
+//! use inscenerator_xfs::XfsReadOnly;
+//!
+//! let mut fs = MockFS::new();
+//! fs.add_file(Path::new("/page.md"), "# Hello").unwrap();
+//!
+//! let tracking = ReadTrackingFs::new(fs.unsafe_clone());
+//! tracking.read_all_lines(Path::new("/page.md")).unwrap();
+//! assert!(!tracking.exists(Path::new("/page.draft")));
+//! let deps = tracking.dependencies();
+//! assert!(deps.is_valid(&fs));
+//!
+//! // Creating a file that was looked for invalidates the result.
+//! fs.add_file(Path::new("/page.draft"), "").unwrap();
+//! assert_eq!(deps.stale(&fs), vec![Path::new("/page.draft")]);
+//! ```
+
+use std::collections::BTreeSet;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::hash::{hash_bytes, hash_file, Digest};
+use crate::{Result, XfsDirEntry, XfsEntryResult, XfsError, XfsMetadata, XfsReadDir, XfsReadOnly};
+
+/// What was observed about a path.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", rename_all = "snake_case")
+)]
+pub enum Dependency {
+    /// The path did not exist.
+    Missing,
+    /// The path existed and was, or was not, a directory.
+    Kind { is_dir: bool },
+    /// The path had the given size.
+    Size { size: u64 },
+    /// The path had the given permission bits.
+    Mode { mode: u32 },
+    /// The path had the given modification time.
+    Modified { modified: Option<SystemTime> },
+    /// The path was a file with contents of the given hash.
+    Contents { hash: Digest },
+    /// The path was a directory with entries of the given names, sorted.
+    Listing { names: Vec<String> },
+}
+
+fn is_missing(e: &XfsError) -> bool {
+    matches!(
+        e,
+        XfsError::NotFound { .. } | XfsError::NotADirectory { .. }
+    )
+}
+
+fn listing_names(entries: &[XfsEntryResult]) -> Vec<String> {
+    let mut names: Vec<String> = entries
+        .iter()
+        .filter_map(|e| e.as_ref().ok())
+        .filter_map(|e| {
+            e.path()
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+impl Dependency {
+    /// Observes `p` in `fs` the same way this dependency was observed.
+    fn observe<F: XfsReadOnly + ?Sized>(&self, fs: &F, p: &Path) -> Option<Dependency> {
+        match self {
+            Dependency::Missing | Dependency::Kind { .. } => Some(match fs.metadata(p) {
+                Ok(md) => Dependency::Kind {
+                    is_dir: md.is_dir(),
+                },
+                Err(e) if is_missing(&e) => Dependency::Missing,
+                Err(_) => return None,
+            }),
+            Dependency::Size { .. } => fs
+                .metadata(p)
+                .ok()
+                .map(|md| Dependency::Size { size: md.size() }),
+            Dependency::Mode { .. } => fs
+                .metadata(p)
+                .ok()
+                .map(|md| Dependency::Mode { mode: md.mode() }),
+            Dependency::Modified { .. } => fs.metadata(p).ok().map(|md| Dependency::Modified {
+                modified: md.modified(),
+            }),
+            Dependency::Contents { .. } => hash_file(fs, p)
+                .ok()
+                .map(|hash| Dependency::Contents { hash }),
+            Dependency::Listing { .. } => fs.read_dir(p).ok().map(|entries| {
+                let entries: Vec<_> = entries.collect();
+                Dependency::Listing {
+                    names: listing_names(&entries),
+                }
+            }),
+        }
+    }
+}
+
+/// The dependencies of a computation, as recorded by a `ReadTrackingFs`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DependencySet {
+    deps: BTreeSet<(PathBuf, Dependency)>,
+}
+
+impl DependencySet {
+    pub fn new() -> DependencySet {
+        DependencySet::default()
+    }
+
+    /// Adds a dependency on what was observed about `p`.
+    pub fn insert(&mut self, p: &Path, dep: Dependency) {
+        self.deps.insert((p.to_path_buf(), dep));
+    }
+
+    /// Adds every dependency of `other`.
+    pub fn extend(&mut self, other: &DependencySet) {
+        self.deps.extend(other.deps.iter().cloned());
+    }
+
+    /// Returns the dependencies, sorted by path.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &Dependency)> {
+        self.deps.iter().map(|(p, d)| (p.as_path(), d))
+    }
+
+    pub fn len(&self) -> usize {
+        self.deps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deps.is_empty()
+    }
+
+    /// Returns the paths whose dependencies no longer hold in `fs`, without
+    /// duplicates. Paths that cannot be inspected are treated as changed.
+    pub fn stale<F: XfsReadOnly + ?Sized>(&self, fs: &F) -> Vec<PathBuf> {
+        let mut stale: Vec<PathBuf> = vec![];
+        for (p, dep) in &self.deps {
+            if stale.last() == Some(p) {
+                continue;
+            }
+            if dep.observe(fs, p).as_ref() != Some(dep) {
+                stale.push(p.clone());
+            }
+        }
+        stale
+    }
+
+    /// Returns true if every dependency still holds in `fs`.
+    pub fn is_valid<F: XfsReadOnly + ?Sized>(&self, fs: &F) -> bool {
+        self.deps
+            .iter()
+            .all(|(p, dep)| dep.observe(fs, p).as_ref() == Some(dep))
+    }
+}
+
+/// Metadata that records the fields read from it. The kind of entry is
+/// recorded when the metadata is looked up. Access and creation times are
+/// not recorded, as reading a file changes the first and the second never
+/// changes by itself.
+struct TrackedMetadata {
+    path: PathBuf,
+    deps: Arc<Mutex<DependencySet>>,
+    md: Box<dyn XfsMetadata>,
+}
+
+impl TrackedMetadata {
+    fn record(&self, dep: Dependency) {
+        self.deps.lock().unwrap().insert(&self.path, dep);
+    }
+}
+
+impl XfsMetadata for TrackedMetadata {
+    fn is_dir(&self) -> bool {
+        self.md.is_dir()
+    }
+
+    fn is_file(&self) -> bool {
+        self.md.is_file()
+    }
+
+    fn size(&self) -> u64 {
+        let size = self.md.size();
+        self.record(Dependency::Size { size });
+        size
+    }
+
+    fn mode(&self) -> u32 {
+        let mode = self.md.mode();
+        self.record(Dependency::Mode { mode });
+        mode
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        let modified = self.md.modified();
+        self.record(Dependency::Modified { modified });
+        modified
+    }
+
+    fn accessed(&self) -> Option<SystemTime> {
+        self.md.accessed()
+    }
+
+    fn created(&self) -> Option<SystemTime> {
+        self.md.created()
+    }
+}
+
+/// A directory entry whose metadata is tracked like `metadata` on the
+/// filesystem.
+struct TrackedDirEntry {
+    deps: Arc<Mutex<DependencySet>>,
+    entry: Box<dyn XfsDirEntry>,
+}
+
+impl XfsDirEntry for TrackedDirEntry {
+    fn path(&self) -> PathBuf {
+        self.entry.path()
+    }
+
+    fn metadata(&self) -> Result<Box<dyn XfsMetadata>> {
+        let md = self.entry.metadata()?;
+        Ok(track_metadata(&self.deps, &self.entry.path(), md))
+    }
+}
+
+/// Records the kind of `p` and wraps its metadata to record the fields read.
+fn track_metadata(
+    deps: &Arc<Mutex<DependencySet>>,
+    p: &Path,
+    md: Box<dyn XfsMetadata>,
+) -> Box<dyn XfsMetadata> {
+    deps.lock().unwrap().insert(
+        p,
+        Dependency::Kind {
+            is_dir: md.is_dir(),
+        },
+    );
+    Box::new(TrackedMetadata {
+        path: p.to_path_buf(),
+        deps: deps.clone(),
+        md,
+    })
+}
+
+/// Wraps a filesystem, recording what is read through it.
+///
+/// Clones share the recorded dependencies.
+pub struct ReadTrackingFs<F: ?Sized> {
+    deps: Arc<Mutex<DependencySet>>,
+    inner: Box<F>,
+}
+
+impl<F: ?Sized> ReadTrackingFs<F> {
+    pub fn new(inner: Box<F>) -> ReadTrackingFs<F> {
+        ReadTrackingFs {
+            deps: Arc::new(Mutex::new(DependencySet::new())),
+            inner,
+        }
+    }
+
+    /// Returns the dependencies recorded so far.
+    pub fn dependencies(&self) -> DependencySet {
+        self.deps.lock().unwrap().clone()
+    }
+
+    /// Returns the dependencies recorded so far and starts a new, empty set,
+    /// e.g. between the steps of a build.
+    pub fn take_dependencies(&self) -> DependencySet {
+        std::mem::take(&mut *self.deps.lock().unwrap())
+    }
+
+    /// Returns the wrapped filesystem.
+    pub fn into_inner(self) -> Box<F> {
+        self.inner
+    }
+
+    fn record(&self, p: &Path, dep: Dependency) {
+        self.deps.lock().unwrap().insert(p, dep);
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> ReadTrackingFs<F> {
+    /// Records what `p` is after an operation on it failed, such as reading
+    /// a directory or a missing file.
+    fn record_failure(&self, p: &Path) {
+        match self.inner.metadata(p) {
+            Ok(md) => self.record(
+                p,
+                Dependency::Kind {
+                    is_dir: md.is_dir(),
+                },
+            ),
+            Err(e) if is_missing(&e) => self.record(p, Dependency::Missing),
+            Err(_) => {}
+        }
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> XfsReadOnly for ReadTrackingFs<F> {
+    fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
+        Box::new(ReadTrackingFs {
+            deps: self.deps.clone(),
+            inner: self.inner.unsafe_clone(),
+        })
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        self.inner.canonical_path(p)
+    }
+
+    fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
+        let entries: Vec<_> = match self.inner.read_dir(p) {
+            Ok(entries) => entries.collect(),
+            Err(e) => {
+                self.record_failure(p);
+                return Err(e);
+            }
+        };
+        self.record(
+            p,
+            Dependency::Listing {
+                names: listing_names(&entries),
+            },
+        );
+        let deps = self.deps.clone();
+        Ok(Box::new(entries.into_iter().map(move |entry| {
+            entry.map(|entry| {
+                Box::new(TrackedDirEntry {
+                    deps: deps.clone(),
+                    entry,
+                }) as Box<dyn XfsDirEntry>
+            })
+        })))
+    }
+
+    fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
+        // Read the whole file up front, so that its hash is known even if
+        // the caller stops reading early.
+        let mut r = match self.inner.reader(p) {
+            Ok(r) => r,
+            Err(e) => {
+                self.record_failure(p);
+                return Err(e);
+            }
+        };
+        let mut data = vec![];
+        r.read_to_end(&mut data).map_err(|e| XfsError::IoError {
+            path: p.to_path_buf(),
+            source: e,
+        })?;
+        self.record(
+            p,
+            Dependency::Contents {
+                hash: hash_bytes(&data),
+            },
+        );
+        Ok(Box::new(Cursor::new(data)))
+    }
+
+    fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
+        // The lines do not preserve line endings, so read the file once
+        // through `reader`, which records the hash of the whole file.
+        let mut s = String::new();
+        self.reader(p)?
+            .read_to_string(&mut s)
+            .map_err(|_| XfsError::InvalidUtf8 {
+                path: p.to_path_buf(),
+            })?;
+        Ok(s.lines().map(|line| line.to_string()).collect())
+    }
+
+    fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        let md = match self.inner.metadata(p) {
+            Ok(md) => md,
+            Err(e) => {
+                self.record_failure(p);
+                return Err(e);
+            }
+        };
+        Ok(track_metadata(&self.deps, p, md))
+    }
+}
@@ -0,0 +1,726 @@
+//! A wrapper that answers reads from another filesystem but keeps all changes
+//! in memory, for implementing `--dry-run`.
+//!
+//! Changes are applied to an in-memory overlay, so later reads through the
+//! wrapper see them, while the wrapped filesystem is never modified. Every
+//! change is also recorded, and `plan` describes them for the user.
+//!
+//! The overlay works like a union mount: new and modified files live in a
+//! `MockFS`, removed paths are hidden from the wrapped filesystem, and files
+//! are copied into the overlay when their permissions or times change. Files
+//! renamed from the wrapped filesystem are not copied, but read from where
+//! they were until they change.
+//!
+//! Paths are resolved with the wrapped filesystem's `canonical_path` before
+//! they are looked up in the overlay, so that the overlay treats relative
+//! paths, symbolic links and paths with `.` or `..` the way the wrapped
+//! filesystem would, and a dry run gives the same results as a real one.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::Write;
+//! use std::path::Path;
+//! use inscenerator_xfs::dryrun::DryRunFs;
+//! use inscenerator_xfs::mockfs::MockFS;
+//! use inscenerator_xfs::{Xfs, XfsReadOnly};
+//!
+//! let mut real = MockFS::new();
+//! real.add_file(Path::new("/site/old.html"), "").unwrap();
+//!
+//! let mut fs = DryRunFs::new(Box::new(real));
+//! fs.writer(Path::new("/site/new.html")).unwrap().write_all(b"hi").unwrap();
+//! fs.remove_file(Path::new("/site/old.html")).unwrap();
+//! assert!(fs.is_file(Path::new("/site/new.html")));
+//!
+//! assert_eq!(
+//!     fs.plan(),
+//!     "create file /site/new.html\ndelete file /site/old.html\n"
+//! );
+//! let real = fs.into_inner();
+//! assert!(real.is_file(Path::new("/site/old.html")));
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::copy::normalize;
+use crate::mockfs::MockFS;
+use crate::{
+    AlreadyExistsSnafu, DirectoryNotEmptySnafu, GeneralSnafu, NotADirectorySnafu, NotAFileSnafu,
+    NotFoundSnafu, Result, Xfs, XfsDirEntry, XfsEntryResult, XfsError, XfsMetadata, XfsReadDir,
+    XfsReadOnly, XfsWrite,
+};
+
+/// A change made through a `DryRunFs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    CreateFile { path: PathBuf },
+    ModifyFile { path: PathBuf },
+    CreateDir { path: PathBuf },
+    RemoveFile { path: PathBuf },
+    RemoveDir { path: PathBuf },
+    Rename { from: PathBuf, to: PathBuf },
+    SetPermissions { path: PathBuf, mode: u32 },
+    SetTimes { path: PathBuf },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::CreateFile { path } => write!(f, "create file {}", path.display()),
+            Change::ModifyFile { path } => write!(f, "modify file {}", path.display()),
+            Change::CreateDir { path } => write!(f, "create directory {}", path.display()),
+            Change::RemoveFile { path } => write!(f, "delete file {}", path.display()),
+            Change::RemoveDir { path } => write!(f, "delete directory {}", path.display()),
+            Change::Rename { from, to } => {
+                write!(f, "rename {} to {}", from.display(), to.display())
+            }
+            Change::SetPermissions { path, mode } => {
+                write!(f, "set permissions of {} to {:o}", path.display(), mode)
+            }
+            Change::SetTimes { path } => write!(f, "set times of {}", path.display()),
+        }
+    }
+}
+
+/// Reports the paths in an error about the resolved path `c`, or paths
+/// below it, as the path `p` the caller gave.
+fn as_given<T>(r: Result<T>, c: &Path, p: &Path) -> Result<T> {
+    r.map_err(|e| {
+        e.map_path(|path| match path.strip_prefix(c) {
+            Ok(rel) if rel.as_os_str().is_empty() => p.to_path_buf(),
+            Ok(rel) => p.join(rel),
+            Err(_) => path,
+        })
+    })
+}
+
+/// A directory entry listed under the path the caller gave.
+struct GivenDirEntry {
+    path: PathBuf,
+    inner: Box<dyn XfsDirEntry>,
+}
+
+impl XfsDirEntry for GivenDirEntry {
+    fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn metadata(&self) -> Result<Box<dyn XfsMetadata>> {
+        self.inner.metadata()
+    }
+}
+
+struct Overlay {
+    /// New and modified files and directories.
+    upper: MockFS,
+    /// Paths removed from the wrapped filesystem.
+    whiteouts: BTreeSet<PathBuf>,
+    /// Directories of the upper layer that hide the wrapped filesystem's
+    /// entries below them, because they replace a removed path.
+    opaque: BTreeSet<PathBuf>,
+    /// Directories of the upper layer created only to hold changed entries,
+    /// whose metadata still comes from the wrapped filesystem.
+    scaffold: BTreeSet<PathBuf>,
+    /// Files of the upper layer renamed from the wrapped filesystem, with
+    /// the paths in the wrapped filesystem their contents and metadata are
+    /// read from. The upper layer holds an empty placeholder.
+    redirects: BTreeMap<PathBuf, PathBuf>,
+    changes: Vec<Change>,
+}
+
+impl Overlay {
+    /// Returns true if `p` has been created or changed in the overlay.
+    fn has(&self, p: &Path) -> bool {
+        self.upper.exists(p) && !self.scaffold.contains(&normalize(p))
+    }
+
+    /// Removes all record of the paths strictly below `k`.
+    fn forget_below(&mut self, k: &Path) {
+        let below = |p: &PathBuf| p != k && p.starts_with(k);
+        self.whiteouts.retain(|p| !below(p));
+        self.opaque.retain(|p| !below(p));
+        self.scaffold.retain(|p| !below(p));
+        self.redirects.retain(|p, _| !below(p));
+    }
+
+    /// Returns the path of the wrapped filesystem that the upper file `p`
+    /// is read from, if it was renamed from there.
+    fn redirect(&self, p: &Path) -> Option<&PathBuf> {
+        self.redirects.get(&normalize(p))
+    }
+
+    fn record(&mut self, change: Change) {
+        // Writing a file again straight after writing it is one change.
+        if let Change::ModifyFile { path } = &change {
+            if let Some(Change::CreateFile { path: last } | Change::ModifyFile { path: last }) =
+                self.changes.last()
+            {
+                if last == path {
+                    return;
+                }
+            }
+        }
+        self.changes.push(change);
+    }
+}
+
+/// Wraps a filesystem, keeping every change in memory instead of applying it.
+///
+/// The wrapped filesystem only needs to be readable. Clones share the
+/// overlay and the recorded changes.
+pub struct DryRunFs<F: ?Sized> {
+    overlay: Arc<Mutex<Overlay>>,
+    inner: Box<F>,
+}
+
+impl<F: ?Sized> DryRunFs<F> {
+    pub fn new(inner: Box<F>) -> DryRunFs<F> {
+        DryRunFs {
+            overlay: Arc::new(Mutex::new(Overlay {
+                upper: MockFS::new(),
+                whiteouts: BTreeSet::new(),
+                opaque: BTreeSet::new(),
+                scaffold: BTreeSet::new(),
+                redirects: BTreeMap::new(),
+                changes: vec![],
+            })),
+            inner,
+        }
+    }
+
+    /// Returns the changes made so far, in order.
+    pub fn changes(&self) -> Vec<Change> {
+        self.overlay.lock().unwrap().changes.clone()
+    }
+
+    /// Describes the changes made so far, one per line.
+    pub fn plan(&self) -> String {
+        self.changes()
+            .iter()
+            .map(|change| format!("{}\n", change))
+            .collect()
+    }
+
+    /// Returns the wrapped filesystem, which is unchanged.
+    pub fn into_inner(self) -> Box<F> {
+        self.inner
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> DryRunFs<F> {
+    /// Returns true if `p` in the wrapped filesystem is not hidden by the
+    /// overlay.
+    fn lower_visible(&self, o: &Overlay, p: &Path) -> bool {
+        let k = normalize(p);
+        if o.whiteouts.contains(&k) {
+            return false;
+        }
+        !k.ancestors()
+            .skip(1)
+            .any(|a| o.whiteouts.contains(a) || o.opaque.contains(a) || o.upper.is_file(a))
+    }
+
+    /// Returns true if `p` should be read from the overlay rather than the
+    /// wrapped filesystem.
+    fn reads_upper(&self, o: &Overlay, p: &Path) -> bool {
+        o.has(p) || !self.lower_visible(o, p)
+    }
+
+    fn metadata_in(&self, o: &Overlay, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        if !self.reads_upper(o, p) {
+            self.inner.metadata(p)
+        } else if let Some(source) = o.redirect(p) {
+            self.inner.metadata(source)
+        } else {
+            o.upper.metadata(p)
+        }
+    }
+
+    fn reader_in(&self, o: &Overlay, p: &Path) -> Result<Box<dyn Read>> {
+        if !self.reads_upper(o, p) {
+            self.inner.reader(p)
+        } else if let Some(source) = o.redirect(p) {
+            self.inner.reader(source)
+        } else {
+            o.upper.reader(p)
+        }
+    }
+
+    fn read_all_lines_in(&self, o: &Overlay, p: &Path) -> Result<Vec<String>> {
+        if !self.reads_upper(o, p) {
+            self.inner.read_all_lines(p)
+        } else if let Some(source) = o.redirect(p) {
+            self.inner.read_all_lines(source)
+        } else {
+            o.upper.read_all_lines(p)
+        }
+    }
+
+    /// Returns `Some(true)` for directories, `Some(false)` for files and
+    /// `None` for missing paths.
+    fn kind(&self, o: &Overlay, p: &Path) -> Option<bool> {
+        self.metadata_in(o, p).ok().map(|md| md.is_dir())
+    }
+
+    fn read_dir_in(&self, o: &Overlay, p: &Path) -> Result<Vec<XfsEntryResult>> {
+        let in_upper = o.upper.is_dir(p);
+        if !in_upper && self.reads_upper(o, p) {
+            return Ok(o.upper.read_dir(p)?.collect());
+        }
+        let mut names = HashSet::new();
+        let mut entries = vec![];
+        if in_upper {
+            for entry in o.upper.read_dir(p)? {
+                let entry = entry?;
+                let path = entry.path();
+                // Scaffolding is listed from the wrapped filesystem instead.
+                if o.scaffold.contains(&normalize(&path)) {
+                    continue;
+                }
+                names.insert(path.file_name().map(|n| n.to_os_string()));
+                entries.push(Ok(entry));
+            }
+            if o.opaque.contains(&normalize(p)) || !self.lower_visible(o, p) {
+                return Ok(entries);
+            }
+        }
+        let lower = match self.inner.read_dir(p) {
+            Ok(lower) => lower,
+            Err(_) if in_upper => return Ok(entries),
+            Err(e) => return Err(e),
+        };
+        for entry in lower {
+            if let Ok(entry) = &entry {
+                let path = entry.path();
+                if names.contains(&path.file_name().map(|n| n.to_os_string()))
+                    || o.whiteouts.contains(&normalize(&path))
+                {
+                    continue;
+                }
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    fn read_bytes(&self, o: &Overlay, p: &Path) -> Result<Vec<u8>> {
+        let mut r = self.reader_in(o, p)?;
+        let mut data = vec![];
+        r.read_to_end(&mut data).map_err(|e| XfsError::IoError {
+            path: p.to_path_buf(),
+            source: e,
+        })?;
+        Ok(data)
+    }
+
+    fn write_bytes(o: &mut Overlay, p: &Path, data: &[u8]) -> Result<()> {
+        let mut w = o.upper.writer(p)?;
+        w.write_all(data)
+            .and_then(|_| w.flush())
+            .map_err(|e| XfsError::IoError {
+                path: p.to_path_buf(),
+                source: e,
+            })
+    }
+
+    fn copy_attributes(o: &mut Overlay, p: &Path, md: &dyn XfsMetadata) -> Result<()> {
+        o.upper.set_permissions(p, md.mode())?;
+        o.upper.set_times(p, md.accessed(), md.modified())
+    }
+
+    /// Fails unless the parent of `p` is an existing directory.
+    fn check_parent(&self, o: &Overlay, p: &Path) -> Result<()> {
+        match p.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => match self.kind(o, parent) {
+                Some(true) => Ok(()),
+                Some(false) => NotADirectorySnafu { path: p }.fail(),
+                None => NotFoundSnafu { path: p }.fail(),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Makes the directory `d`, which must exist, available in the overlay
+    /// so that entries can be added to it.
+    fn ensure_upper_dir(&self, o: &mut Overlay, d: &Path) -> Result<()> {
+        if d.as_os_str().is_empty() || o.upper.is_dir(d) {
+            return Ok(());
+        }
+        if let Some(parent) = d.parent() {
+            self.ensure_upper_dir(o, parent)?;
+        }
+        o.upper.create_dir(d)?;
+        o.scaffold.insert(normalize(d));
+        Ok(())
+    }
+
+    /// Copies `p` from the wrapped filesystem into the overlay, so that its
+    /// attributes can be changed.
+    fn copy_up(&self, o: &mut Overlay, p: &Path) -> Result<()> {
+        if let Some(source) = o.redirect(p).cloned() {
+            // A renamed file only needs its contents once it changes.
+            let md = self.inner.metadata(&source)?;
+            let data = self.read_bytes(o, p)?;
+            o.redirects.remove(&normalize(p));
+            Self::write_bytes(o, p, &data)?;
+            return Self::copy_attributes(o, p, md.as_ref());
+        }
+        if o.has(p) {
+            return Ok(());
+        }
+        if let Some(parent) = p.parent() {
+            self.ensure_upper_dir(o, parent)?;
+        }
+        let md = self.inner.metadata(p)?;
+        // Scaffolding already holds the directory.
+        let was_scaffold = o.scaffold.remove(&normalize(p));
+        if md.is_dir() && !was_scaffold {
+            o.upper.create_dir(p)?;
+        } else if !md.is_dir() {
+            let data = self.read_bytes(o, p)?;
+            Self::write_bytes(o, p, &data)?;
+        }
+        Self::copy_attributes(o, p, md.as_ref())
+    }
+
+    /// Copies the tree at `from` into the overlay at `to`, which must not
+    /// exist. Files of the wrapped filesystem are not read, but redirected
+    /// to.
+    fn copy_tree(&self, o: &mut Overlay, from: &Path, to: &Path) -> Result<()> {
+        let md = self.metadata_in(o, from)?;
+        if md.is_dir() {
+            o.upper.create_dir(to)?;
+            o.opaque.insert(normalize(to));
+            for entry in self.read_dir_in(o, from)? {
+                if let Some(name) = entry?.path().file_name() {
+                    self.copy_tree(o, &from.join(name), &to.join(name))?;
+                }
+            }
+        } else {
+            let source = if !self.reads_upper(o, from) {
+                Some(from.to_path_buf())
+            } else {
+                o.redirect(from).cloned()
+            };
+            if let Some(source) = source {
+                Self::write_bytes(o, to, &[])?;
+                o.redirects.insert(normalize(to), source);
+                return Ok(());
+            }
+            let data = self.read_bytes(o, from)?;
+            Self::write_bytes(o, to, &data)?;
+        }
+        Self::copy_attributes(o, to, md.as_ref())
+    }
+
+    /// Creates the directory `p`, recording it as `given`.
+    fn create_dir_in(&self, o: &mut Overlay, p: &Path, given: &Path) -> Result<()> {
+        if self.kind(o, p).is_some() {
+            return AlreadyExistsSnafu { path: p }.fail();
+        }
+        self.check_parent(o, p)?;
+        if let Some(parent) = p.parent() {
+            self.ensure_upper_dir(o, parent)?;
+        }
+        o.upper.create_dir(p)?;
+        // A directory created where one was removed must not show the
+        // removed directory's entries.
+        o.opaque.insert(normalize(p));
+        o.record(Change::CreateDir {
+            path: given.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    fn remove_in(&self, o: &mut Overlay, p: &Path) -> Result<()> {
+        let k = normalize(p);
+        if o.upper.is_dir(p) {
+            o.upper.remove_dir_all(p)?;
+        } else if o.upper.is_file(p) {
+            o.upper.remove_file(p)?;
+        }
+        if self.lower_visible(o, p) && self.inner.exists(p) {
+            o.whiteouts.insert(k.clone());
+        }
+        o.opaque.remove(&k);
+        o.scaffold.remove(&k);
+        o.redirects.remove(&k);
+        o.forget_below(&k);
+        Ok(())
+    }
+
+    fn exists_in(&self, o: &Overlay, p: &Path) -> Result<()> {
+        match self.kind(o, p) {
+            Some(_) => Ok(()),
+            None => NotFoundSnafu { path: p }.fail(),
+        }
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> XfsReadOnly for DryRunFs<F> {
+    fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
+        Box::new(DryRunFs {
+            overlay: self.overlay.clone(),
+            inner: self.inner.unsafe_clone(),
+        })
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        self.inner.canonical_path(p)
+    }
+
+    fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
+        let o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        let entries = as_given(self.read_dir_in(&o, &c), &c, p)?;
+        // Entries are listed under the path as given, as other backends do.
+        let given = p.to_path_buf();
+        let entries = entries.into_iter().map(move |entry| {
+            let entry = entry?;
+            let path = match entry.path().file_name() {
+                Some(name) => given.join(name),
+                None => entry.path(),
+            };
+            Ok(Box::new(GivenDirEntry { path, inner: entry }) as Box<dyn XfsDirEntry>)
+        });
+        Ok(Box::new(entries))
+    }
+
+    fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
+        let o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        as_given(self.reader_in(&o, &c), &c, p)
+    }
+
+    fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
+        let o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        as_given(self.read_all_lines_in(&o, &c), &c, p)
+    }
+
+    fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        let o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        as_given(self.metadata_in(&o, &c), &c, p)
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> DryRunFs<F> {
+    fn writer_in(&self, o: &mut Overlay, p: &Path) -> Result<(Box<dyn XfsWrite>, bool)> {
+        self.check_parent(o, p)?;
+        let existed = match self.kind(o, p) {
+            Some(true) => return NotAFileSnafu { path: p }.fail(),
+            Some(false) => true,
+            None => false,
+        };
+        if let Some(parent) = p.parent() {
+            self.ensure_upper_dir(o, parent)?;
+        }
+        let w = o.upper.writer(p)?;
+        o.redirects.remove(&normalize(p));
+        Ok((w, existed))
+    }
+
+    /// Creates the directory `p` and its missing parents, recording them as
+    /// the corresponding ancestors of `given`.
+    fn create_dir_all_in(&self, o: &mut Overlay, p: &Path, given: &Path) -> Result<()> {
+        let mut ancestors: Vec<(usize, &Path)> = p
+            .ancestors()
+            .filter(|a| !a.as_os_str().is_empty())
+            .enumerate()
+            .collect();
+        ancestors.reverse();
+        for (depth, a) in ancestors {
+            match self.kind(o, a) {
+                Some(true) => {}
+                Some(false) if a == p => return AlreadyExistsSnafu { path: p }.fail(),
+                Some(false) => return NotADirectorySnafu { path: p }.fail(),
+                None => self.create_dir_in(o, a, given.ancestors().nth(depth).unwrap_or(a))?,
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_file_in(&self, o: &mut Overlay, p: &Path) -> Result<()> {
+        match self.kind(o, p) {
+            Some(false) => {}
+            Some(true) => return NotAFileSnafu { path: p }.fail(),
+            None => return NotFoundSnafu { path: p }.fail(),
+        }
+        self.remove_in(o, p)
+    }
+
+    fn remove_dir_all_in(&self, o: &mut Overlay, p: &Path) -> Result<()> {
+        match self.kind(o, p) {
+            Some(true) => {}
+            Some(false) => return NotADirectorySnafu { path: p }.fail(),
+            None => return NotFoundSnafu { path: p }.fail(),
+        }
+        self.remove_in(o, p)
+    }
+
+    fn rename_in(&self, o: &mut Overlay, from: &Path, to: &Path) -> Result<()> {
+        let from_is_dir = match self.kind(o, from) {
+            Some(is_dir) => is_dir,
+            None => return NotFoundSnafu { path: from }.fail(),
+        };
+        if from == to {
+            return Ok(());
+        }
+        self.check_parent(o, to)?;
+        if from_is_dir && to.starts_with(from) {
+            return GeneralSnafu {
+                message: format!(
+                    "cannot move directory {} inside itself to {}",
+                    from.display(),
+                    to.display()
+                ),
+            }
+            .fail();
+        }
+        match (from_is_dir, self.kind(o, to)) {
+            (false, Some(true)) => return NotAFileSnafu { path: to }.fail(),
+            (true, Some(false)) => return NotADirectorySnafu { path: to }.fail(),
+            (true, Some(true)) if !self.read_dir_in(o, to)?.is_empty() => {
+                return DirectoryNotEmptySnafu { path: to }.fail()
+            }
+            (_, Some(_)) => self.remove_in(o, to)?,
+            (_, None) => {}
+        }
+        if let Some(parent) = to.parent() {
+            self.ensure_upper_dir(o, parent)?;
+        }
+        self.copy_tree(o, from, to)?;
+        self.remove_in(o, from)
+    }
+
+    fn set_permissions_in(&self, o: &mut Overlay, p: &Path, mode: u32) -> Result<()> {
+        self.exists_in(o, p)?;
+        self.copy_up(o, p)?;
+        o.upper.set_permissions(p, mode)
+    }
+
+    fn set_times_in(
+        &self,
+        o: &mut Overlay,
+        p: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<()> {
+        self.exists_in(o, p)?;
+        self.copy_up(o, p)?;
+        o.upper.set_times(p, accessed, modified)
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> Xfs for DryRunFs<F> {
+    fn unsafe_clone_mut(&mut self) -> Box<dyn Xfs> {
+        Box::new(DryRunFs {
+            overlay: self.overlay.clone(),
+            inner: self.inner.unsafe_clone(),
+        })
+    }
+
+    fn writer(&mut self, p: &Path) -> Result<Box<dyn XfsWrite>> {
+        let mut o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        let (w, existed) = as_given(self.writer_in(&mut o, &c), &c, p)?;
+        let path = p.to_path_buf();
+        o.record(if existed {
+            Change::ModifyFile { path }
+        } else {
+            Change::CreateFile { path }
+        });
+        Ok(w)
+    }
+
+    fn create_dir(&mut self, p: &Path) -> Result<()> {
+        let mut o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        as_given(self.create_dir_in(&mut o, &c, p), &c, p)
+    }
+
+    fn create_dir_all(&mut self, p: &Path) -> Result<()> {
+        let mut o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        as_given(self.create_dir_all_in(&mut o, &c, p), &c, p)
+    }
+
+    fn remove_file(&mut self, p: &Path) -> Result<()> {
+        let mut o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        as_given(self.remove_file_in(&mut o, &c), &c, p)?;
+        o.record(Change::RemoveFile {
+            path: p.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    fn remove_dir_all(&mut self, p: &Path) -> Result<()> {
+        let mut o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        as_given(self.remove_dir_all_in(&mut o, &c), &c, p)?;
+        o.record(Change::RemoveDir {
+            path: p.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let mut o = self.overlay.lock().unwrap();
+        let (from_c, to_c) = (self.canonical_path(from), self.canonical_path(to));
+        let r = self.rename_in(&mut o, &from_c, &to_c);
+        as_given(as_given(r, &from_c, from), &to_c, to)?;
+        if from_c != to_c {
+            o.record(Change::Rename {
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+            });
+        }
+        Ok(())
+    }
+
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()> {
+        let mut o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        as_given(self.set_permissions_in(&mut o, &c, mode), &c, p)?;
+        o.record(Change::SetPermissions {
+            path: p.to_path_buf(),
+            mode,
+        });
+        Ok(())
+    }
+
+    fn set_times(
+        &mut self,
+        p: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<()> {
+        let mut o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        as_given(self.set_times_in(&mut o, &c, accessed, modified), &c, p)?;
+        o.record(Change::SetTimes {
+            path: p.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    fn sync_all(&mut self, p: &Path) -> Result<()> {
+        let o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        as_given(self.exists_in(&o, &c), &c, p)
+    }
+
+    fn sync_data(&mut self, p: &Path) -> Result<()> {
+        let o = self.overlay.lock().unwrap();
+        let c = self.canonical_path(p);
+        as_given(self.exists_in(&o, &c), &c, p)
+    }
+}
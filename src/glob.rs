@@ -0,0 +1,211 @@
+//! Glob patterns for matching paths.
+//!
+//! Patterns are split into segments at `/` and matched against the
+//! components of a path, so a pattern starting with `/` only matches absolute
+//! paths. Within a segment:
+//!
+//! - `*` matches any run of characters,
+//! - `?` matches any single character,
+//! - `[abc]`, `[a-z]` and `[!abc]` match one character from, or not from, a set.
+//!
+//! A segment that is exactly `**` matches any number of whole components,
+//! including none, so `src/**` matches `src` itself and everything below it.
+//!
+//! Paths are resolved lexically before matching. A `..` left over, as at the
+//! start of `../x.md`, is only matched by a literal `..` segment, never by
+//! `*`, `?`, a set or `**`.
+
+use std::path::{Component, Path};
+
+use crate::{GeneralSnafu, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyRun,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    AnyComponents,
+    Tokens(Vec<Token>),
+}
+
+/// A compiled glob pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    pattern: String,
+    segments: Vec<Segment>,
+}
+
+fn parse_segment(pattern: &str, segment: &str) -> Result<Segment> {
+    if segment == "**" {
+        return Ok(Segment::AnyComponents);
+    }
+    let mut tokens = vec![];
+    let mut chars = segment.chars();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '*' => Token::AnyRun,
+            '?' => Token::AnyChar,
+            '[' => {
+                let mut negated = false;
+                let mut ranges = vec![];
+                let mut first = true;
+                loop {
+                    let c = match chars.next() {
+                        Some(c) => c,
+                        None => {
+                            return GeneralSnafu {
+                                message: format!("unclosed '[' in glob pattern {:?}", pattern),
+                            }
+                            .fail()
+                        }
+                    };
+                    match c {
+                        '!' if first && !negated => negated = true,
+                        ']' if !ranges.is_empty() => break,
+                        _ => {
+                            let mut lookahead = chars.clone();
+                            match (lookahead.next(), lookahead.next()) {
+                                (Some('-'), Some(end)) if end != ']' => {
+                                    ranges.push((c, end));
+                                    chars = lookahead;
+                                }
+                                _ => ranges.push((c, c)),
+                            }
+                        }
+                    }
+                    first = false;
+                }
+                Token::Class { negated, ranges }
+            }
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+    Ok(Segment::Tokens(tokens))
+}
+
+fn match_tokens(tokens: &[Token], s: &[char]) -> bool {
+    match tokens.split_first() {
+        None => s.is_empty(),
+        Some((Token::AnyRun, rest)) => (0..=s.len()).any(|i| match_tokens(rest, &s[i..])),
+        Some((token, rest)) => {
+            let (c, s) = match s.split_first() {
+                Some(x) => x,
+                None => return false,
+            };
+            let matched = match token {
+                Token::Char(t) => t == c,
+                Token::AnyChar => true,
+                Token::Class { negated, ranges } => {
+                    ranges.iter().any(|(lo, hi)| lo <= c && c <= hi) != *negated
+                }
+                Token::AnyRun => unreachable!(),
+            };
+            matched && match_tokens(rest, s)
+        }
+    }
+}
+
+fn match_segments(segments: &[Segment], components: &[String]) -> bool {
+    match segments.split_first() {
+        None => components.is_empty(),
+        Some((Segment::AnyComponents, rest)) => {
+            // `**` never skips over a `..`.
+            let skippable = components.iter().take_while(|c| *c != "..").count();
+            (0..=skippable).any(|i| match_segments(rest, &components[i..]))
+        }
+        Some((Segment::Tokens(tokens), rest)) => match components.split_first() {
+            // `..` is only matched by a literal `..` segment, never by a
+            // wildcard.
+            Some((c, components)) if c == ".." => {
+                *tokens == [Token::Char('.'), Token::Char('.')] && match_segments(rest, components)
+            }
+            Some((c, components)) => {
+                let chars: Vec<char> = c.chars().collect();
+                match_tokens(tokens, &chars) && match_segments(rest, components)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Splits a path into the components matched by globs, resolving `.` and
+/// `..` lexically. An absolute path starts with an empty component, matching
+/// the empty segment before the leading `/` of an absolute pattern.
+fn path_components(p: &Path) -> Vec<String> {
+    let mut components: Vec<String> = vec![];
+    for c in p.components() {
+        match c {
+            Component::Prefix(prefix) => {
+                components.push(prefix.as_os_str().to_string_lossy().into_owned())
+            }
+            Component::RootDir => components.push(String::new()),
+            Component::CurDir => {}
+            Component::ParentDir => match components.last() {
+                Some(last) if !last.is_empty() && last != ".." => {
+                    components.pop();
+                }
+                // `..` at the root is the root.
+                Some(last) if last.is_empty() => {}
+                _ => components.push("..".to_string()),
+            },
+            Component::Normal(name) => components.push(name.to_string_lossy().into_owned()),
+        }
+    }
+    components
+}
+
+/// Returns true if `p` still starts with `..` after resolving `.` and `..`
+/// lexically, so that it refers to somewhere above the path it is relative to.
+pub(crate) fn escapes(p: &Path) -> bool {
+    path_components(p).first().map(String::as_str) == Some("..")
+}
+
+impl Glob {
+    /// Compiles a pattern.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pattern contains an unclosed `[`.
+    pub fn new(pattern: &str) -> Result<Glob> {
+        let segments = pattern
+            .split('/')
+            .map(|segment| parse_segment(pattern, segment))
+            .collect::<Result<_>>()?;
+        Ok(Glob {
+            pattern: pattern.to_string(),
+            segments,
+        })
+    }
+
+    /// Returns the pattern this glob was compiled from.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns true if `p` matches the pattern, after resolving `.` and `..`
+    /// components.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use inscenerator_xfs::glob::Glob;
+    ///
+    /// let glob = Glob::new("src/**/*.rs").unwrap();
+    /// assert!(glob.matches(Path::new("src/lib.rs")));
+    /// assert!(glob.matches(Path::new("src/a/b/mod.rs")));
+    /// assert!(!glob.matches(Path::new("src/../secret.rs")));
+    /// ```
+    pub fn matches(&self, p: &Path) -> bool {
+        match_segments(&self.segments, &path_components(p))
+    }
+}
@@ -0,0 +1,133 @@
+//! SHA-256 content hashes of files.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::Path;
+//! use inscenerator_xfs::hash::{hash_bytes, hash_file};
+//! use inscenerator_xfs::mockfs::MockFS;
+//!
+//! let mut fs = MockFS::new();
+//! fs.add_file(Path::new("a.txt"), "hello").unwrap();
+//! let digest = hash_file(&fs, Path::new("a.txt")).unwrap();
+//! assert_eq!(digest, hash_bytes(b"hello"));
+//! assert_eq!(
+//!     digest.to_string(),
+//!     "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+//! );
+//! ```
+
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use sha2::{Digest as _, Sha256};
+
+use crate::{GeneralSnafu, Result, XfsError, XfsReadOnly};
+
+/// A SHA-256 hash, displayed and parsed as lowercase hexadecimal.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Digest([u8; 32]);
+
+impl Digest {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for Digest {
+    fn from(bytes: [u8; 32]) -> Digest {
+        Digest(bytes)
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in &self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest({})", self)
+    }
+}
+
+impl FromStr for Digest {
+    type Err = XfsError;
+
+    fn from_str(s: &str) -> Result<Digest> {
+        let invalid = || {
+            GeneralSnafu {
+                message: format!("invalid SHA-256 digest {:?}", s),
+            }
+            .fail()
+        };
+        // `from_str_radix` also accepts a leading `+`.
+        if s.len() != 64 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return invalid();
+        }
+        let mut bytes = [0u8; 32];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = match u8::from_str_radix(&s[2 * i..2 * i + 2], 16) {
+                Ok(b) => b,
+                Err(_) => return invalid(),
+            };
+        }
+        Ok(Digest(bytes))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Digest {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Digest {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> std::result::Result<Digest, D::Error> {
+        let s = String::deserialize(d)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Hashes a byte slice.
+pub fn hash_bytes(data: &[u8]) -> Digest {
+    Digest(Sha256::digest(data).into())
+}
+
+/// Hashes everything read from `r`. `p` is only used in errors.
+///
+/// # Errors
+///
+/// Returns `IoError` if reading fails.
+pub fn hash_reader(r: &mut dyn Read, p: &Path) -> Result<Digest> {
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = r.read(&mut buf).map_err(|e| XfsError::IoError {
+            path: p.to_path_buf(),
+            source: e,
+        })?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(Digest(hasher.finalize().into()))
+}
+
+/// Hashes the contents of the file at `p`.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or read.
+pub fn hash_file<F: XfsReadOnly + ?Sized>(fs: &F, p: &Path) -> Result<Digest> {
+    hash_reader(&mut fs.reader(p)?, p)
+}
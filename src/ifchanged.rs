@@ -0,0 +1,330 @@
+//! Writing files only when their contents change.
+//!
+//! Rewriting a file with identical contents still bumps its modification
+//! time, which makes build tools and file watchers think it changed.
+//! `write_if_changed` compares the new contents with the existing file and
+//! leaves the file untouched if they are equal. `WriteIfChangedFs` does the
+//! same for every writer opened through it, and records which files changed.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::Write;
+//! use std::path::Path;
+//! use inscenerator_xfs::ifchanged::{write_if_changed, WriteIfChangedFs};
+//! use inscenerator_xfs::mockfs::MockFS;
+//! use inscenerator_xfs::Xfs;
+//!
+//! let mut fs = MockFS::new();
+//! assert!(write_if_changed(&mut fs, Path::new("a.txt"), b"hello").unwrap());
+//! assert!(!write_if_changed(&mut fs, Path::new("a.txt"), b"hello").unwrap());
+//!
+//! let mut fs = WriteIfChangedFs::new(Box::new(fs));
+//! let mut w = fs.writer(Path::new("a.txt")).unwrap();
+//! w.write_all(b"hello").unwrap();
+//! w.flush().unwrap();
+//! assert_eq!(fs.unchanged(), vec![Path::new("a.txt")]);
+//! ```
+
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::{
+    PermissionDeniedSnafu, Result, Xfs, XfsError, XfsMetadata, XfsReadDir, XfsReadOnly, XfsWrite,
+};
+
+/// Returns true if the file at `p` exists and contains exactly `data`.
+fn has_contents<F: XfsReadOnly + ?Sized>(fs: &F, p: &Path, data: &[u8]) -> Result<bool> {
+    if !fs.is_file(p) {
+        return Ok(false);
+    }
+    let mut existing = vec![];
+    fs.reader(p)?
+        .read_to_end(&mut existing)
+        .map_err(|e| XfsError::IoError {
+            path: p.to_path_buf(),
+            source: e,
+        })?;
+    Ok(existing == data)
+}
+
+/// Writes `data` to the file at `p` unless it already contains exactly
+/// `data`. Returns true if the file was written.
+///
+/// # Errors
+///
+/// Returns an error if the existing file cannot be read, or the file cannot
+/// be written.
+pub fn write_if_changed<F: Xfs + ?Sized>(fs: &mut F, p: &Path, data: &[u8]) -> Result<bool> {
+    if has_contents(fs, p, data)? {
+        return Ok(false);
+    }
+    let mut w = fs.writer(p)?;
+    w.write_all(data)
+        .and_then(|_| w.flush())
+        .map_err(|e| XfsError::IoError {
+            path: p.to_path_buf(),
+            source: e,
+        })?;
+    Ok(true)
+}
+
+/// The files written through a `WriteIfChangedFs`.
+#[derive(Default)]
+struct Outcomes {
+    changed: BTreeSet<PathBuf>,
+    unchanged: BTreeSet<PathBuf>,
+}
+
+impl Outcomes {
+    fn record(&mut self, p: &Path, changed: bool) {
+        if changed {
+            self.unchanged.remove(p);
+            self.changed.insert(p.to_path_buf());
+        } else if !self.changed.contains(p) {
+            self.unchanged.insert(p.to_path_buf());
+        }
+    }
+}
+
+/// Wraps a filesystem so that writers leave files with unchanged contents
+/// untouched.
+///
+/// Writers to existing files buffer everything written and compare it with
+/// the file when flushed or dropped, replacing the file only if the contents
+/// differ. As with `BufWriter`, errors while dropping a writer are ignored,
+/// so flush writers whose errors matter. Writers to new files write through
+/// directly.
+///
+/// Clones share the record of changed and unchanged files.
+pub struct WriteIfChangedFs<F: ?Sized> {
+    outcomes: Arc<Mutex<Outcomes>>,
+    inner: Box<F>,
+}
+
+impl<F: ?Sized> WriteIfChangedFs<F> {
+    pub fn new(inner: Box<F>) -> WriteIfChangedFs<F> {
+        WriteIfChangedFs {
+            outcomes: Arc::new(Mutex::new(Outcomes::default())),
+            inner,
+        }
+    }
+
+    /// Returns the files that were created or replaced, sorted.
+    pub fn changed(&self) -> Vec<PathBuf> {
+        let outcomes = self.outcomes.lock().unwrap();
+        outcomes.changed.iter().cloned().collect()
+    }
+
+    /// Returns the files that were written with their existing contents, and
+    /// so left untouched, sorted.
+    pub fn unchanged(&self) -> Vec<PathBuf> {
+        let outcomes = self.outcomes.lock().unwrap();
+        outcomes.unchanged.iter().cloned().collect()
+    }
+
+    /// Returns the wrapped filesystem.
+    pub fn into_inner(self) -> Box<F> {
+        self.inner
+    }
+}
+
+/// A writer for a new file, which records the file as changed.
+struct CreatingWriter {
+    path: PathBuf,
+    outcomes: Arc<Mutex<Outcomes>>,
+    inner: Box<dyn XfsWrite>,
+}
+
+impl Write for CreatingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl XfsWrite for CreatingWriter {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        self.inner.sync_all()
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        self.inner.sync_data()
+    }
+}
+
+impl Drop for CreatingWriter {
+    fn drop(&mut self) {
+        self.outcomes.lock().unwrap().record(&self.path, true);
+    }
+}
+
+/// A writer for an existing file, which buffers the new contents until it is
+/// flushed or dropped.
+struct BufferingWriter {
+    path: PathBuf,
+    data: Vec<u8>,
+    committed: bool,
+    outcomes: Arc<Mutex<Outcomes>>,
+    fs: Box<dyn Xfs>,
+}
+
+impl BufferingWriter {
+    fn commit(&mut self) -> Result<()> {
+        let changed = write_if_changed(self.fs.as_mut(), &self.path, &self.data)?;
+        self.committed = true;
+        self.outcomes.lock().unwrap().record(&self.path, changed);
+        Ok(())
+    }
+}
+
+impl Write for BufferingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        self.committed = false;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.commit().map_err(into_io_error)
+    }
+}
+
+/// Syncing commits the buffered contents, then syncs the file, which is left
+/// untouched if the contents did not change.
+impl XfsWrite for BufferingWriter {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.fs.sync_all(&self.path).map_err(into_io_error)
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.fs.sync_data(&self.path).map_err(into_io_error)
+    }
+}
+
+fn into_io_error(e: XfsError) -> std::io::Error {
+    match e {
+        XfsError::IoError { source, .. } => source,
+        e => std::io::Error::other(e.to_string()),
+    }
+}
+
+impl Drop for BufferingWriter {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = self.commit();
+        }
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> XfsReadOnly for WriteIfChangedFs<F> {
+    fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
+        Box::new(WriteIfChangedFs {
+            outcomes: self.outcomes.clone(),
+            inner: self.inner.unsafe_clone(),
+        })
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        self.inner.canonical_path(p)
+    }
+
+    fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
+        self.inner.read_dir(p)
+    }
+
+    fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
+        self.inner.reader(p)
+    }
+
+    fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
+        self.inner.read_all_lines(p)
+    }
+
+    fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        self.inner.metadata(p)
+    }
+}
+
+impl<F: Xfs + ?Sized> Xfs for WriteIfChangedFs<F> {
+    fn unsafe_clone_mut(&mut self) -> Box<dyn Xfs> {
+        Box::new(WriteIfChangedFs {
+            outcomes: self.outcomes.clone(),
+            inner: self.inner.unsafe_clone_mut(),
+        })
+    }
+
+    fn writer(&mut self, p: &Path) -> Result<Box<dyn XfsWrite>> {
+        let metadata = self.inner.metadata(p);
+        if !metadata.as_ref().is_ok_and(|md| md.is_file()) {
+            // New files always change, and the inner filesystem reports
+            // directories and missing parents.
+            return Ok(Box::new(CreatingWriter {
+                path: p.to_path_buf(),
+                outcomes: self.outcomes.clone(),
+                inner: self.inner.writer(p)?,
+            }));
+        }
+        // The file is only written when the writer is flushed or dropped, so
+        // fail now, as opening it would, if it is read-only.
+        if metadata?.mode() & 0o222 == 0 {
+            return PermissionDeniedSnafu { path: p }.fail();
+        }
+        Ok(Box::new(BufferingWriter {
+            path: p.to_path_buf(),
+            data: vec![],
+            committed: false,
+            outcomes: self.outcomes.clone(),
+            fs: self.inner.unsafe_clone_mut(),
+        }))
+    }
+
+    fn create_dir(&mut self, p: &Path) -> Result<()> {
+        self.inner.create_dir(p)
+    }
+
+    fn create_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.inner.create_dir_all(p)
+    }
+
+    fn remove_file(&mut self, p: &Path) -> Result<()> {
+        self.inner.remove_file(p)
+    }
+
+    fn remove_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.inner.remove_dir_all(p)
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()> {
+        self.inner.set_permissions(p, mode)
+    }
+
+    fn set_times(
+        &mut self,
+        p: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<()> {
+        self.inner.set_times(p, accessed, modified)
+    }
+
+    fn sync_all(&mut self, p: &Path) -> Result<()> {
+        self.inner.sync_all(p)
+    }
+
+    fn sync_data(&mut self, p: &Path) -> Result<()> {
+        self.inner.sync_data(p)
+    }
+}
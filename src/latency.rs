@@ -0,0 +1,345 @@
+//! A wrapper that slows down another filesystem, to reproduce the behaviour
+//! of network shares and other slow storage in tests and benchmarks.
+//!
+//! Every operation sleeps for a configurable latency before being passed on,
+//! and readers and writers additionally sleep in proportion to the number of
+//! bytes transferred when a bandwidth limit is set. Delays are exact unless
+//! jitter is configured, in which case they vary randomly, but reproducibly,
+//! from a seed.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::Path;
+//! use std::time::Duration;
+//! use inscenerator_xfs::latency::{LatencyConfig, LatencyFs};
+//! use inscenerator_xfs::mockfs::MockFS;
+//! use inscenerator_xfs::XfsReadOnly;
+//!
+//! let fs = LatencyFs::new(
+//!     Box::new(MockFS::new()),
+//!     LatencyConfig {
+//!         latency: Duration::from_millis(1),
+//!         read_bandwidth: Some(1024 * 1024),
+//!         ..LatencyConfig::default()
+//!     },
+//! );
+//! assert!(fs.is_dir(Path::new("/")));
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::{Result, Xfs, XfsMetadata, XfsReadDir, XfsReadOnly, XfsWrite};
+
+/// The operations that can be given their own latency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Operation {
+    ReadDir,
+    Reader,
+    ReadAllLines,
+    Metadata,
+    Writer,
+    CreateDir,
+    CreateDirAll,
+    RemoveFile,
+    RemoveDirAll,
+    Rename,
+    SetPermissions,
+    SetTimes,
+    SyncAll,
+    SyncData,
+}
+
+/// How delays vary from their configured values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Jitter {
+    /// Every delay is exactly as configured.
+    None,
+    /// Every delay is scaled by a random factor in `1 ± fraction`, drawn from
+    /// a generator seeded with `seed` so that runs are reproducible.
+    ///
+    /// Delays never go below zero, and are capped at `Duration::MAX` if the
+    /// factor is too large. A `fraction` that is NaN adds no jitter.
+    Uniform { fraction: f64, seed: u64 },
+}
+
+/// The delays added by a `LatencyFs`.
+#[derive(Debug, Clone)]
+pub struct LatencyConfig {
+    /// The delay before every operation without an entry in `per_operation`.
+    pub latency: Duration,
+    /// Delays for specific operations, overriding `latency`.
+    pub per_operation: BTreeMap<Operation, Duration>,
+    /// The maximum read rate, in bytes per second, or `None` for no limit.
+    pub read_bandwidth: Option<u64>,
+    /// The maximum write rate, in bytes per second, or `None` for no limit.
+    pub write_bandwidth: Option<u64>,
+    pub jitter: Jitter,
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        LatencyConfig {
+            latency: Duration::ZERO,
+            per_operation: BTreeMap::new(),
+            read_bandwidth: None,
+            write_bandwidth: None,
+            jitter: Jitter::None,
+        }
+    }
+}
+
+/// A function that waits for the given duration.
+pub type SleepFn = Arc<dyn Fn(Duration) + Send + Sync>;
+
+/// The state shared by a `LatencyFs`, its clones, and its readers and writers.
+struct Pacer {
+    config: LatencyConfig,
+    rng: Mutex<u64>,
+    sleep: SleepFn,
+}
+
+impl Pacer {
+    /// The splitmix64 generator, which is small and good enough for jitter.
+    fn next_random(&self) -> u64 {
+        let mut state = self.rng.lock().unwrap();
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn wait(&self, d: Duration) {
+        if d.is_zero() {
+            return;
+        }
+        let d = match self.config.jitter {
+            Jitter::None => d,
+            Jitter::Uniform { fraction, .. } => {
+                // A uniform value in [0, 1) from the top 53 bits.
+                let unit = (self.next_random() >> 11) as f64 / (1u64 << 53) as f64;
+                let factor = 1.0 + fraction * (2.0 * unit - 1.0);
+                if factor.is_nan() {
+                    d
+                } else {
+                    Duration::try_from_secs_f64(d.as_secs_f64() * factor.max(0.0))
+                        .unwrap_or(Duration::MAX)
+                }
+            }
+        };
+        (self.sleep)(d)
+    }
+
+    fn operation(&self, op: Operation) {
+        let d = self
+            .config
+            .per_operation
+            .get(&op)
+            .copied()
+            .unwrap_or(self.config.latency);
+        self.wait(d)
+    }
+
+    fn transfer(&self, bytes: usize, bandwidth: Option<u64>) {
+        if let Some(bandwidth) = bandwidth {
+            self.wait(Duration::from_secs_f64(
+                bytes as f64 / bandwidth.max(1) as f64,
+            ))
+        }
+    }
+}
+
+/// Wraps a filesystem, delaying every operation and limiting the bandwidth of
+/// its readers and writers.
+///
+/// Clones share the configuration and the jitter generator.
+pub struct LatencyFs<F: ?Sized> {
+    pacer: Arc<Pacer>,
+    inner: Box<F>,
+}
+
+impl<F: ?Sized> LatencyFs<F> {
+    /// Wraps `inner`, sleeping with `std::thread::sleep`.
+    pub fn new(inner: Box<F>, config: LatencyConfig) -> LatencyFs<F> {
+        Self::with_sleep(inner, config, Arc::new(std::thread::sleep))
+    }
+
+    /// Wraps `inner`, waiting with `sleep` instead of sleeping the thread.
+    ///
+    /// This allows the delays to be recorded, or applied to a
+    /// `ManualClock`, so tests do not need to wait.
+    pub fn with_sleep(inner: Box<F>, config: LatencyConfig, sleep: SleepFn) -> LatencyFs<F> {
+        let seed = match config.jitter {
+            Jitter::None => 0,
+            Jitter::Uniform { seed, .. } => seed,
+        };
+        LatencyFs {
+            pacer: Arc::new(Pacer {
+                config,
+                rng: Mutex::new(seed),
+                sleep,
+            }),
+            inner,
+        }
+    }
+
+    /// Returns the wrapped filesystem.
+    pub fn into_inner(self) -> Box<F> {
+        self.inner
+    }
+}
+
+struct LatencyReader {
+    pacer: Arc<Pacer>,
+    inner: Box<dyn Read>,
+}
+
+impl Read for LatencyReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pacer.transfer(n, self.pacer.config.read_bandwidth);
+        Ok(n)
+    }
+}
+
+struct LatencyWriter {
+    pacer: Arc<Pacer>,
+    inner: Box<dyn XfsWrite>,
+}
+
+impl Write for LatencyWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.pacer.transfer(n, self.pacer.config.write_bandwidth);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl XfsWrite for LatencyWriter {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        self.pacer.operation(Operation::SyncAll);
+        self.inner.sync_all()
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        self.pacer.operation(Operation::SyncData);
+        self.inner.sync_data()
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> XfsReadOnly for LatencyFs<F> {
+    fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
+        Box::new(LatencyFs {
+            pacer: self.pacer.clone(),
+            inner: self.inner.unsafe_clone(),
+        })
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        self.inner.canonical_path(p)
+    }
+
+    fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
+        self.pacer.operation(Operation::ReadDir);
+        self.inner.read_dir(p)
+    }
+
+    fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
+        self.pacer.operation(Operation::Reader);
+        Ok(Box::new(LatencyReader {
+            pacer: self.pacer.clone(),
+            inner: self.inner.reader(p)?,
+        }))
+    }
+
+    fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
+        self.pacer.operation(Operation::ReadAllLines);
+        let lines = self.inner.read_all_lines(p)?;
+        // Count a line ending per line, as the original file must have had
+        // at least that many bytes.
+        let bytes = lines.iter().map(|l| l.len() + 1).sum();
+        self.pacer.transfer(bytes, self.pacer.config.read_bandwidth);
+        Ok(lines)
+    }
+
+    fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        self.pacer.operation(Operation::Metadata);
+        self.inner.metadata(p)
+    }
+}
+
+impl<F: Xfs + ?Sized> Xfs for LatencyFs<F> {
+    fn unsafe_clone_mut(&mut self) -> Box<dyn Xfs> {
+        Box::new(LatencyFs {
+            pacer: self.pacer.clone(),
+            inner: self.inner.unsafe_clone_mut(),
+        })
+    }
+
+    fn writer(&mut self, p: &Path) -> Result<Box<dyn XfsWrite>> {
+        self.pacer.operation(Operation::Writer);
+        Ok(Box::new(LatencyWriter {
+            pacer: self.pacer.clone(),
+            inner: self.inner.writer(p)?,
+        }))
+    }
+
+    fn create_dir(&mut self, p: &Path) -> Result<()> {
+        self.pacer.operation(Operation::CreateDir);
+        self.inner.create_dir(p)
+    }
+
+    fn create_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.pacer.operation(Operation::CreateDirAll);
+        self.inner.create_dir_all(p)
+    }
+
+    fn remove_file(&mut self, p: &Path) -> Result<()> {
+        self.pacer.operation(Operation::RemoveFile);
+        self.inner.remove_file(p)
+    }
+
+    fn remove_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.pacer.operation(Operation::RemoveDirAll);
+        self.inner.remove_dir_all(p)
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        self.pacer.operation(Operation::Rename);
+        self.inner.rename(from, to)
+    }
+
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()> {
+        self.pacer.operation(Operation::SetPermissions);
+        self.inner.set_permissions(p, mode)
+    }
+
+    fn set_times(
+        &mut self,
+        p: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<()> {
+        self.pacer.operation(Operation::SetTimes);
+        self.inner.set_times(p, accessed, modified)
+    }
+
+    fn sync_all(&mut self, p: &Path) -> Result<()> {
+        self.pacer.operation(Operation::SyncAll);
+        self.inner.sync_all(p)
+    }
+
+    fn sync_data(&mut self, p: &Path) -> Result<()> {
+        self.pacer.operation(Operation::SyncData);
+        self.inner.sync_data(p)
+    }
+}
@@ -1,9 +1,36 @@
 use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
-
-use snafu::{ResultExt, Snafu};
-
+use std::time::SystemTime;
+
+use snafu::Snafu;
+
+mod macros;
+
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+pub mod clock;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+pub mod copy;
+pub mod deps;
+pub mod dryrun;
+pub mod glob;
+pub mod hash;
+pub mod ifchanged;
+pub mod latency;
+pub mod manifest;
+pub mod mirror;
 pub mod mockfs;
+pub mod mount;
+pub mod outputs;
+pub mod policy;
+pub mod snapshot;
+pub mod strict;
+pub mod subfs;
+pub mod txtar;
+pub mod union;
+
+pub use copy::Overwrite;
 
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub))]
@@ -26,6 +53,24 @@ pub enum XfsError {
     #[snafu(display("Path is not a file: {}", path.display()))]
     NotAFile { path: PathBuf },
 
+    #[snafu(display("Directory not empty: {}", path.display()))]
+    DirectoryNotEmpty { path: PathBuf },
+
+    #[snafu(display("Permission denied at {}", path.display()))]
+    PermissionDenied { path: PathBuf },
+
+    #[snafu(display("Filesystem is read-only at {}", path.display()))]
+    ReadOnlyFilesystem { path: PathBuf },
+
+    #[snafu(display("Cannot move {} to a different filesystem", path.display()))]
+    CrossesDevices { path: PathBuf },
+
+    #[snafu(display("Too many open files when opening {}", path.display()))]
+    TooManyOpenFiles { path: PathBuf },
+
+    #[snafu(display("File is in use: {}", path.display()))]
+    FileInUse { path: PathBuf },
+
     #[snafu(display("Path steps outside the sandbox: {}", path.display()))]
     PathOutsideSandbox { path: PathBuf },
 
@@ -41,6 +86,33 @@ pub enum XfsError {
     },
 }
 
+impl XfsError {
+    /// Rewrites the path carried by the error, for wrappers that translate
+    /// between namespaces.
+    pub(crate) fn map_path(self, f: impl FnOnce(PathBuf) -> PathBuf) -> XfsError {
+        use XfsError::*;
+        match self {
+            IoError { path, source } => IoError {
+                path: f(path),
+                source,
+            },
+            NotFound { path } => NotFound { path: f(path) },
+            AlreadyExists { path } => AlreadyExists { path: f(path) },
+            NotADirectory { path } => NotADirectory { path: f(path) },
+            NotAFile { path } => NotAFile { path: f(path) },
+            DirectoryNotEmpty { path } => DirectoryNotEmpty { path: f(path) },
+            PermissionDenied { path } => PermissionDenied { path: f(path) },
+            ReadOnlyFilesystem { path } => ReadOnlyFilesystem { path: f(path) },
+            CrossesDevices { path } => CrossesDevices { path: f(path) },
+            TooManyOpenFiles { path } => TooManyOpenFiles { path: f(path) },
+            FileInUse { path } => FileInUse { path: f(path) },
+            PathOutsideSandbox { path } => PathOutsideSandbox { path: f(path) },
+            InvalidUtf8 { path } => InvalidUtf8 { path: f(path) },
+            e @ (GeneralError { .. } | UserError { .. }) => e,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, XfsError>;
 
 /// A result type for a single directory entry.
@@ -57,6 +129,58 @@ pub trait XfsDirEntry {
 pub trait XfsMetadata {
     fn is_dir(&self) -> bool;
     fn is_file(&self) -> bool;
+
+    /// The size of a file in bytes. The size reported for a directory is
+    /// platform-specific.
+    fn size(&self) -> u64;
+
+    /// The Unix-style permission bits (e.g. `0o644`) of the entry.
+    ///
+    /// On platforms without Unix permissions this is derived from the
+    /// read-only flag.
+    fn mode(&self) -> u32;
+
+    /// The last modification time, if the platform records it.
+    fn modified(&self) -> Option<SystemTime>;
+
+    /// The last access time, if the platform records it.
+    fn accessed(&self) -> Option<SystemTime>;
+
+    /// The creation time, if the platform records it.
+    fn created(&self) -> Option<SystemTime>;
+}
+
+/// A writer for a file, as returned by `Xfs::writer`.
+pub trait XfsWrite: Write {
+    /// Flushes buffered data, then the contents and metadata of the file to
+    /// durable storage.
+    fn sync_all(&mut self) -> std::io::Result<()>;
+
+    /// Flushes buffered data, then the contents of the file to durable
+    /// storage, but not necessarily metadata such as its timestamps.
+    fn sync_data(&mut self) -> std::io::Result<()>;
+}
+
+impl<W: XfsWrite + ?Sized> XfsWrite for Box<W> {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        (**self).sync_all()
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        (**self).sync_data()
+    }
+}
+
+impl XfsWrite for BufWriter<std::fs::File> {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.get_ref().sync_all()
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.get_ref().sync_data()
+    }
 }
 
 /// A read-only interface to a filesystem.
@@ -136,6 +260,18 @@ pub trait XfsReadOnly: Send {
     fn is_file(&self, p: &Path) -> bool {
         self.metadata(p).map(|md| md.is_file()).unwrap_or(false)
     }
+
+    /// Returns the path that `p` names in this filesystem, with `.` and `..`
+    /// resolved and, where the filesystem defines what relative paths are
+    /// relative to, made absolute. Paths that name the same entry give the
+    /// same result. Backends with symbolic links look them up to resolve them,
+    /// but nothing else accesses the filesystem.
+    ///
+    /// The default resolves `.` and `..` lexically and leaves relative paths
+    /// relative.
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        copy::normalize(p)
+    }
 }
 
 /// A read-write interface to a filesystem.
@@ -157,11 +293,14 @@ pub trait Xfs: XfsReadOnly {
 
     /// Creates a new file or truncates an existing one for writing.
     ///
+    /// The writer can make what was written durable with
+    /// `XfsWrite::sync_all`, which also flushes any buffered data.
+    ///
     /// # Errors
     ///
     /// Returns an error if the parent directory does not exist, or if there is
     /// an IO error.
-    fn writer(&mut self, p: &Path) -> Result<Box<dyn Write>>;
+    fn writer(&mut self, p: &Path) -> Result<Box<dyn XfsWrite>>;
 
     /// Creates a new directory.
     ///
@@ -197,22 +336,216 @@ pub trait Xfs: XfsReadOnly {
 
     /// Renames or moves a file or directory.
     ///
+    /// An existing file at `to` is replaced by a file, and an existing empty
+    /// directory at `to` is replaced by a directory.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the source path does not exist, or if there is
-    /// an IO error.
+    /// Returns an error if the source path does not exist, if `to` exists and
+    /// cannot be replaced, if a directory would be moved inside itself, or if
+    /// there is an IO error.
     fn rename(&mut self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Sets the Unix-style permission bits (e.g. `0o644`) of a file or directory.
+    ///
+    /// On platforms without Unix permissions only the read-only flag is
+    /// updated, based on whether any write bit is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path does not exist, if the caller is not
+    /// allowed to change the permissions, or if there is an IO error.
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()>;
+
+    /// Sets the access and/or modification times of a file or directory.
+    ///
+    /// Times given as `None` are left unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path does not exist, if the caller is not
+    /// allowed to change the times, or if there is an IO error.
+    fn set_times(
+        &mut self,
+        p: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<()>;
+
+    /// Flushes the contents and metadata of a file to durable storage.
+    ///
+    /// Called on a directory, this makes the creation, removal and renaming
+    /// of its entries durable. Platforms that cannot sync directories treat
+    /// this as a no-op. To sync a file while writing it, including data still
+    /// buffered in the writer, use `XfsWrite::sync_all` instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path does not exist, cannot be opened, or if
+    /// there is an IO error.
+    fn sync_all(&mut self, p: &Path) -> Result<()>;
+
+    /// Flushes the contents of a file to durable storage, but not necessarily
+    /// metadata such as its timestamps. Called on a directory it behaves like
+    /// `sync_all`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path does not exist, cannot be opened, or if
+    /// there is an IO error.
+    fn sync_data(&mut self, p: &Path) -> Result<()>;
+
+    /// Copies the contents and permissions of the file at `from` to `to`.
+    ///
+    /// An existing file at `to` is handled according to `overwrite`. Copying
+    /// a file onto itself does nothing unless `overwrite` is `Error`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `from` does not exist or is a directory, if `to`
+    /// exists and `overwrite` is `Error`, if `to` is a directory and
+    /// `overwrite` is `Merge`, or if there is an IO error.
+    fn copy_file(&mut self, from: &Path, to: &Path, overwrite: Overwrite) -> Result<()> {
+        copy::copy_file(self, from, to, overwrite)
+    }
+
+    /// Copies a file, or a directory and everything below it, from `from` to
+    /// `to`.
+    ///
+    /// With `Skip` and `Merge`, existing directories are merged with the
+    /// copied ones, and existing files are kept or replaced respectively.
+    /// Symbolic links below `from` are copied as the files they point to, or
+    /// as empty directories, so that links back up the tree cannot make the
+    /// copy loop.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `from` does not exist, if `to` is inside `from`,
+    /// if an entry exists and `overwrite` is `Error`, if a file and a
+    /// directory would be merged, or if there is an IO error. Entries copied
+    /// before the failure are kept.
+    fn copy_recursive(&mut self, from: &Path, to: &Path, overwrite: Overwrite) -> Result<()> {
+        copy::copy_recursive(self, from, to, overwrite)
+    }
+
+    /// Moves a file, or a directory and everything below it, from `from` to
+    /// `to`.
+    ///
+    /// Entries are renamed where possible. Where `rename` fails with
+    /// `CrossesDevices`, such as between the mounts of a `MountFs` or the
+    /// devices of an `OsFs`, they are copied and then removed. Existing
+    /// entries are handled as by `copy_recursive`; with `Skip`, source
+    /// entries that were not moved are left in place.
+    ///
+    /// # Errors
+    ///
+    /// As for `copy_recursive`. Entries moved before the failure are kept.
+    fn move_recursive(&mut self, from: &Path, to: &Path, overwrite: Overwrite) -> Result<()> {
+        copy::move_recursive(self, from, to, overwrite)
+    }
 }
 
 pub struct OsFs {}
 
+/// `EMFILE`, which has the same value on Linux, macOS and the BSDs.
+#[cfg(unix)]
+const TOO_MANY_OPEN_FILES: i32 = 24;
+/// `ERROR_TOO_MANY_OPEN_FILES`.
+#[cfg(windows)]
+const TOO_MANY_OPEN_FILES: i32 = 4;
+/// `ERROR_SHARING_VIOLATION`.
+#[cfg(windows)]
+const SHARING_VIOLATION: i32 = 32;
+
+/// Converts an IO error into an `XfsError`, using the specific variants for
+/// error kinds that `MockFS` also reports.
+fn io_error(path: &Path, source: std::io::Error) -> XfsError {
+    let path_buf = path.to_path_buf();
+    match source.raw_os_error() {
+        Some(TOO_MANY_OPEN_FILES) => return XfsError::TooManyOpenFiles { path: path_buf },
+        #[cfg(windows)]
+        Some(SHARING_VIOLATION) => return XfsError::FileInUse { path: path_buf },
+        _ => {}
+    }
+    match source.kind() {
+        std::io::ErrorKind::NotFound => XfsError::NotFound { path: path_buf },
+        std::io::ErrorKind::AlreadyExists => XfsError::AlreadyExists { path: path_buf },
+        std::io::ErrorKind::NotADirectory => XfsError::NotADirectory { path: path_buf },
+        std::io::ErrorKind::IsADirectory => XfsError::NotAFile { path: path_buf },
+        std::io::ErrorKind::DirectoryNotEmpty => XfsError::DirectoryNotEmpty { path: path_buf },
+        std::io::ErrorKind::PermissionDenied => XfsError::PermissionDenied { path: path_buf },
+        std::io::ErrorKind::ReadOnlyFilesystem => XfsError::ReadOnlyFilesystem { path: path_buf },
+        std::io::ErrorKind::CrossesDevices => XfsError::CrossesDevices { path: path_buf },
+        _ => XfsError::IoError {
+            path: path.to_path_buf(),
+            source,
+        },
+    }
+}
+
+/// Opens a file for reading, failing with `NotAFile` for directories (which
+/// some platforms allow to be opened).
+fn open_file(p: &Path) -> Result<std::fs::File> {
+    let file = std::fs::File::open(p).map_err(|e| io_error(p, e))?;
+    let md = file.metadata().map_err(|e| io_error(p, e))?;
+    if md.is_dir() {
+        return NotAFileSnafu { path: p }.fail();
+    }
+    Ok(file)
+}
+
+/// `FILE_FLAG_BACKUP_SEMANTICS`, without which directories cannot be opened.
+#[cfg(windows)]
+const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+/// Opens a file or directory so that its timestamps can be set. Files are
+/// opened for writing, which works even if they cannot be read. Directories
+/// cannot be opened for writing on Unix, so they are opened for reading.
+fn open_for_times(p: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        options.custom_flags(FILE_FLAG_BACKUP_SEMANTICS);
+    }
+    match options.open(p) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => std::fs::File::open(p).map_err(|_| e),
+        r => r,
+    }
+}
+
+/// Opens a file or directory and applies `sync` to it. Directories cannot be
+/// opened on every platform, so elsewhere syncing them does nothing. Files
+/// are opened for writing where permitted, as some platforms only flush
+/// files opened that way, and for reading otherwise.
+fn sync_path(p: &Path, sync: fn(&std::fs::File) -> std::io::Result<()>) -> Result<()> {
+    let is_dir = std::fs::metadata(p).map_err(|e| io_error(p, e))?.is_dir();
+    #[cfg(not(unix))]
+    if is_dir {
+        return Ok(());
+    }
+    let file = match std::fs::OpenOptions::new()
+        .write(!is_dir)
+        .read(is_dir)
+        .open(p)
+    {
+        Err(e) if !is_dir && e.kind() == std::io::ErrorKind::PermissionDenied => {
+            std::fs::File::open(p)
+        }
+        r => r,
+    }
+    .map_err(|e| io_error(p, e))?;
+    sync(&file).map_err(|e| io_error(p, e))
+}
+
 impl XfsDirEntry for std::fs::DirEntry {
     fn path(&self) -> PathBuf {
         std::fs::DirEntry::path(self)
     }
 
     fn metadata(&self) -> Result<Box<dyn XfsMetadata>> {
-        let md = std::fs::DirEntry::metadata(self).context(IoSnafu { path: self.path() })?;
+        let md = std::fs::DirEntry::metadata(self).map_err(|e| io_error(&self.path(), e))?;
         Ok(Box::new(md))
     }
 }
@@ -225,6 +558,38 @@ impl XfsMetadata for std::fs::Metadata {
     fn is_file(&self) -> bool {
         std::fs::Metadata::is_file(self)
     }
+
+    fn size(&self) -> u64 {
+        std::fs::Metadata::len(self)
+    }
+
+    #[cfg(unix)]
+    fn mode(&self) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        self.permissions().mode() & 0o7777
+    }
+
+    #[cfg(not(unix))]
+    fn mode(&self) -> u32 {
+        let base = if self.is_dir() { 0o755 } else { 0o644 };
+        if self.permissions().readonly() {
+            base & !0o222
+        } else {
+            base
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::Metadata::modified(self).ok()
+    }
+
+    fn accessed(&self) -> Option<SystemTime> {
+        std::fs::Metadata::accessed(self).ok()
+    }
+
+    fn created(&self) -> Option<SystemTime> {
+        std::fs::Metadata::created(self).ok()
+    }
 }
 
 impl XfsReadOnly for OsFs {
@@ -232,11 +597,27 @@ impl XfsReadOnly for OsFs {
         Box::new(OsFs {})
     }
 
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        // Relative paths are relative to the current directory. Symbolic
+        // links are resolved in the longest part of the path that exists, and
+        // the rest, which does not exist yet, is resolved lexically.
+        let absolute = std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf());
+        let components: Vec<_> = absolute.components().collect();
+        for n in (1..=components.len()).rev() {
+            let existing: PathBuf = components[..n].iter().collect();
+            if let Ok(mut resolved) = std::fs::canonicalize(&existing) {
+                resolved.extend(&components[n..]);
+                return copy::normalize(&resolved);
+            }
+        }
+        copy::normalize(&absolute)
+    }
+
     fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
         let path_buf = p.to_path_buf();
-        let read_dir = std::fs::read_dir(p).context(IoSnafu { path: p })?;
+        let read_dir = std::fs::read_dir(p).map_err(|e| io_error(p, e))?;
         let iter = read_dir.map(move |entry| {
-            let entry = entry.context(IoSnafu { path: &path_buf })?;
+            let entry = entry.map_err(|e| io_error(&path_buf, e))?;
             let entry: Box<dyn XfsDirEntry> = Box::new(entry);
             Ok(entry)
         });
@@ -244,18 +625,23 @@ impl XfsReadOnly for OsFs {
     }
 
     fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
-        let file = std::fs::File::open(p).context(IoSnafu { path: p })?;
+        let file = open_file(p)?;
         Ok(Box::new(BufReader::new(file)))
     }
 
     fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
-        let file = std::fs::File::open(p).context(IoSnafu { path: p })?;
+        let file = open_file(p)?;
         let lines: std::io::Result<Vec<_>> = BufReader::new(file).lines().collect();
-        lines.context(IoSnafu { path: p })
+        lines.map_err(|e| match e.kind() {
+            std::io::ErrorKind::InvalidData => XfsError::InvalidUtf8 {
+                path: p.to_path_buf(),
+            },
+            _ => io_error(p, e),
+        })
     }
 
     fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
-        let m = std::fs::metadata(p).context(IoSnafu { path: p })?;
+        let m = std::fs::metadata(p).map_err(|e| io_error(p, e))?;
         Ok(Box::new(m))
     }
 }
@@ -265,33 +651,84 @@ impl Xfs for OsFs {
         Box::new(OsFs {})
     }
 
-    fn writer(&mut self, p: &Path) -> Result<Box<dyn Write>> {
-        let file = std::fs::File::create(p).context(IoSnafu { path: p })?;
+    fn writer(&mut self, p: &Path) -> Result<Box<dyn XfsWrite>> {
+        let file = std::fs::File::create(p).map_err(|e| io_error(p, e))?;
         Ok(Box::new(BufWriter::new(file)))
     }
 
     fn create_dir(&mut self, p: &Path) -> Result<()> {
-        std::fs::create_dir(p).context(IoSnafu { path: p })?;
+        std::fs::create_dir(p).map_err(|e| io_error(p, e))?;
         Ok(())
     }
 
     fn create_dir_all(&mut self, p: &Path) -> Result<()> {
-        std::fs::create_dir_all(p).context(IoSnafu { path: p })?;
+        std::fs::create_dir_all(p).map_err(|e| io_error(p, e))?;
         Ok(())
     }
 
     fn remove_file(&mut self, p: &Path) -> Result<()> {
-        std::fs::remove_file(p).context(IoSnafu { path: p })?;
+        std::fs::remove_file(p).map_err(|e| io_error(p, e))?;
         Ok(())
     }
 
     fn remove_dir_all(&mut self, p: &Path) -> Result<()> {
-        std::fs::remove_dir_all(p).context(IoSnafu { path: p })?;
+        std::fs::remove_dir_all(p).map_err(|e| io_error(p, e))?;
         Ok(())
     }
 
     fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
-        std::fs::rename(from, to).context(IoSnafu { path: from })?;
+        std::fs::rename(from, to).map_err(|e| io_error(from, e))?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let permissions = std::fs::Permissions::from_mode(mode);
+        std::fs::set_permissions(p, permissions).map_err(|e| io_error(p, e))?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()> {
+        let mut permissions = std::fs::metadata(p)
+            .map_err(|e| io_error(p, e))?
+            .permissions();
+        permissions.set_readonly(mode & 0o222 == 0);
+        std::fs::set_permissions(p, permissions).map_err(|e| io_error(p, e))?;
+        Ok(())
+    }
+
+    fn set_times(
+        &mut self,
+        p: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<()> {
+        let mut times = std::fs::FileTimes::new();
+        if let Some(accessed) = accessed {
+            times = times.set_accessed(accessed);
+        }
+        if let Some(modified) = modified {
+            times = times.set_modified(modified);
+        }
+        let file = open_for_times(p).map_err(|e| io_error(p, e))?;
+        file.set_times(times).map_err(|e| io_error(p, e))?;
+        Ok(())
+    }
+
+    fn sync_all(&mut self, p: &Path) -> Result<()> {
+        sync_path(p, std::fs::File::sync_all)
+    }
+
+    fn sync_data(&mut self, p: &Path) -> Result<()> {
+        sync_path(p, std::fs::File::sync_data)
+    }
+
+    fn copy_file(&mut self, from: &Path, to: &Path, overwrite: Overwrite) -> Result<()> {
+        if copy::prepare_copy_file(self, from, to, overwrite)? {
+            std::fs::copy(from, to).map_err(|e| io_error(to, e))?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,58 @@
+/// Builds a `MockFS` from a nested literal description.
+///
+/// Keys are file or directory names. A value in braces is a directory, which
+/// may be empty; any other value is the contents of a file and may be a string
+/// or a byte string (anything implementing `mockfs::IntoMockContents`).
+///
+/// # Panics
+///
+/// Panics if the description contains conflicting or invalid paths.
+///
+/// # Example
+///
+/// ```
+/// use std::path::Path;
+/// use inscenerator_xfs::{mockfs, XfsReadOnly};
+///
+/// let fs = mockfs!({
+///     "src": { "main.rs": "fn main(){}" },
+///     "empty_dir": {},
+///     "bin": b"\x00\x01",
+/// });
+/// assert_eq!(fs.get_str(Path::new("src/main.rs")).unwrap(), "fn main(){}");
+/// assert!(fs.is_dir(Path::new("empty_dir")));
+/// assert_eq!(fs.get(Path::new("bin")).unwrap(), vec![0, 1]);
+/// ```
+#[macro_export]
+macro_rules! mockfs {
+    ({ $($body:tt)* }) => {
+        $crate::mockfs!($($body)*)
+    };
+    ($($body:tt)*) => {{
+        let mut fs = $crate::mockfs::MockFS::new();
+        $crate::__mockfs_entries!(fs, ::std::path::PathBuf::new(), $($body)*);
+        fs
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __mockfs_entries {
+    ($fs:ident, $dir:expr, ) => {};
+    ($fs:ident, $dir:expr, $name:literal : { $($inner:tt)* } $(, $($rest:tt)*)?) => {
+        {
+            let path = $dir.join($name);
+            $crate::Xfs::create_dir_all(&mut $fs, &path).unwrap();
+            $crate::__mockfs_entries!($fs, path, $($inner)*);
+        }
+        $crate::__mockfs_entries!($fs, $dir, $($($rest)*)?);
+    };
+    ($fs:ident, $dir:expr, $name:literal : $contents:expr $(, $($rest:tt)*)?) => {
+        $fs.add_r(
+            &$dir.join($name),
+            $crate::mockfs::IntoMockContents::into_mock_contents($contents),
+        )
+        .unwrap();
+        $crate::__mockfs_entries!($fs, $dir, $($($rest)*)?);
+    };
+}
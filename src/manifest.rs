@@ -0,0 +1,324 @@
+//! Manifests of whole directory trees, for integrity checks and cache keys.
+//!
+//! A `Manifest` records the type of every entry below a root directory and
+//! the size and SHA-256 hash of every file. It can be formatted as text and
+//! parsed back, and checked against any filesystem with `verify`.
+//!
+//! The text format has one line per entry, sorted by path. Paths are relative
+//! to the root, separated by `/`, and may contain spaces:
+//!
+//! ```text
+//! dir css
+//! file 2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824 5 css/site.css
+//! file e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855 0 index.html
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::Write;
+//! use std::path::Path;
+//! use inscenerator_xfs::manifest::{Manifest, Mismatch};
+//! use inscenerator_xfs::mockfs::MockFS;
+//! use inscenerator_xfs::Xfs;
+//!
+//! let mut fs = MockFS::new();
+//! fs.add_file(Path::new("/release/app.js"), "console.log(1)").unwrap();
+//! let manifest = Manifest::from_fs(&fs, Path::new("/release")).unwrap();
+//! assert_eq!(Manifest::parse(&manifest.format()).unwrap(), manifest);
+//!
+//! fs.writer(Path::new("/release/app.js"))
+//!     .unwrap()
+//!     .write_all(b"console.log(2)")
+//!     .unwrap();
+//! assert_eq!(
+//!     manifest.verify(&fs, Path::new("/release")).unwrap(),
+//!     vec![Mismatch::Changed { name: "app.js".to_string() }]
+//! );
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::copy::is_link;
+use crate::hash::{hash_reader, Digest};
+use crate::{GeneralSnafu, InvalidUtf8Snafu, Result, Xfs, XfsError, XfsReadOnly};
+
+/// What a manifest records about one path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", rename_all = "snake_case")
+)]
+pub enum Entry {
+    File { hash: Digest, size: u64 },
+    Dir,
+}
+
+/// A difference between a manifest and a filesystem.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Mismatch {
+    /// The manifest lists the path but the filesystem does not have it.
+    Missing { name: String },
+    /// The filesystem has the path but the manifest does not list it.
+    Unexpected { name: String },
+    /// The path has a different type, size or hash.
+    Changed { name: String },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Missing { name } => write!(f, "missing {}", name),
+            Mismatch::Unexpected { name } => write!(f, "unexpected {}", name),
+            Mismatch::Changed { name } => write!(f, "changed {}", name),
+        }
+    }
+}
+
+/// The entries of a directory tree, keyed by path relative to its root.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Manifest {
+    entries: BTreeMap<String, Entry>,
+}
+
+/// Counts the bytes read through it.
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+fn relative_name(root: &Path, p: &Path) -> Result<String> {
+    let rel = p.strip_prefix(root).unwrap_or(p);
+    let parts: Option<Vec<&str>> = rel.iter().map(|c| c.to_str()).collect();
+    match parts {
+        // `parse` splits lines with `str::lines`, which would drop a `\r`
+        // before the `\n` ending the line.
+        Some(parts) if parts.iter().any(|c| c.contains(['\n', '\r'])) => GeneralSnafu {
+            message: format!("cannot list {:?} in a manifest", p),
+        }
+        .fail(),
+        Some(parts) => Ok(parts.join("/")),
+        None => InvalidUtf8Snafu { path: p }.fail(),
+    }
+}
+
+fn collect(
+    fs: &dyn XfsReadOnly,
+    root: &Path,
+    dir: &Path,
+    entries: &mut BTreeMap<String, Entry>,
+) -> Result<()> {
+    let canonical_dir = fs.canonical_path(dir);
+    for de in fs.read_dir(dir)? {
+        let de = de?;
+        let path: PathBuf = de.path();
+        let name = relative_name(root, &path)?;
+        if de.metadata()?.is_dir() {
+            entries.insert(name, Entry::Dir);
+            if !is_link(fs, &canonical_dir, &path) {
+                collect(fs, root, &path, entries)?;
+            }
+            continue;
+        }
+        if fs.is_dir(&path) {
+            // The entry's metadata did not follow a symbolic link to a
+            // directory.
+            entries.insert(name, Entry::Dir);
+            continue;
+        }
+        let mut r = CountingReader {
+            inner: fs.reader(&path)?,
+            count: 0,
+        };
+        let hash = hash_reader(&mut r, &path)?;
+        entries.insert(
+            name,
+            Entry::File {
+                hash,
+                size: r.count,
+            },
+        );
+    }
+    Ok(())
+}
+
+impl Manifest {
+    pub fn new() -> Manifest {
+        Manifest::default()
+    }
+
+    /// Builds a manifest of every file and directory below `root`.
+    ///
+    /// Symbolic links to files are recorded as the files they point to.
+    /// Symbolic links to directories are recorded as empty directories, so
+    /// that links pointing back up the tree cannot make the walk loop.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any path is not valid UTF-8 or contains a newline
+    /// or carriage return, or if reading fails.
+    pub fn from_fs(fs: &dyn XfsReadOnly, root: &Path) -> Result<Manifest> {
+        let mut entries = BTreeMap::new();
+        collect(fs, root, root, &mut entries)?;
+        Ok(Manifest { entries })
+    }
+
+    /// Adds or replaces the entry for `name`, a `/`-separated relative path.
+    pub fn insert(&mut self, name: &str, entry: Entry) {
+        self.entries.insert(name.to_string(), entry);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.get(name)
+    }
+
+    /// Returns the entries, sorted by path.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.entries.iter().map(|(name, e)| (name.as_str(), e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Parses a manifest in the text format. Blank lines are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeneralError` naming the first malformed line.
+    pub fn parse(s: &str) -> Result<Manifest> {
+        let mut manifest = Manifest::new();
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let parsed = match line.split_once(' ') {
+                Some(("dir", name)) => Some((name, Entry::Dir)),
+                Some(("file", rest)) => {
+                    let mut fields = rest.splitn(3, ' ');
+                    match (fields.next(), fields.next(), fields.next()) {
+                        (Some(hash), Some(size), Some(name)) => {
+                            match (hash.parse(), size.parse()) {
+                                (Ok(hash), Ok(size)) => Some((name, Entry::File { hash, size })),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            match parsed {
+                Some((name, entry)) if !name.is_empty() => manifest.insert(name, entry),
+                _ => {
+                    return GeneralSnafu {
+                        message: format!("invalid manifest line {}: {:?}", i + 1, line),
+                    }
+                    .fail()
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Formats the manifest as text, one line per entry.
+    pub fn format(&self) -> String {
+        let mut s = String::new();
+        for (name, entry) in &self.entries {
+            match entry {
+                Entry::File { hash, size } => {
+                    s.push_str(&format!("file {} {} {}\n", hash, size, name))
+                }
+                Entry::Dir => s.push_str(&format!("dir {}\n", name)),
+            }
+        }
+        s
+    }
+
+    /// Reads a manifest from the file at `p`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid
+    /// manifest.
+    pub fn load(fs: &dyn XfsReadOnly, p: &Path) -> Result<Manifest> {
+        let mut data = vec![];
+        fs.reader(p)?
+            .read_to_end(&mut data)
+            .map_err(|e| XfsError::IoError {
+                path: p.to_path_buf(),
+                source: e,
+            })?;
+        let s = String::from_utf8(data).map_err(|_| XfsError::InvalidUtf8 {
+            path: p.to_path_buf(),
+        })?;
+        Manifest::parse(&s)
+    }
+
+    /// Writes the manifest to the file at `p`, replacing it if it exists.
+    pub fn save(&self, fs: &mut dyn Xfs, p: &Path) -> Result<()> {
+        let mut w = fs.writer(p)?;
+        w.write_all(self.format().as_bytes())
+            .and_then(|_| w.flush())
+            .map_err(|e| XfsError::IoError {
+                path: p.to_path_buf(),
+                source: e,
+            })
+    }
+
+    /// Returns the differences between this manifest and `actual`, sorted by
+    /// path.
+    pub fn diff(&self, actual: &Manifest) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+        for (name, entry) in &self.entries {
+            match actual.entries.get(name) {
+                None => mismatches.push(Mismatch::Missing { name: name.clone() }),
+                Some(e) if e != entry => mismatches.push(Mismatch::Changed { name: name.clone() }),
+                Some(_) => {}
+            }
+        }
+        for name in actual.entries.keys() {
+            if !self.entries.contains_key(name) {
+                mismatches.push(Mismatch::Unexpected { name: name.clone() });
+            }
+        }
+        mismatches.sort_by(|a, b| mismatch_name(a).cmp(mismatch_name(b)));
+        mismatches
+    }
+
+    /// Checks the tree below `root` in `fs` against this manifest, returning
+    /// the differences sorted by path. An empty result means the tree
+    /// matches exactly.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree cannot be read.
+    pub fn verify(&self, fs: &dyn XfsReadOnly, root: &Path) -> Result<Vec<Mismatch>> {
+        Ok(self.diff(&Manifest::from_fs(fs, root)?))
+    }
+}
+
+fn mismatch_name(m: &Mismatch) -> &str {
+    match m {
+        Mismatch::Missing { name } | Mismatch::Unexpected { name } | Mismatch::Changed { name } => {
+            name
+        }
+    }
+}
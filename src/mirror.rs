@@ -0,0 +1,265 @@
+//! One-way synchronization of a directory tree between filesystems.
+//!
+//! `sync` makes the tree below a destination directory match the tree below
+//! a source directory, like `rsync -r`. Files that already match are left
+//! untouched, so syncing a build into a served directory only touches what
+//! changed. The source and destination can be any filesystems, such as a
+//! `MockFS` holding a build and an `OsFs` holding the published site.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::Path;
+//! use inscenerator_xfs::mirror::{sync, SyncOptions};
+//! use inscenerator_xfs::mockfs::MockFS;
+//!
+//! let mut build = MockFS::new();
+//! build.add_file(Path::new("/index.html"), "<html>").unwrap();
+//! let mut public = MockFS::new();
+//!
+//! let options = SyncOptions::default();
+//! let report = sync(&build, Path::new("/"), &mut public, Path::new("/public"), &options).unwrap();
+//! assert_eq!(report.created, vec![Path::new("/public"), Path::new("/public/index.html")]);
+//!
+//! let report = sync(&build, Path::new("/"), &mut public, Path::new("/public"), &options).unwrap();
+//! assert!(report.is_unchanged());
+//! ```
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::copy::is_link;
+use crate::hash::hash_file;
+use crate::{NotADirectorySnafu, Result, Xfs, XfsError, XfsMetadata, XfsReadOnly};
+
+/// How `sync` decides whether a destination file is up to date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    /// Files with the same size and modification time are up to date. Files
+    /// are compared by contents if either filesystem does not record
+    /// modification times.
+    SizeAndModified,
+    /// Files with the same size and SHA-256 hash are up to date.
+    Contents,
+}
+
+/// Options for `sync`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncOptions {
+    pub compare: Compare,
+    /// Whether to delete destination entries that are not in the source.
+    pub delete: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions {
+            compare: Compare::SizeAndModified,
+            delete: false,
+        }
+    }
+}
+
+/// What `sync` did, with destination paths in the order they were visited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Files and directories that were created.
+    pub created: Vec<PathBuf>,
+    /// Files whose contents were replaced.
+    pub updated: Vec<PathBuf>,
+    /// Files and directories that were deleted, either because they are not
+    /// in the source or because the source has a different type of entry.
+    pub deleted: Vec<PathBuf>,
+    /// The number of files that were already up to date.
+    pub unchanged: usize,
+    /// The number of bytes copied.
+    pub bytes_copied: u64,
+}
+
+impl SyncReport {
+    /// Returns true if the destination already matched the source.
+    pub fn is_unchanged(&self) -> bool {
+        self.created.is_empty() && self.updated.is_empty() && self.deleted.is_empty()
+    }
+}
+
+/// Makes the tree below `dst_root` in `dst` match the tree below `src_root`
+/// in `src`, creating `dst_root` if needed.
+///
+/// New and changed files are copied, and copied files are given the source's
+/// modification time. Destination entries that are not in the source are
+/// kept unless `options.delete` is set.
+///
+/// Symbolic links in the source are synced as the files they point to, or
+/// as empty directories, so that links back up the tree cannot make the sync
+/// loop.
+///
+/// # Errors
+///
+/// Returns an error if `src_root` or `dst_root` is not a directory, or if
+/// reading from the source or writing to the destination fails. Changes made
+/// before the failure are kept.
+pub fn sync(
+    src: &dyn XfsReadOnly,
+    src_root: &Path,
+    dst: &mut dyn Xfs,
+    dst_root: &Path,
+    options: &SyncOptions,
+) -> Result<SyncReport> {
+    if !src.metadata(src_root)?.is_dir() {
+        return NotADirectorySnafu { path: src_root }.fail();
+    }
+    let mut report = SyncReport::default();
+    match dst.metadata(dst_root) {
+        Ok(md) if md.is_dir() => {}
+        Ok(_) => return NotADirectorySnafu { path: dst_root }.fail(),
+        Err(XfsError::NotFound { .. }) => {
+            dst.create_dir_all(dst_root)?;
+            report.created.push(dst_root.to_path_buf());
+        }
+        Err(e) => return Err(e),
+    }
+    sync_dir(src, src_root, dst, dst_root, options, true, &mut report)?;
+    Ok(report)
+}
+
+/// Lists a directory keyed by entry name, so that both sides are visited in
+/// the same, sorted order.
+fn entries<F: XfsReadOnly + ?Sized>(fs: &F, dir: &Path) -> Result<BTreeMap<OsString, PathBuf>> {
+    let mut entries = BTreeMap::new();
+    for de in fs.read_dir(dir)? {
+        let p = de?.path();
+        if let Some(name) = p.file_name() {
+            entries.insert(name.to_os_string(), p);
+        }
+    }
+    Ok(entries)
+}
+
+fn remove(dst: &mut dyn Xfs, p: &Path, is_dir: bool, report: &mut SyncReport) -> Result<()> {
+    if is_dir {
+        dst.remove_dir_all(p)?;
+    } else {
+        dst.remove_file(p)?;
+    }
+    report.deleted.push(p.to_path_buf());
+    Ok(())
+}
+
+fn sync_dir(
+    src: &dyn XfsReadOnly,
+    src_dir: &Path,
+    dst: &mut dyn Xfs,
+    dst_dir: &Path,
+    options: &SyncOptions,
+    descend: bool,
+    report: &mut SyncReport,
+) -> Result<()> {
+    // A directory that is not descended into is synced as an empty one.
+    let src_entries = if descend {
+        entries(src, src_dir)?
+    } else {
+        BTreeMap::new()
+    };
+    let canonical_src_dir = src.canonical_path(src_dir);
+    for (name, src_path) in &src_entries {
+        let dst_path = dst_dir.join(name);
+        let src_md = src.metadata(src_path)?;
+        let dst_md = match dst.metadata(&dst_path) {
+            Ok(md) => Some(md),
+            Err(XfsError::NotFound { .. }) => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(md) = &dst_md {
+            if md.is_dir() != src_md.is_dir() {
+                remove(dst, &dst_path, md.is_dir(), report)?;
+            }
+        }
+        let dst_md = dst_md.filter(|md| md.is_dir() == src_md.is_dir());
+        if src_md.is_dir() {
+            if dst_md.is_none() {
+                dst.create_dir(&dst_path)?;
+                report.created.push(dst_path.clone());
+            }
+            let descend = !is_link(src, &canonical_src_dir, src_path);
+            sync_dir(src, src_path, dst, &dst_path, options, descend, report)?;
+            continue;
+        }
+        match dst_md {
+            Some(dst_md) => {
+                if up_to_date(
+                    src,
+                    src_path,
+                    src_md.as_ref(),
+                    &*dst,
+                    &dst_path,
+                    dst_md.as_ref(),
+                    options,
+                )? {
+                    report.unchanged += 1;
+                    continue;
+                }
+                copy_file(src, src_path, src_md.as_ref(), dst, &dst_path, report)?;
+                report.updated.push(dst_path);
+            }
+            None => {
+                copy_file(src, src_path, src_md.as_ref(), dst, &dst_path, report)?;
+                report.created.push(dst_path);
+            }
+        }
+    }
+    if options.delete {
+        for (name, dst_path) in entries(dst, dst_dir)? {
+            if !src_entries.contains_key(&name) {
+                let is_dir = dst.is_dir(&dst_path);
+                remove(dst, &dst_path, is_dir, report)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn up_to_date(
+    src: &dyn XfsReadOnly,
+    src_path: &Path,
+    src_md: &dyn XfsMetadata,
+    dst: &dyn Xfs,
+    dst_path: &Path,
+    dst_md: &dyn XfsMetadata,
+    options: &SyncOptions,
+) -> Result<bool> {
+    if src_md.size() != dst_md.size() {
+        return Ok(false);
+    }
+    if options.compare == Compare::SizeAndModified {
+        if let (Some(s), Some(d)) = (src_md.modified(), dst_md.modified()) {
+            return Ok(s == d);
+        }
+    }
+    Ok(hash_file(src, src_path)? == hash_file(dst, dst_path)?)
+}
+
+fn copy_file(
+    src: &dyn XfsReadOnly,
+    src_path: &Path,
+    src_md: &dyn XfsMetadata,
+    dst: &mut dyn Xfs,
+    dst_path: &Path,
+    report: &mut SyncReport,
+) -> Result<()> {
+    let mut r = src.reader(src_path)?;
+    {
+        let mut w = dst.writer(dst_path)?;
+        report.bytes_copied += std::io::copy(&mut r, &mut w)
+            .and_then(|n| w.flush().map(|_| n))
+            .map_err(|e| XfsError::IoError {
+                path: dst_path.to_path_buf(),
+                source: e,
+            })?;
+    }
+    if let Some(modified) = src_md.modified() {
+        dst.set_times(dst_path, None, Some(modified))?;
+    }
+    Ok(())
+}
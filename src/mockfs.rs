@@ -2,21 +2,181 @@ use std::collections::BTreeMap;
 use std::ffi::{OsStr, OsString};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
 
+use crate::clock::{Clock, SystemClock};
+
+mod durability;
+mod handles;
+#[cfg(feature = "serde")]
+mod serde_support;
 use crate::{
-    AlreadyExistsSnafu, GeneralSnafu, NotADirectorySnafu, NotAFileSnafu, Result, Xfs, XfsDirEntry,
-    XfsError, XfsMetadata, XfsReadDir, XfsReadOnly,
+    AlreadyExistsSnafu, DirectoryNotEmptySnafu, GeneralSnafu, NotADirectorySnafu, NotAFileSnafu,
+    PermissionDeniedSnafu, ReadOnlyFilesystemSnafu, Result, Xfs, XfsDirEntry, XfsError,
+    XfsMetadata, XfsReadDir, XfsReadOnly, XfsWrite,
 };
+pub use durability::CrashMode;
+use durability::DurableState;
+use handles::{HandleGuard, HandleTable};
+
+/// The user id that owns new entries and performs operations by default.
+pub const DEFAULT_UID: u32 = 1000;
+
+/// The group id that owns new entries and performs operations by default.
+pub const DEFAULT_GID: u32 = 1000;
+
+/// The shared contents of a file.
+type Contents = Arc<RwLock<Vec<u8>>>;
+
+/// Identifies a node by the address of one of its shared parts, such as a
+/// file's contents or a directory's entries.
+fn node_key<T>(a: &Arc<T>) -> usize {
+    Arc::as_ptr(a) as *const () as usize
+}
+
+/// The user on whose behalf a `MockFS` performs operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MockUser {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Default for MockUser {
+    fn default() -> Self {
+        MockUser {
+            uid: DEFAULT_UID,
+            gid: DEFAULT_GID,
+        }
+    }
+}
+
+/// Unix-style mode bits and ownership of a `MockFS` entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MockPermissions {
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl MockPermissions {
+    pub const READ: u32 = 0o4;
+    pub const WRITE: u32 = 0o2;
+    pub const EXECUTE: u32 = 0o1;
+
+    /// Permissions given to newly created files (`0o644`).
+    pub fn file_default() -> MockPermissions {
+        MockPermissions {
+            mode: 0o644,
+            uid: DEFAULT_UID,
+            gid: DEFAULT_GID,
+        }
+    }
+
+    /// Permissions given to newly created directories (`0o755`).
+    pub fn dir_default() -> MockPermissions {
+        MockPermissions {
+            mode: 0o755,
+            uid: DEFAULT_UID,
+            gid: DEFAULT_GID,
+        }
+    }
+
+    /// Checks whether `user` is granted all of the `access` bits, a combination
+    /// of `READ`, `WRITE` and `EXECUTE`.
+    ///
+    /// The owner bits apply if the user owns the entry, otherwise the group bits
+    /// apply if the user is in the entry's group, otherwise the other bits apply.
+    pub fn allows(&self, user: &MockUser, access: u32) -> bool {
+        let shift = if user.uid == self.uid {
+            6
+        } else if user.gid == self.gid {
+            3
+        } else {
+            0
+        };
+        (self.mode >> shift) & access == access
+    }
+}
+
+/// The timestamps of a `MockFS` entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MockTimes {
+    pub created: SystemTime,
+    pub modified: SystemTime,
+    pub accessed: SystemTime,
+}
+
+impl MockTimes {
+    /// Timestamps with every field set to `t`.
+    pub fn at(t: SystemTime) -> MockTimes {
+        MockTimes {
+            created: t,
+            modified: t,
+            accessed: t,
+        }
+    }
+}
+
+impl Default for MockTimes {
+    fn default() -> Self {
+        MockTimes::at(SystemTime::UNIX_EPOCH)
+    }
+}
+
+/// Conversion into the raw contents of a `MockFS` file, used by the `mockfs!`
+/// macro.
+pub trait IntoMockContents {
+    fn into_mock_contents(self) -> Vec<u8>;
+}
+
+impl IntoMockContents for &str {
+    fn into_mock_contents(self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl IntoMockContents for String {
+    fn into_mock_contents(self) -> Vec<u8> {
+        self.into_bytes()
+    }
+}
+
+impl IntoMockContents for &[u8] {
+    fn into_mock_contents(self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl<const N: usize> IntoMockContents for &[u8; N] {
+    fn into_mock_contents(self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl IntoMockContents for Vec<u8> {
+    fn into_mock_contents(self) -> Vec<u8> {
+        self
+    }
+}
 
 pub struct MockWriter {
-    data: Arc<RwLock<Vec<u8>>>,
+    file: MockFSFileEntry,
+    clock: Arc<dyn Clock>,
+    frozen: Arc<AtomicBool>,
+    durability: Arc<RwLock<Option<DurableState>>>,
+    _handle: HandleGuard,
 }
 
 impl Write for MockWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let mut data = self.data.write().unwrap();
+        if self.frozen.load(Ordering::SeqCst) {
+            return Err(std::io::ErrorKind::ReadOnlyFilesystem.into());
+        }
+        let mut data = self.file.contents.write().unwrap();
         data.extend_from_slice(buf);
+        self.file.times.write().unwrap().modified = self.clock.now();
         Ok(buf.len())
     }
 
@@ -25,9 +185,23 @@ impl Write for MockWriter {
     }
 }
 
+impl XfsWrite for MockWriter {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        if let Some(state) = self.durability.write().unwrap().as_mut() {
+            state.sync_file(&self.file);
+        }
+        Ok(())
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        self.sync_all()
+    }
+}
+
 pub struct MockReader {
     index: usize,
     data: Arc<RwLock<Vec<u8>>>,
+    _handle: HandleGuard,
 }
 
 impl Read for MockReader {
@@ -43,9 +217,21 @@ impl Read for MockReader {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct MockFSDirectoryEntry {
     pub entries: Arc<RwLock<BTreeMap<OsString, MockFSEntry>>>,
+    pub permissions: Arc<RwLock<MockPermissions>>,
+    pub times: Arc<RwLock<MockTimes>>,
+}
+
+impl Default for MockFSDirectoryEntry {
+    fn default() -> Self {
+        MockFSDirectoryEntry {
+            entries: Arc::default(),
+            permissions: Arc::new(RwLock::new(MockPermissions::dir_default())),
+            times: Arc::default(),
+        }
+    }
 }
 
 impl MockFSDirectoryEntry {
@@ -85,6 +271,8 @@ impl MockFSDirectoryEntry {
 
         let file = MockFSFileEntry {
             contents: contents.clone(),
+            permissions: Arc::new(RwLock::new(MockPermissions::file_default())),
+            times: Arc::default(),
         };
         entries.insert(OsString::from(pc), MockFSEntry::File(file.clone()));
         Ok(file)
@@ -111,6 +299,8 @@ impl MockFSDirectoryEntry {
 #[derive(Debug, Clone)]
 pub struct MockFSFileEntry {
     pub contents: Arc<RwLock<Vec<u8>>>,
+    pub permissions: Arc<RwLock<MockPermissions>>,
+    pub times: Arc<RwLock<MockTimes>>,
 }
 
 #[derive(Debug, Clone)]
@@ -151,15 +341,37 @@ impl MockFSEntry {
             })
     }
 
+    pub fn permissions(&self) -> Arc<RwLock<MockPermissions>> {
+        match self {
+            MockFSEntry::Directory(d) => d.permissions.clone(),
+            MockFSEntry::File(f) => f.permissions.clone(),
+        }
+    }
+
+    pub fn times(&self) -> Arc<RwLock<MockTimes>> {
+        match self {
+            MockFSEntry::Directory(d) => d.times.clone(),
+            MockFSEntry::File(f) => f.times.clone(),
+        }
+    }
+
     fn metadata(&self) -> MockMetadata {
+        let mode = self.permissions().read().unwrap().mode;
+        let times = *self.times().read().unwrap();
         match self {
             MockFSEntry::Directory(_) => MockMetadata {
                 is_file: false,
                 is_dir: true,
+                size: 0,
+                mode,
+                times,
             },
-            MockFSEntry::File(_) => MockMetadata {
+            MockFSEntry::File(f) => MockMetadata {
                 is_file: true,
                 is_dir: false,
+                size: f.contents.read().unwrap().len() as u64,
+                mode,
+                times,
             },
         }
     }
@@ -168,15 +380,193 @@ impl MockFSEntry {
 #[derive(Debug)]
 pub struct MockFS {
     pub root: MockFSEntry,
+    user: Arc<RwLock<MockUser>>,
+    clock: Arc<dyn Clock>,
+    durability: Arc<RwLock<Option<DurableState>>>,
+    handles: Arc<RwLock<HandleTable>>,
+    frozen: Arc<AtomicBool>,
 }
 
 impl MockFS {
     pub fn new() -> MockFS {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Creates an empty filesystem that stamps entries using `clock`.
+    ///
+    /// Creation, writes and removals update modification times, opening a file
+    /// for reading updates its access time, and renames update the modification
+    /// times of both parent directories.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use std::sync::Arc;
+    /// use std::time::{Duration, SystemTime};
+    /// use inscenerator_xfs::{Xfs, XfsReadOnly};
+    /// use inscenerator_xfs::clock::ManualClock;
+    /// use inscenerator_xfs::mockfs::MockFS;
+    ///
+    /// let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+    /// let mut fs = MockFS::with_clock(Arc::new(clock.clone()));
+    /// fs.writer(Path::new("a.txt")).unwrap();
+    /// clock.advance(Duration::from_secs(1));
+    /// fs.writer(Path::new("b.txt")).unwrap();
+    ///
+    /// let a = fs.metadata(Path::new("a.txt")).unwrap().modified().unwrap();
+    /// let b = fs.metadata(Path::new("b.txt")).unwrap().modified().unwrap();
+    /// assert!(a < b);
+    /// ```
+    pub fn with_clock(clock: Arc<dyn Clock>) -> MockFS {
+        let root = MockFSDirectoryEntry::default();
+        *root.times.write().unwrap() = MockTimes::at(clock.now());
+        MockFS {
+            root: MockFSEntry::Directory(root),
+            user: Arc::default(),
+            clock,
+            durability: Arc::default(),
+            handles: Arc::default(),
+            frozen: Arc::default(),
+        }
+    }
+
+    /// Creates a new handle sharing all state with this one.
+    fn share(&self) -> MockFS {
         MockFS {
-            root: MockFSEntry::Directory(MockFSDirectoryEntry::default()),
+            root: self.root.clone(),
+            user: self.user.clone(),
+            clock: self.clock.clone(),
+            durability: self.durability.clone(),
+            handles: self.handles.clone(),
+            frozen: self.frozen.clone(),
+        }
+    }
+
+    /// Returns the clock used to stamp entries.
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    /// Returns the user on whose behalf operations are performed.
+    pub fn user(&self) -> MockUser {
+        *self.user.read().unwrap()
+    }
+
+    /// Changes the user on whose behalf operations are performed.
+    ///
+    /// Permission checks use this user, and newly created entries are owned by
+    /// it. The change is shared by all clones of this filesystem.
+    pub fn set_user(&mut self, uid: u32, gid: u32) {
+        *self.user.write().unwrap() = MockUser { uid, gid };
+    }
+
+    /// Makes every mutating operation, including writes through writers that
+    /// are already open, fail with `ReadOnlyFilesystem` until `unfreeze` is
+    /// called.
+    ///
+    /// The switch is shared by all clones of this filesystem, so it also
+    /// locks down handles that have been passed to the code under test.
+    /// Setup helpers such as `add_file` are not affected.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use inscenerator_xfs::{Xfs, XfsError};
+    /// use inscenerator_xfs::mockfs::MockFS;
+    ///
+    /// let mut fs = MockFS::new();
+    /// let mut handle = fs.unsafe_clone_mut();
+    /// fs.freeze();
+    /// assert!(matches!(
+    ///     handle.create_dir(Path::new("out")),
+    ///     Err(XfsError::ReadOnlyFilesystem { .. })
+    /// ));
+    /// ```
+    pub fn freeze(&mut self) {
+        self.frozen.store(true, Ordering::SeqCst);
+    }
+
+    /// Allows mutating operations again after `freeze`.
+    pub fn unfreeze(&mut self) {
+        self.frozen.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns true if the filesystem has been frozen with `freeze`.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::SeqCst)
+    }
+
+    /// Fails with `ReadOnlyFilesystem` if the filesystem is frozen.
+    fn check_not_frozen(&self, p: &Path) -> Result<()> {
+        if self.is_frozen() {
+            return ReadOnlyFilesystemSnafu { path: p }.fail();
+        }
+        Ok(())
+    }
+
+    /// Changes the owner of an entry, without any permission checks.
+    pub fn set_owner(&mut self, p: &Path, uid: u32, gid: u32) -> Result<()> {
+        let permissions = self.resolve_path(p)?.permissions();
+        let mut permissions = permissions.write().unwrap();
+        permissions.uid = uid;
+        permissions.gid = gid;
+        Ok(())
+    }
+
+    /// Fails with `PermissionDenied` unless the current user is granted the
+    /// `access` bits by `permissions`.
+    fn check_access(
+        &self,
+        permissions: &Arc<RwLock<MockPermissions>>,
+        access: u32,
+        p: &Path,
+    ) -> Result<()> {
+        if permissions.read().unwrap().allows(&self.user(), access) {
+            Ok(())
+        } else {
+            PermissionDeniedSnafu {
+                path: p.to_path_buf(),
+            }
+            .fail()
         }
     }
 
+    /// Gives a newly created entry the current user as owner and the current
+    /// time for all its timestamps.
+    fn adopt(&self, entry: &MockFSEntry) {
+        let user = self.user();
+        let permissions = entry.permissions();
+        let mut permissions = permissions.write().unwrap();
+        permissions.uid = user.uid;
+        permissions.gid = user.gid;
+        *entry.times().write().unwrap() = MockTimes::at(self.clock.now());
+    }
+
+    /// Records a modification of an entry, such as a change to a directory's
+    /// entries.
+    fn touch_modified(&self, times: &Arc<RwLock<MockTimes>>) {
+        times.write().unwrap().modified = self.clock.now();
+    }
+
+    /// Returns the child directory `pc` of `dir`, creating it if it is missing.
+    fn get_or_create_dir(
+        &self,
+        dir: &MockFSDirectoryEntry,
+        pc: &OsStr,
+    ) -> Result<MockFSDirectoryEntry> {
+        if let Some(entry) = dir.entries.read().unwrap().get(pc) {
+            return entry.as_dir().map_err(|_| XfsError::NotADirectory {
+                path: PathBuf::from(pc),
+            });
+        }
+        let new_dir = dir.create_dir(pc)?;
+        self.adopt(&MockFSEntry::Directory(new_dir.clone()));
+        self.touch_modified(&dir.times);
+        Ok(new_dir)
+    }
+
     fn normalize_path(p: &Path) -> Result<Vec<&OsStr>> {
         let mut result = vec![];
         for pc in p.components() {
@@ -210,11 +600,13 @@ impl MockFS {
         })?;
         let mut current_dir = dir;
         for pc in &p_comp[..p_comp.len() - 1] {
-            current_dir = current_dir.get_or_create_dir(pc)?;
+            current_dir = self.get_or_create_dir(&current_dir, pc)?;
         }
         let pc = p_comp[p_comp.len() - 1];
         let contents = Arc::new(RwLock::new(contents));
-        current_dir.create_file(pc, contents)?;
+        let file = current_dir.create_file(pc, contents)?;
+        self.adopt(&MockFSEntry::File(file));
+        self.touch_modified(&current_dir.times);
         Ok(())
     }
 
@@ -244,13 +636,63 @@ impl MockFS {
     pub fn resolve_path(&self, p: &Path) -> Result<MockFSEntry> {
         let mut result = self.root.clone();
         for pc in Self::normalize_path(p)? {
-            result = result.child(pc).map_err(|_| XfsError::NotFound {
+            let dir = result.as_dir().map_err(|_| XfsError::NotADirectory {
+                path: p.to_path_buf(),
+            })?;
+            let child = dir.entries.read().unwrap().get(pc).cloned();
+            result = child.ok_or_else(|| XfsError::NotFound {
                 path: p.to_path_buf(),
             })?;
         }
         Ok(result)
     }
 
+    /// Resolves `p` like `resolve_path`, but fails with `PermissionDenied`
+    /// if a directory on the way cannot be searched by the current user.
+    fn lookup(&self, p: &Path) -> Result<MockFSEntry> {
+        let mut result = self.root.clone();
+        for pc in Self::normalize_path(p)? {
+            let dir = result.as_dir().map_err(|_| XfsError::NotADirectory {
+                path: p.to_path_buf(),
+            })?;
+            self.check_access(&dir.permissions, MockPermissions::EXECUTE, p)?;
+            let child = dir.entries.read().unwrap().get(pc).cloned();
+            result = child.ok_or_else(|| XfsError::NotFound {
+                path: p.to_path_buf(),
+            })?;
+        }
+        Ok(result)
+    }
+
+    /// Looks up the parent directory `pp` of `p`, which must be searchable
+    /// to find or change the entries in it.
+    fn lookup_parent(&self, pp: &Path, p: &Path) -> Result<MockFSDirectoryEntry> {
+        let dir = self
+            .lookup(pp)?
+            .as_dir()
+            .map_err(|_| XfsError::NotADirectory {
+                path: pp.to_path_buf(),
+            })?;
+        self.check_access(&dir.permissions, MockPermissions::EXECUTE, p)?;
+        Ok(dir)
+    }
+
+    /// Checks that every directory in the tree below `dir` can be listed and
+    /// have its entries removed, as `remove_dir_all` must do.
+    fn check_removable(&self, dir: &MockFSDirectoryEntry, p: &Path) -> Result<()> {
+        self.check_access(
+            &dir.permissions,
+            MockPermissions::READ | MockPermissions::WRITE | MockPermissions::EXECUTE,
+            p,
+        )?;
+        for (name, entry) in dir.entries.read().unwrap().iter() {
+            if let MockFSEntry::Directory(child) = entry {
+                self.check_removable(child, &p.join(name))?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn tree(&self) -> String {
         Self::tree_(&OsString::from("/"), &self.root, "")
     }
@@ -276,7 +718,10 @@ impl MockFS {
         }
     }
 
-    pub fn copy_recursive(
+    /// Copies a file, or a directory and everything below it, from
+    /// `other_path` in another filesystem to `self_path`. A file copied onto
+    /// an existing directory is copied into it.
+    pub fn copy_from(
         &mut self,
         other_fs: &dyn XfsReadOnly,
         other_path: &Path,
@@ -309,8 +754,12 @@ impl MockFS {
             if let Ok(self_md) = self_md {
                 if !self_md.is_dir() {
                     return GeneralSnafu {
-                        message: format!("mockfs::copy_recursive creating directory {} but already exists as file", self_path.display()),
-                    }.fail();
+                        message: format!(
+                            "mockfs::copy_from creating directory {} but already exists as file",
+                            self_path.display()
+                        ),
+                    }
+                    .fail();
                 }
             } else {
                 // If it doesn't exist we need to create it
@@ -320,7 +769,7 @@ impl MockFS {
             for de in other_fs.read_dir(other_path)? {
                 let de = de?;
                 let self_child_path = self_path.join(de.path().file_name().unwrap());
-                self.copy_recursive(other_fs, &de.path(), &self_child_path)?;
+                self.copy_from(other_fs, &de.path(), &self_child_path)?;
             }
         }
 
@@ -353,6 +802,9 @@ impl XfsDirEntry for MockDirEntry {
 struct MockMetadata {
     is_file: bool,
     is_dir: bool,
+    size: u64,
+    mode: u32,
+    times: MockTimes,
 }
 
 impl XfsMetadata for MockMetadata {
@@ -363,25 +815,49 @@ impl XfsMetadata for MockMetadata {
     fn is_file(&self) -> bool {
         self.is_file
     }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        Some(self.times.modified)
+    }
+
+    fn accessed(&self) -> Option<SystemTime> {
+        Some(self.times.accessed)
+    }
+
+    fn created(&self) -> Option<SystemTime> {
+        Some(self.times.created)
+    }
 }
 
 impl XfsReadOnly for MockFS {
     fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
-        Box::new(MockFS {
-            root: self.root.clone(),
-        })
+        Box::new(self.share())
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        // Relative paths are relative to the root.
+        match Self::normalize_path(p) {
+            Ok(components) => Path::new("/").join(components.iter().collect::<PathBuf>()),
+            Err(_) => p.to_path_buf(),
+        }
     }
 
     fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
         let dir = self
-            .resolve_path(p)
-            .map_err(|_| XfsError::NotFound {
-                path: p.to_path_buf(),
-            })?
+            .lookup(p)?
             .as_dir()
             .map_err(|_| XfsError::NotADirectory {
                 path: p.to_path_buf(),
             })?;
+        self.check_access(&dir.permissions, MockPermissions::READ, p)?;
 
         let entries = dir.entries.read().unwrap();
         let entries: Vec<Result<Box<dyn XfsDirEntry>>> = entries
@@ -399,33 +875,28 @@ impl XfsReadOnly for MockFS {
     }
 
     fn reader(&self, p: &Path) -> Result<Box<dyn std::io::Read>> {
-        let f = self
-            .resolve_path(p)
-            .map_err(|_| XfsError::NotFound {
-                path: p.to_path_buf(),
-            })?
-            .as_file()
-            .map_err(|_| XfsError::NotAFile {
-                path: p.to_path_buf(),
-            })?;
+        let f = self.lookup(p)?.as_file().map_err(|_| XfsError::NotAFile {
+            path: p.to_path_buf(),
+        })?;
+        self.check_access(&f.permissions, MockPermissions::READ, p)?;
+        let handle = self.open_handle(&f.contents, p)?;
+        f.times.write().unwrap().accessed = self.clock.now();
 
         let r = MockReader {
             index: 0,
             data: f.contents.clone(),
+            _handle: handle,
         };
         Ok(Box::new(r))
     }
 
     fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
-        let file = self
-            .resolve_path(p)
-            .map_err(|_| XfsError::NotFound {
-                path: p.to_path_buf(),
-            })?
-            .as_file()
-            .map_err(|_| XfsError::NotAFile {
-                path: p.to_path_buf(),
-            })?;
+        let file = self.lookup(p)?.as_file().map_err(|_| XfsError::NotAFile {
+            path: p.to_path_buf(),
+        })?;
+        self.check_access(&file.permissions, MockPermissions::READ, p)?;
+        let _handle = self.open_handle(&file.contents, p)?;
+        file.times.write().unwrap().accessed = self.clock.now();
         let data = file.contents.read().unwrap();
 
         let s = std::str::from_utf8(data.as_slice()).map_err(|_| XfsError::InvalidUtf8 {
@@ -436,21 +907,18 @@ impl XfsReadOnly for MockFS {
     }
 
     fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
-        let entry = self.resolve_path(p).map_err(|_| XfsError::NotFound {
-            path: p.to_path_buf(),
-        })?;
+        let entry = self.lookup(p)?;
         Ok(Box::new(entry.metadata()))
     }
 }
 
 impl Xfs for MockFS {
     fn unsafe_clone_mut(&mut self) -> Box<dyn Xfs> {
-        Box::new(MockFS {
-            root: self.root.clone(),
-        })
+        Box::new(self.share())
     }
 
-    fn writer(&mut self, p: &Path) -> Result<Box<dyn std::io::Write>> {
+    fn writer(&mut self, p: &Path) -> Result<Box<dyn XfsWrite>> {
+        self.check_not_frozen(p)?;
         let pp = if let Some(pp) = p.parent() {
             pp
         } else {
@@ -469,23 +937,22 @@ impl Xfs for MockFS {
             path: p.to_path_buf(),
         })?;
 
-        let parent_dir = self
-            .resolve_path(pp)
-            .map_err(|_| XfsError::NotFound {
-                path: pp.to_path_buf(),
-            })?
-            .as_dir()
-            .map_err(|_| XfsError::NotADirectory {
-                path: pp.to_path_buf(),
-            })?;
+        let parent_dir = self.lookup_parent(pp, p)?;
 
         let mut entries = parent_dir.entries.write().unwrap();
         if let Some(entry) = entries.get(file_name) {
             match entry {
                 MockFSEntry::File(f) => {
+                    self.check_access(&f.permissions, MockPermissions::WRITE, p)?;
+                    let handle = self.open_handle(&f.contents, p)?;
                     f.contents.write().unwrap().clear();
+                    self.touch_modified(&f.times);
                     let w = MockWriter {
-                        data: f.contents.clone(),
+                        file: f.clone(),
+                        clock: self.clock.clone(),
+                        frozen: self.frozen.clone(),
+                        durability: self.durability.clone(),
+                        _handle: handle,
                     };
                     return Ok(Box::new(w));
                 }
@@ -498,17 +965,31 @@ impl Xfs for MockFS {
             }
         }
 
+        self.check_access(&parent_dir.permissions, MockPermissions::WRITE, p)?;
         let data = Arc::new(RwLock::new(Vec::new()));
+        let handle = self.open_handle(&data, p)?;
         let file = MockFSFileEntry {
             contents: data.clone(),
+            permissions: Arc::new(RwLock::new(MockPermissions::file_default())),
+            times: Arc::default(),
         };
-        entries.insert(file_name.to_os_string(), MockFSEntry::File(file));
+        let w = MockWriter {
+            file: file.clone(),
+            clock: self.clock.clone(),
+            frozen: self.frozen.clone(),
+            durability: self.durability.clone(),
+            _handle: handle,
+        };
+        let file = MockFSEntry::File(file);
+        self.adopt(&file);
+        entries.insert(file_name.to_os_string(), file);
+        self.touch_modified(&parent_dir.times);
 
-        let w = MockWriter { data };
         Ok(Box::new(w))
     }
 
     fn create_dir(&mut self, p: &Path) -> Result<()> {
+        self.check_not_frozen(p)?;
         // The root always exists, so we can't create it
         if p.as_os_str().is_empty() || p == Path::new("/") {
             return AlreadyExistsSnafu {
@@ -520,43 +1001,44 @@ impl Xfs for MockFS {
         let pp = p.parent().ok_or_else(|| XfsError::NotFound {
             path: p.to_path_buf(),
         })?;
-        let parent_dir = self
-            .resolve_path(pp)
-            .map_err(|_| XfsError::NotFound {
-                path: pp.to_path_buf(),
-            })?
-            .as_dir()
-            .map_err(|_| XfsError::NotADirectory {
-                path: pp.to_path_buf(),
-            })?;
-        parent_dir.create_dir(p.file_name().unwrap())?;
+        let parent_dir = self.lookup_parent(pp, p)?;
+        self.check_access(&parent_dir.permissions, MockPermissions::WRITE, p)?;
+        let new_dir = parent_dir.create_dir(p.file_name().unwrap())?;
+        self.adopt(&MockFSEntry::Directory(new_dir));
+        self.touch_modified(&parent_dir.times);
         Ok(())
     }
 
     fn create_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.check_not_frozen(p)?;
         let p_comp: Vec<&OsStr> = Self::normalize_path(p)?;
         let mut root = self.root.as_dir().map_err(|_| XfsError::NotADirectory {
             path: PathBuf::from("/"),
         })?;
-        for pc in p_comp {
-            root = root.get_or_create_dir(pc)?;
+        let last = p_comp.len().saturating_sub(1);
+        for (i, pc) in p_comp.into_iter().enumerate() {
+            self.check_access(&root.permissions, MockPermissions::EXECUTE, p)?;
+            match root.entries.read().unwrap().get(pc) {
+                None => self.check_access(&root.permissions, MockPermissions::WRITE, p)?,
+                Some(MockFSEntry::File(_)) if i == last => {
+                    return AlreadyExistsSnafu {
+                        path: p.to_path_buf(),
+                    }
+                    .fail();
+                }
+                Some(_) => {}
+            }
+            root = self.get_or_create_dir(&root, pc)?;
         }
         Ok(())
     }
 
     fn remove_file(&mut self, p: &Path) -> Result<()> {
+        self.check_not_frozen(p)?;
         let pp = p.parent().ok_or_else(|| XfsError::NotFound {
             path: p.to_path_buf(),
         })?;
-        let parent_dir = self
-            .resolve_path(pp)
-            .map_err(|_| XfsError::NotFound {
-                path: pp.to_path_buf(),
-            })?
-            .as_dir()
-            .map_err(|_| XfsError::NotADirectory {
-                path: pp.to_path_buf(),
-            })?;
+        let parent_dir = self.lookup_parent(pp, p)?;
 
         let file_name = p.file_name().ok_or_else(|| XfsError::NotFound {
             path: p.to_path_buf(),
@@ -564,8 +1046,11 @@ impl Xfs for MockFS {
 
         let mut parent_entries = parent_dir.entries.write().unwrap();
         match parent_entries.get(file_name) {
-            Some(MockFSEntry::File(_)) => {
+            Some(entry @ MockFSEntry::File(_)) => {
+                self.check_access(&parent_dir.permissions, MockPermissions::WRITE, p)?;
+                self.check_not_in_use(entry, p)?;
                 parent_entries.remove(file_name);
+                self.touch_modified(&parent_dir.times);
                 Ok(())
             }
             Some(MockFSEntry::Directory(_)) => NotAFileSnafu {
@@ -579,18 +1064,11 @@ impl Xfs for MockFS {
     }
 
     fn remove_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.check_not_frozen(p)?;
         let pp = p.parent().ok_or_else(|| XfsError::NotFound {
             path: p.to_path_buf(),
         })?;
-        let parent_dir = self
-            .resolve_path(pp)
-            .map_err(|_| XfsError::NotFound {
-                path: pp.to_path_buf(),
-            })?
-            .as_dir()
-            .map_err(|_| XfsError::NotADirectory {
-                path: pp.to_path_buf(),
-            })?;
+        let parent_dir = self.lookup_parent(pp, p)?;
 
         let name = p.file_name().ok_or_else(|| XfsError::NotFound {
             path: p.to_path_buf(),
@@ -598,8 +1076,12 @@ impl Xfs for MockFS {
 
         let mut parent_entries = parent_dir.entries.write().unwrap();
         match parent_entries.get(name) {
-            Some(MockFSEntry::Directory(_)) => {
+            Some(entry @ MockFSEntry::Directory(dir)) => {
+                self.check_access(&parent_dir.permissions, MockPermissions::WRITE, p)?;
+                self.check_removable(dir, p)?;
+                self.check_not_in_use(entry, p)?;
                 parent_entries.remove(name);
+                self.touch_modified(&parent_dir.times);
                 Ok(())
             }
             Some(MockFSEntry::File(_)) => NotADirectorySnafu {
@@ -613,9 +1095,7 @@ impl Xfs for MockFS {
     }
 
     fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
-        if from == to {
-            return Ok(());
-        }
+        self.check_not_frozen(from)?;
         let from_pp = from.parent().ok_or_else(|| XfsError::NotFound {
             path: from.to_path_buf(),
         })?;
@@ -630,17 +1110,74 @@ impl Xfs for MockFS {
             path: to.to_path_buf(),
         })?;
 
-        // 1. Ensure 'from' exists.
-        self.resolve_path(from)?;
+        // 1. Ensure both parents exist and are directories, then that 'from'
+        // exists, matching the order in which a real filesystem checks them.
+        self.lookup_parent(from_pp, from)?;
+        let to_parent = self.lookup_parent(to_pp, to)?;
+        let from_entry = self.lookup(from)?;
 
-        // 2. Ensure 'to' parent exists and is a directory.
-        self.resolve_path(to_pp)?
-            .as_dir()
-            .map_err(|_| XfsError::NotADirectory {
-                path: to_pp.to_path_buf(),
-            })?;
+        // 2. Ensure the move is valid, and can replace anything at 'to'.
+        let from_comp = Self::normalize_path(from)?;
+        let to_comp = Self::normalize_path(to)?;
+        if from_comp == to_comp {
+            return Ok(());
+        }
+        if let MockFSEntry::Directory(_) = from_entry {
+            if to_comp.starts_with(&from_comp) {
+                return GeneralSnafu {
+                    message: format!(
+                        "cannot move directory {} inside itself to {}",
+                        from.display(),
+                        to.display()
+                    ),
+                }
+                .fail();
+            }
+        }
+        if from_comp.starts_with(&to_comp) {
+            // 'to' is an ancestor of 'from', so cannot be an empty directory.
+            return DirectoryNotEmptySnafu {
+                path: to.to_path_buf(),
+            }
+            .fail();
+        }
+        let existing = self.resolve_path(to).ok();
+        if let Some(existing) = &existing {
+            match (&from_entry, existing) {
+                (MockFSEntry::File(_), MockFSEntry::Directory(_)) => {
+                    return NotAFileSnafu {
+                        path: to.to_path_buf(),
+                    }
+                    .fail();
+                }
+                (MockFSEntry::Directory(_), MockFSEntry::File(_)) => {
+                    return NotADirectorySnafu {
+                        path: to.to_path_buf(),
+                    }
+                    .fail();
+                }
+                (MockFSEntry::Directory(_), MockFSEntry::Directory(d)) if d.num_entries() > 0 => {
+                    return DirectoryNotEmptySnafu {
+                        path: to.to_path_buf(),
+                    }
+                    .fail();
+                }
+                _ => {}
+            }
+        }
 
-        // 3. Perform the move.
+        // 3. Ensure both parents are writable.
+        let from_parent = self.resolve_path(from_pp)?.as_dir()?;
+        self.check_access(&from_parent.permissions, MockPermissions::WRITE, from)?;
+        self.check_access(&to_parent.permissions, MockPermissions::WRITE, to)?;
+
+        // 4. Ensure nothing involved is open, if that is enforced.
+        self.check_not_in_use(&from_entry, from)?;
+        if let Some(existing) = &existing {
+            self.check_not_in_use(existing, to)?;
+        }
+
+        // 5. Perform the move.
         let entry = {
             let from_parent =
                 self.resolve_path(from_pp)?
@@ -656,7 +1193,66 @@ impl Xfs for MockFS {
 
         let mut to_parent_entries = to_parent.entries.write().unwrap();
         to_parent_entries.insert(to_name.to_os_string(), entry);
+        self.touch_modified(&from_parent.times);
+        self.touch_modified(&to_parent.times);
+
+        Ok(())
+    }
+
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()> {
+        self.check_not_frozen(p)?;
+        let permissions = self.lookup(p)?.permissions();
+        let mut permissions = permissions.write().unwrap();
+        if permissions.uid != self.user().uid {
+            return PermissionDeniedSnafu {
+                path: p.to_path_buf(),
+            }
+            .fail();
+        }
+        permissions.mode = mode & 0o7777;
+        Ok(())
+    }
+
+    fn set_times(
+        &mut self,
+        p: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<()> {
+        self.check_not_frozen(p)?;
+        let entry = self.lookup(p)?;
+        if entry.permissions().read().unwrap().uid != self.user().uid {
+            return PermissionDeniedSnafu {
+                path: p.to_path_buf(),
+            }
+            .fail();
+        }
+        let times = entry.times();
+        let mut times = times.write().unwrap();
+        if let Some(accessed) = accessed {
+            times.accessed = accessed;
+        }
+        if let Some(modified) = modified {
+            times.modified = modified;
+        }
+        Ok(())
+    }
 
+    fn sync_all(&mut self, p: &Path) -> Result<()> {
+        let entry = self.lookup(p)?;
+        self.check_access(&entry.permissions(), MockPermissions::READ, p)?;
+        if let Some(state) = self.durability.write().unwrap().as_mut() {
+            match &entry {
+                MockFSEntry::Directory(d) => state.sync_dir(d),
+                MockFSEntry::File(f) => state.sync_file(f),
+            }
+        }
         Ok(())
     }
+
+    fn sync_data(&mut self, p: &Path) -> Result<()> {
+        // Metadata is not tracked for durability, so this is the same as
+        // `sync_all`.
+        self.sync_all(p)
+    }
 }
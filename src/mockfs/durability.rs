@@ -0,0 +1,167 @@
+//! Durability tracking for `MockFS`, used to simulate crashes.
+//!
+//! While tracking is enabled the filesystem remembers, for every file, the
+//! contents it had when it was last synced, and for every directory, the
+//! entries it had when it was last synced. Nodes that have never been synced
+//! are durably empty. A simulated crash rebuilds the tree from that
+//! remembered state.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::sync::{Arc, RwLock};
+
+use super::{
+    node_key as key, Contents, MockFS, MockFSDirectoryEntry, MockFSEntry, MockFSFileEntry,
+};
+
+/// Which unsynced changes a simulated crash loses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashMode {
+    /// Every change that was not synced is lost: unsynced file contents are
+    /// rolled back and unsynced creations, removals and renames are undone.
+    LoseAllUnsynced,
+    /// Changes to directories survive but unsynced file contents are lost, as
+    /// on filesystems that journal metadata but not data. A file created and
+    /// renamed into place without being synced is left empty.
+    KeepDirectoryChanges,
+}
+
+type Entries = BTreeMap<OsString, MockFSEntry>;
+
+/// The durable state of every node, keyed by the address of the node's
+/// shared contents. Each record holds a reference to the node, so addresses
+/// cannot be reused while tracking is enabled.
+#[derive(Debug, Default)]
+pub(super) struct DurableState {
+    files: HashMap<usize, (Contents, Vec<u8>)>,
+    dirs: HashMap<usize, (Arc<RwLock<Entries>>, Entries)>,
+}
+
+impl DurableState {
+    /// Records the whole tree below `entry` as durable.
+    fn capture(entry: &MockFSEntry) -> DurableState {
+        let mut state = DurableState::default();
+        state.capture_entry(entry);
+        state
+    }
+
+    fn capture_entry(&mut self, entry: &MockFSEntry) {
+        match entry {
+            MockFSEntry::Directory(d) => {
+                self.sync_dir(d);
+                for child in d.entries.read().unwrap().values() {
+                    self.capture_entry(child);
+                }
+            }
+            MockFSEntry::File(f) => self.sync_file(f),
+        }
+    }
+
+    pub(super) fn sync_file(&mut self, f: &MockFSFileEntry) {
+        let contents = f.contents.read().unwrap().clone();
+        self.files
+            .insert(key(&f.contents), (f.contents.clone(), contents));
+    }
+
+    pub(super) fn sync_dir(&mut self, d: &MockFSDirectoryEntry) {
+        let entries = d.entries.read().unwrap().clone();
+        self.dirs
+            .insert(key(&d.entries), (d.entries.clone(), entries));
+    }
+
+    fn restore_dir(&self, d: &MockFSDirectoryEntry, mode: CrashMode, visited: &mut HashSet<usize>) {
+        if !visited.insert(key(&d.entries)) {
+            return;
+        }
+        let entries = match mode {
+            CrashMode::LoseAllUnsynced => self
+                .dirs
+                .get(&key(&d.entries))
+                .map(|(_, entries)| entries.clone())
+                .unwrap_or_default(),
+            CrashMode::KeepDirectoryChanges => d.entries.read().unwrap().clone(),
+        };
+        for child in entries.values() {
+            match child {
+                MockFSEntry::Directory(child) => self.restore_dir(child, mode, visited),
+                MockFSEntry::File(f) => {
+                    if visited.insert(key(&f.contents)) {
+                        *f.contents.write().unwrap() = self
+                            .files
+                            .get(&key(&f.contents))
+                            .map(|(_, contents)| contents.clone())
+                            .unwrap_or_default();
+                    }
+                }
+            }
+        }
+        *d.entries.write().unwrap() = entries;
+    }
+}
+
+impl MockFS {
+    /// Starts tracking which changes have been made durable with `sync_all`
+    /// or `sync_data`, treating the current state as durable.
+    ///
+    /// Tracking is shared by all clones of this filesystem. Enabling it again
+    /// marks the current state as durable.
+    pub fn enable_durability_tracking(&mut self) {
+        *self.durability.write().unwrap() = Some(DurableState::capture(&self.root));
+    }
+
+    /// Returns true if durability tracking is enabled.
+    pub fn is_tracking_durability(&self) -> bool {
+        self.durability.read().unwrap().is_some()
+    }
+
+    /// Simulates a power loss, rolling the filesystem back to its durable
+    /// state as with `CrashMode::LoseAllUnsynced`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use std::path::Path;
+    /// use inscenerator_xfs::{Xfs, XfsReadOnly};
+    /// use inscenerator_xfs::mockfs::MockFS;
+    ///
+    /// let mut fs = MockFS::new();
+    /// fs.add_file(Path::new("config"), "old").unwrap();
+    /// fs.enable_durability_tracking();
+    ///
+    /// // Save by writing a temporary file and renaming it over the original,
+    /// // but forget to sync the directory afterwards.
+    /// fs.writer(Path::new("config.tmp")).unwrap().write_all(b"new").unwrap();
+    /// fs.sync_all(Path::new("config.tmp")).unwrap();
+    /// fs.rename(Path::new("config.tmp"), Path::new("config")).unwrap();
+    ///
+    /// fs.simulate_crash();
+    /// assert_eq!(fs.get_str(Path::new("config")).unwrap(), "old");
+    /// assert!(!fs.exists(Path::new("config.tmp")));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if durability tracking has not been enabled.
+    pub fn simulate_crash(&mut self) {
+        self.simulate_crash_with(CrashMode::LoseAllUnsynced)
+    }
+
+    /// Simulates a power loss, losing the unsynced changes selected by `mode`.
+    ///
+    /// Afterwards the surviving state is treated as durable, so recovery code
+    /// can be run and further crashes simulated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if durability tracking has not been enabled.
+    pub fn simulate_crash_with(&mut self, mode: CrashMode) {
+        let mut durability = self.durability.write().unwrap();
+        let state = durability
+            .as_ref()
+            .expect("simulate_crash requires enable_durability_tracking");
+        let root = self.root.as_dir().expect("the root is always a directory");
+        state.restore_dir(&root, mode, &mut HashSet::new());
+        *durability = Some(DurableState::capture(&self.root));
+    }
+}
@@ -0,0 +1,182 @@
+//! Tracking of the reader and writer handles open on `MockFS` files.
+//!
+//! Every `MockReader` and `MockWriter` holds a guard that counts as one open
+//! handle on its file until it is dropped. The count follows the file rather
+//! than its path, so it survives renames.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use super::{node_key, Contents, MockFS, MockFSEntry};
+use crate::{FileInUseSnafu, Result, TooManyOpenFilesSnafu, XfsError};
+
+#[derive(Debug, Default)]
+pub(super) struct HandleTable {
+    /// Open handle counts, keyed by the address of each file's contents.
+    counts: HashMap<usize, usize>,
+    limit: Option<usize>,
+    exclusive: bool,
+}
+
+impl HandleTable {
+    fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+}
+
+fn count(counts: &HashMap<usize, usize>, contents: &Contents) -> usize {
+    counts.get(&node_key(contents)).copied().unwrap_or(0)
+}
+
+/// One open handle on a file, released when dropped.
+#[derive(Debug)]
+pub(super) struct HandleGuard {
+    table: Arc<RwLock<HandleTable>>,
+    // Keeps the file's contents alive, so its address is not reused while
+    // the handle is counted.
+    contents: Contents,
+}
+
+impl Drop for HandleGuard {
+    fn drop(&mut self) {
+        let mut table = self.table.write().unwrap();
+        let key = node_key(&self.contents);
+        if let Some(count) = table.counts.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                table.counts.remove(&key);
+            }
+        }
+    }
+}
+
+fn any_open(counts: &HashMap<usize, usize>, entry: &MockFSEntry) -> bool {
+    match entry {
+        MockFSEntry::File(f) => count(counts, &f.contents) > 0,
+        MockFSEntry::Directory(d) => d
+            .entries
+            .read()
+            .unwrap()
+            .values()
+            .any(|e| any_open(counts, e)),
+    }
+}
+
+fn collect_paths(entry: &MockFSEntry, path: PathBuf, paths: &mut HashMap<usize, PathBuf>) {
+    match entry {
+        MockFSEntry::File(f) => {
+            paths.insert(node_key(&f.contents), path);
+        }
+        MockFSEntry::Directory(d) => {
+            for (name, child) in d.entries.read().unwrap().iter() {
+                collect_paths(child, path.join(name), paths);
+            }
+        }
+    }
+}
+
+impl MockFS {
+    /// Returns a copy of the open handle counts, so that the tree can be
+    /// walked without holding the handle table's lock.
+    fn open_counts(&self) -> HashMap<usize, usize> {
+        self.handles.read().unwrap().counts.clone()
+    }
+
+    /// Opens a handle on the file with the given contents, failing with
+    /// `TooManyOpenFiles` if the open file limit has been reached.
+    pub(super) fn open_handle(&self, contents: &Contents, p: &Path) -> Result<HandleGuard> {
+        let mut table = self.handles.write().unwrap();
+        if let Some(limit) = table.limit {
+            if table.total() >= limit {
+                return TooManyOpenFilesSnafu { path: p }.fail();
+            }
+        }
+        *table.counts.entry(node_key(contents)).or_insert(0) += 1;
+        Ok(HandleGuard {
+            table: self.handles.clone(),
+            contents: contents.clone(),
+        })
+    }
+
+    /// Fails with `FileInUse` if Windows-style locking is enabled and `entry`
+    /// is, or contains, a file with open handles.
+    pub(super) fn check_not_in_use(&self, entry: &MockFSEntry, p: &Path) -> Result<()> {
+        let exclusive = self.handles.read().unwrap().exclusive;
+        if exclusive && any_open(&self.open_counts(), entry) {
+            return FileInUseSnafu { path: p }.fail();
+        }
+        Ok(())
+    }
+
+    /// Returns the number of open readers and writers on the file at `p`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path does not exist or is a directory.
+    pub fn open_handles(&self, p: &Path) -> Result<usize> {
+        let file = self
+            .resolve_path(p)?
+            .as_file()
+            .map_err(|_| XfsError::NotAFile {
+                path: p.to_path_buf(),
+            })?;
+        Ok(count(&self.open_counts(), &file.contents))
+    }
+
+    /// Returns the number of open readers and writers on all files, including
+    /// files that have since been removed.
+    pub fn total_open_handles(&self) -> usize {
+        self.handles.read().unwrap().total()
+    }
+
+    /// Limits the number of handles that may be open at once. Opening a file
+    /// beyond the limit fails with `TooManyOpenFiles`, like `EMFILE`. The
+    /// limit is shared by all clones of this filesystem.
+    pub fn set_open_file_limit(&mut self, limit: Option<usize>) {
+        self.handles.write().unwrap().limit = limit;
+    }
+
+    /// Enables or disables Windows-style locking, under which removing or
+    /// renaming a file with open handles, or a directory containing one,
+    /// fails with `FileInUse`. The setting is shared by all clones of this
+    /// filesystem.
+    pub fn set_windows_file_locking(&mut self, enabled: bool) {
+        self.handles.write().unwrap().exclusive = enabled;
+    }
+
+    /// Asserts that no readers or writers are open.
+    ///
+    /// # Panics
+    ///
+    /// Panics listing each file with open handles, and how many.
+    ///
+    /// # Example
+    ///
+    /// ```should_panic
+    /// use std::path::Path;
+    /// use inscenerator_xfs::Xfs;
+    /// use inscenerator_xfs::mockfs::MockFS;
+    ///
+    /// let mut fs = MockFS::new();
+    /// let leaked = fs.writer(Path::new("a.txt")).unwrap();
+    /// fs.assert_no_open_handles();
+    /// ```
+    pub fn assert_no_open_handles(&self) {
+        let counts = self.open_counts();
+        if counts.is_empty() {
+            return;
+        }
+        let mut paths = HashMap::new();
+        collect_paths(&self.root, PathBuf::from("/"), &mut paths);
+        let mut open: Vec<String> = counts
+            .iter()
+            .map(|(key, count)| match paths.get(key) {
+                Some(path) => format!("  {}: {}\n", path.display(), count),
+                None => format!("  (removed file): {}\n", count),
+            })
+            .collect();
+        open.sort();
+        panic!("MockFS has open handles:\n{}", open.concat());
+    }
+}
@@ -0,0 +1,235 @@
+//! Serde support for `MockFS`, enabled by the `serde` feature.
+//!
+//! A filesystem is serialized as a tree of externally tagged entries, so it
+//! can be stored both in self-describing formats such as JSON and in compact
+//! formats such as bincode. File contents are tagged `text` when they are
+//! valid UTF-8 and `bytes` otherwise, and entries whose names are not valid
+//! UTF-8 are listed separately as raw bytes, which is only supported on Unix.
+//! Metadata is always written, but every metadata field is optional when
+//! loading from a self-describing format, with missing times set to the Unix
+//! epoch so that loading is deterministic, and hand-written fixtures can be as
+//! small as:
+//!
+//! ```json
+//! {
+//!   "directory": {
+//!     "entries": {
+//!       "hello.txt": { "file": { "contents": { "text": "Hello\n" } } },
+//!       "empty": { "directory": { "entries": {} } }
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! Entry names must be single path components, so names that are empty,
+//! `.` or `..`, or that contain a separator, fail to load.
+
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Component, Path};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    MockFS, MockFSDirectoryEntry, MockFSEntry, MockFSFileEntry, MockPermissions, MockUser,
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SerEntry {
+    Directory {
+        #[serde(default)]
+        metadata: SerMetadata,
+        entries: BTreeMap<String, SerEntry>,
+        /// Entries whose names are not valid UTF-8, as raw bytes.
+        #[serde(default)]
+        non_utf8_entries: Vec<(Vec<u8>, SerEntry)>,
+    },
+    File {
+        #[serde(default)]
+        metadata: SerMetadata,
+        contents: SerContents,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SerContents {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct SerMetadata {
+    #[serde(default)]
+    mode: Option<u32>,
+    #[serde(default)]
+    uid: Option<u32>,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default)]
+    created: Option<SystemTime>,
+    #[serde(default)]
+    modified: Option<SystemTime>,
+    #[serde(default)]
+    accessed: Option<SystemTime>,
+}
+
+#[cfg(unix)]
+fn name_to_bytes(name: &OsString) -> Option<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(name.as_bytes().to_vec())
+}
+
+#[cfg(not(unix))]
+fn name_to_bytes(_name: &OsString) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(unix)]
+fn name_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn name_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    String::from_utf8(bytes).ok().map(OsString::from)
+}
+
+impl SerMetadata {
+    fn from_entry(e: &MockFSEntry) -> SerMetadata {
+        let permissions = *e.permissions().read().unwrap();
+        let times = *e.times().read().unwrap();
+        SerMetadata {
+            mode: Some(permissions.mode),
+            uid: Some(permissions.uid),
+            gid: Some(permissions.gid),
+            created: Some(times.created),
+            modified: Some(times.modified),
+            accessed: Some(times.accessed),
+        }
+    }
+
+    fn apply(&self, e: &MockFSEntry, user: &MockUser) {
+        let permissions = e.permissions();
+        let mut permissions = permissions.write().unwrap();
+        permissions.mode = self.mode.unwrap_or(permissions.mode);
+        permissions.uid = self.uid.unwrap_or(user.uid);
+        permissions.gid = self.gid.unwrap_or(user.gid);
+
+        let times = e.times();
+        let mut times = times.write().unwrap();
+        times.created = self.created.unwrap_or(SystemTime::UNIX_EPOCH);
+        times.modified = self.modified.unwrap_or(SystemTime::UNIX_EPOCH);
+        times.accessed = self.accessed.unwrap_or(SystemTime::UNIX_EPOCH);
+    }
+}
+
+fn to_ser<E: serde::ser::Error>(e: &MockFSEntry) -> Result<SerEntry, E> {
+    let metadata = SerMetadata::from_entry(e);
+    match e {
+        MockFSEntry::Directory(d) => {
+            let mut entries = BTreeMap::new();
+            let mut non_utf8_entries = Vec::new();
+            for (name, child) in d.entries.read().unwrap().iter() {
+                if let Some(name) = name.to_str() {
+                    entries.insert(name.to_string(), to_ser(child)?);
+                    continue;
+                }
+                let bytes = name_to_bytes(name).ok_or_else(|| {
+                    E::custom(format!("cannot serialize non UTF-8 file name {:?}", name))
+                })?;
+                non_utf8_entries.push((bytes, to_ser(child)?));
+            }
+            Ok(SerEntry::Directory {
+                metadata,
+                entries,
+                non_utf8_entries,
+            })
+        }
+        MockFSEntry::File(f) => {
+            let data = f.contents.read().unwrap().clone();
+            let contents = match String::from_utf8(data) {
+                Ok(s) => SerContents::Text(s),
+                Err(e) => SerContents::Bytes(e.into_bytes()),
+            };
+            Ok(SerEntry::File { metadata, contents })
+        }
+    }
+}
+
+/// Fails unless `name` is a single normal path component.
+fn check_name<E: serde::de::Error>(name: &OsStr) -> Result<(), E> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(n)), None) if n == name => Ok(()),
+        _ => Err(E::custom(format!("invalid entry name {:?}", name))),
+    }
+}
+
+fn from_ser<E: serde::de::Error>(e: SerEntry, user: &MockUser) -> Result<MockFSEntry, E> {
+    match e {
+        SerEntry::Directory {
+            metadata,
+            entries,
+            non_utf8_entries,
+        } => {
+            let dir = MockFSDirectoryEntry::default();
+            {
+                let mut dir_entries = dir.entries.write().unwrap();
+                for (name, child) in entries {
+                    let name = OsString::from(name);
+                    check_name(&name)?;
+                    dir_entries.insert(name, from_ser(child, user)?);
+                }
+                for (bytes, child) in non_utf8_entries {
+                    let name = name_from_bytes(bytes).ok_or_else(|| {
+                        E::custom("non UTF-8 file names are only supported on Unix")
+                    })?;
+                    check_name(&name)?;
+                    dir_entries.insert(name, from_ser(child, user)?);
+                }
+            }
+            let entry = MockFSEntry::Directory(dir);
+            metadata.apply(&entry, user);
+            Ok(entry)
+        }
+        SerEntry::File { metadata, contents } => {
+            let data = match contents {
+                SerContents::Text(s) => s.into_bytes(),
+                SerContents::Bytes(b) => b,
+            };
+            let entry = MockFSEntry::File(MockFSFileEntry {
+                contents: Arc::new(RwLock::new(data)),
+                permissions: Arc::new(RwLock::new(MockPermissions::file_default())),
+                times: Arc::default(),
+            });
+            metadata.apply(&entry, user);
+            Ok(entry)
+        }
+    }
+}
+
+impl Serialize for MockFS {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        to_ser::<S::Error>(&self.root)?.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MockFS {
+    /// Loads a filesystem, using the Unix epoch and the default user for any
+    /// missing metadata.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MockFS, D::Error> {
+        let root = SerEntry::deserialize(deserializer)?;
+        if !matches!(root, SerEntry::Directory { .. }) {
+            return Err(D::Error::custom("the root of a MockFS must be a directory"));
+        }
+        let mut fs = MockFS::new();
+        fs.root = from_ser(root, &fs.user())?;
+        Ok(fs)
+    }
+}
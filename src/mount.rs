@@ -0,0 +1,515 @@
+//! A filesystem composed of other filesystems mounted at path prefixes.
+//!
+//! Each call is routed to the mount with the longest mount point containing
+//! the path, and the backend sees the rest of the path as an absolute path,
+//! so a `MockFS` mounted at `/out` receives `/out/a` as `/a`. To mount a
+//! directory of the real filesystem, wrap `OsFs` in a `SubFs`.
+//!
+//! Directories leading to mount points exist even if no backend provides
+//! them, and mount points are listed by `read_dir` on their parents. These
+//! synthesized directories cannot be modified, failing with
+//! `PermissionDenied`. Backends mounted read-only fail every modification
+//! with `ReadOnlyFilesystem`, and renaming between mounts fails with
+//! `CrossesDevices`. Mount points, and directories with mount points below
+//! them, including synthesized directories, cannot be removed, renamed or
+//! replaced, failing with `FileInUse`; remove their contents instead.
+//! Syncing read-only mounts and synthesized directories succeeds, as nothing
+//! in them can have changed.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::Path;
+//! use inscenerator_xfs::mockfs::MockFS;
+//! use inscenerator_xfs::mount::MountFs;
+//! use inscenerator_xfs::{Xfs, XfsError, XfsReadOnly};
+//!
+//! let mut templates = MockFS::new();
+//! templates.add_file(Path::new("/page.html"), "").unwrap();
+//!
+//! let mut fs = MountFs::new();
+//! fs.mount_read_only(Path::new("/templates"), Box::new(templates)).unwrap();
+//! fs.mount(Path::new("/out"), Box::new(MockFS::new())).unwrap();
+//!
+//! assert!(fs.is_file(Path::new("/templates/page.html")));
+//! fs.writer(Path::new("/out/page.html")).unwrap();
+//! assert!(matches!(
+//!     fs.rename(Path::new("/out/page.html"), Path::new("/templates/page.html")),
+//!     Err(XfsError::CrossesDevices { .. })
+//! ));
+//! ```
+
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::{
+    AlreadyExistsSnafu, CrossesDevicesSnafu, FileInUseSnafu, NotAFileSnafu, NotFoundSnafu,
+    PathOutsideSandboxSnafu, PermissionDeniedSnafu, ReadOnlyFilesystemSnafu, Result, Xfs,
+    XfsDirEntry, XfsEntryResult, XfsError, XfsMetadata, XfsReadDir, XfsReadOnly, XfsWrite,
+};
+
+enum Backend {
+    ReadOnly(Box<dyn XfsReadOnly>),
+    ReadWrite(Box<dyn Xfs>),
+}
+
+impl Backend {
+    fn read(&self) -> &dyn XfsReadOnly {
+        match self {
+            Backend::ReadOnly(fs) => fs.as_ref(),
+            Backend::ReadWrite(fs) => fs.as_ref(),
+        }
+    }
+}
+
+struct Mount {
+    point: PathBuf,
+    backend: Backend,
+}
+
+impl Mount {
+    /// Maps a path of the backend back into the mount namespace.
+    fn outer_path(&self, p: PathBuf) -> PathBuf {
+        match p.strip_prefix("/") {
+            Ok(rel) => self.point.join(rel),
+            Err(_) => p,
+        }
+    }
+
+    fn outer<T>(&self, r: Result<T>) -> Result<T> {
+        r.map_err(|e| e.map_path(|path| self.outer_path(path)))
+    }
+}
+
+/// Where a path leads.
+enum Route {
+    /// To the mount with the given index, at the given backend path.
+    Mounted(usize, PathBuf),
+    /// To a synthesized directory leading to mount points.
+    Synthesized,
+    /// Nowhere.
+    Missing,
+}
+
+/// Resolves `p` to an absolute path without `.` or `..` components.
+fn normalize(p: &Path) -> Result<PathBuf> {
+    let mut result = PathBuf::from("/");
+    for c in p.components() {
+        match c {
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+            Component::ParentDir => {
+                if !result.pop() {
+                    return PathOutsideSandboxSnafu { path: p }.fail();
+                }
+            }
+            Component::Normal(name) => result.push(name),
+        }
+    }
+    Ok(result)
+}
+
+/// A filesystem made of other filesystems mounted at path prefixes.
+#[derive(Default)]
+pub struct MountFs {
+    mounts: Vec<Mount>,
+}
+
+impl MountFs {
+    /// Creates a filesystem with nothing mounted.
+    pub fn new() -> MountFs {
+        MountFs::default()
+    }
+
+    fn add_mount(&mut self, point: &Path, backend: Backend) -> Result<()> {
+        let point = normalize(point)?;
+        if self.mounts.iter().any(|m| m.point == point) {
+            return AlreadyExistsSnafu { path: point }.fail();
+        }
+        self.mounts.push(Mount { point, backend });
+        Ok(())
+    }
+
+    /// Mounts `fs` at `point`, hiding anything below `point` in other mounts.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AlreadyExists` if something is already mounted at `point`.
+    pub fn mount(&mut self, point: &Path, fs: Box<dyn Xfs>) -> Result<()> {
+        self.add_mount(point, Backend::ReadWrite(fs))
+    }
+
+    /// Mounts `fs` at `point` so that it can only be read.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AlreadyExists` if something is already mounted at `point`.
+    pub fn mount_read_only(&mut self, point: &Path, fs: Box<dyn XfsReadOnly>) -> Result<()> {
+        self.add_mount(point, Backend::ReadOnly(fs))
+    }
+
+    /// Returns the mount points, in the order they were mounted.
+    pub fn mount_points(&self) -> impl Iterator<Item = &Path> {
+        self.mounts.iter().map(|m| m.point.as_path())
+    }
+
+    fn route(&self, p: &Path) -> Result<Route> {
+        let p = normalize(p)?;
+        let best = self
+            .mounts
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| p.starts_with(&m.point))
+            .max_by_key(|(_, m)| m.point.components().count());
+        Ok(match best {
+            Some((i, m)) => {
+                let rel = p.strip_prefix(&m.point).unwrap();
+                Route::Mounted(i, Path::new("/").join(rel))
+            }
+            None if self.leads_to_mounts(&p) => Route::Synthesized,
+            None => Route::Missing,
+        })
+    }
+
+    /// Returns true if there are mount points below the normalized path `p`.
+    fn leads_to_mounts(&self, p: &Path) -> bool {
+        !self.child_mounts(p).is_empty()
+    }
+
+    /// Returns the names of the entries of the normalized path `p` that lead
+    /// to mount points.
+    fn child_mounts(&self, p: &Path) -> BTreeSet<OsString> {
+        self.mounts
+            .iter()
+            .filter_map(|m| m.point.strip_prefix(p).ok())
+            .filter_map(|rel| rel.iter().next())
+            .map(|name| name.to_os_string())
+            .collect()
+    }
+
+    /// Fails with `FileInUse` if `p` is a mount point or has mount points
+    /// below it, as removing or moving it would pull the backing directory
+    /// out from under them.
+    fn check_no_mounts(&self, p: &Path) -> Result<()> {
+        let normalized = normalize(p)?;
+        if self.mounts.iter().any(|m| m.point.starts_with(&normalized)) {
+            return FileInUseSnafu { path: p }.fail();
+        }
+        Ok(())
+    }
+
+    /// Returns the writable backend for `p` and the path within it, or `None`
+    /// for synthesized directories.
+    fn writable(&mut self, p: &Path) -> Result<Option<(&mut Mount, PathBuf)>> {
+        match self.route(p)? {
+            Route::Mounted(i, inner) => match self.mounts[i].backend {
+                Backend::ReadWrite(_) => Ok(Some((&mut self.mounts[i], inner))),
+                Backend::ReadOnly(_) => ReadOnlyFilesystemSnafu { path: p }.fail(),
+            },
+            Route::Synthesized => Ok(None),
+            Route::Missing => NotFoundSnafu { path: p }.fail(),
+        }
+    }
+
+    /// Applies a modification to the writable backend for `p`, failing with
+    /// `PermissionDenied` for synthesized directories.
+    fn modify(
+        &mut self,
+        p: &Path,
+        f: impl FnOnce(&mut dyn Xfs, &Path) -> Result<()>,
+    ) -> Result<()> {
+        match self.writable(p)? {
+            Some((mount, inner)) => {
+                let r = match &mut mount.backend {
+                    Backend::ReadWrite(fs) => f(fs.as_mut(), &inner),
+                    Backend::ReadOnly(_) => unreachable!(),
+                };
+                mount.outer(r)
+            }
+            None => PermissionDeniedSnafu { path: p }.fail(),
+        }
+    }
+
+    /// Syncs `p` in its backend. Read-only mounts and synthesized directories
+    /// cannot have changed, so there `p` only has to exist.
+    fn sync(&mut self, p: &Path, f: impl FnOnce(&mut dyn Xfs, &Path) -> Result<()>) -> Result<()> {
+        let r = match self.route(p)? {
+            Route::Mounted(i, inner) => {
+                let mount = &mut self.mounts[i];
+                let r = match &mut mount.backend {
+                    Backend::ReadWrite(fs) => f(fs.as_mut(), &inner),
+                    Backend::ReadOnly(fs) => fs.metadata(&inner).map(|_| ()),
+                };
+                mount.outer(r)
+            }
+            Route::Synthesized => return Ok(()),
+            Route::Missing => return NotFoundSnafu { path: p }.fail(),
+        };
+        match r {
+            Err(XfsError::NotFound { .. }) if self.leads_to_mounts(&normalize(p)?) => Ok(()),
+            r => r,
+        }
+    }
+}
+
+/// The metadata of a synthesized directory.
+#[derive(Debug, Clone)]
+struct SynthesizedMetadata;
+
+impl XfsMetadata for SynthesizedMetadata {
+    fn is_dir(&self) -> bool {
+        true
+    }
+
+    fn is_file(&self) -> bool {
+        false
+    }
+
+    fn size(&self) -> u64 {
+        0
+    }
+
+    fn mode(&self) -> u32 {
+        0o555
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        None
+    }
+
+    fn accessed(&self) -> Option<SystemTime> {
+        None
+    }
+
+    fn created(&self) -> Option<SystemTime> {
+        None
+    }
+}
+
+struct MountDirEntry {
+    path: PathBuf,
+    // `None` for entries leading to mount points.
+    inner: Option<Box<dyn XfsDirEntry>>,
+}
+
+impl XfsDirEntry for MountDirEntry {
+    fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn metadata(&self) -> Result<Box<dyn XfsMetadata>> {
+        match &self.inner {
+            Some(entry) => entry.metadata(),
+            None => Ok(Box::new(SynthesizedMetadata)),
+        }
+    }
+}
+
+impl XfsReadOnly for MountFs {
+    fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
+        let mounts = self
+            .mounts
+            .iter()
+            .map(|m| Mount {
+                point: m.point.clone(),
+                backend: Backend::ReadOnly(m.backend.read().unsafe_clone()),
+            })
+            .collect();
+        Box::new(MountFs { mounts })
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        match self.route(p) {
+            Ok(Route::Mounted(i, inner)) => {
+                // Paths the backend resolves outside its root, such as
+                // through symbolic links, are left in its namespace.
+                let fs = self.mounts[i].backend.read();
+                let resolved = fs.canonical_path(&inner);
+                match resolved.strip_prefix(fs.canonical_path(Path::new("/"))) {
+                    Ok(rel) => self.mounts[i].point.join(rel),
+                    Err(_) => resolved,
+                }
+            }
+            _ => normalize(p).unwrap_or_else(|_| p.to_path_buf()),
+        }
+    }
+
+    fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
+        let children = self.child_mounts(&normalize(p)?);
+        let listed = match self.route(p)? {
+            Route::Mounted(i, inner) => {
+                let mount = &self.mounts[i];
+                match mount.outer(mount.backend.read().read_dir(&inner)) {
+                    Ok(entries) => Some((entries, inner)),
+                    Err(XfsError::NotFound { .. }) if !children.is_empty() => None,
+                    Err(e) => return Err(e),
+                }
+            }
+            Route::Synthesized => None,
+            Route::Missing => return NotFoundSnafu { path: p }.fail(),
+        };
+
+        let dir = p.to_path_buf();
+        let synthesized: Vec<XfsEntryResult> = children
+            .iter()
+            .map(|name| {
+                Ok(Box::new(MountDirEntry {
+                    path: dir.join(name),
+                    inner: None,
+                }) as Box<dyn XfsDirEntry>)
+            })
+            .collect();
+        let entries: XfsReadDir = match listed {
+            Some((entries, inner)) => Box::new(entries.filter_map(move |entry| {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => return Some(Err(e)),
+                };
+                let inner_path = entry.path();
+                let rel = inner_path.strip_prefix(&inner).unwrap_or(&inner_path);
+                // Mount points hide the backend's entries of the same name.
+                if rel.iter().next().is_some_and(|n| children.contains(n)) {
+                    return None;
+                }
+                Some(Ok(Box::new(MountDirEntry {
+                    path: dir.join(rel),
+                    inner: Some(entry),
+                }) as Box<dyn XfsDirEntry>))
+            })),
+            None => Box::new(std::iter::empty()),
+        };
+        Ok(Box::new(entries.chain(synthesized)))
+    }
+
+    fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
+        match self.route(p)? {
+            Route::Mounted(i, inner) => {
+                let mount = &self.mounts[i];
+                mount.outer(mount.backend.read().reader(&inner))
+            }
+            Route::Synthesized => NotAFileSnafu { path: p }.fail(),
+            Route::Missing => NotFoundSnafu { path: p }.fail(),
+        }
+    }
+
+    fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
+        match self.route(p)? {
+            Route::Mounted(i, inner) => {
+                let mount = &self.mounts[i];
+                mount.outer(mount.backend.read().read_all_lines(&inner))
+            }
+            Route::Synthesized => NotAFileSnafu { path: p }.fail(),
+            Route::Missing => NotFoundSnafu { path: p }.fail(),
+        }
+    }
+
+    fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        match self.route(p)? {
+            Route::Mounted(i, inner) => {
+                let mount = &self.mounts[i];
+                match mount.outer(mount.backend.read().metadata(&inner)) {
+                    Err(XfsError::NotFound { .. }) if self.leads_to_mounts(&normalize(p)?) => {
+                        Ok(Box::new(SynthesizedMetadata))
+                    }
+                    r => r,
+                }
+            }
+            Route::Synthesized => Ok(Box::new(SynthesizedMetadata)),
+            Route::Missing => NotFoundSnafu { path: p }.fail(),
+        }
+    }
+}
+
+impl Xfs for MountFs {
+    fn unsafe_clone_mut(&mut self) -> Box<dyn Xfs> {
+        let mounts = self
+            .mounts
+            .iter_mut()
+            .map(|m| Mount {
+                point: m.point.clone(),
+                backend: match &mut m.backend {
+                    Backend::ReadOnly(fs) => Backend::ReadOnly(fs.unsafe_clone()),
+                    Backend::ReadWrite(fs) => Backend::ReadWrite(fs.unsafe_clone_mut()),
+                },
+            })
+            .collect();
+        Box::new(MountFs { mounts })
+    }
+
+    fn writer(&mut self, p: &Path) -> Result<Box<dyn XfsWrite>> {
+        match self.writable(p)? {
+            Some((mount, inner)) => {
+                let r = match &mut mount.backend {
+                    Backend::ReadWrite(fs) => fs.writer(&inner),
+                    Backend::ReadOnly(_) => unreachable!(),
+                };
+                mount.outer(r)
+            }
+            None => NotAFileSnafu { path: p }.fail(),
+        }
+    }
+
+    fn create_dir(&mut self, p: &Path) -> Result<()> {
+        if let Route::Synthesized = self.route(p)? {
+            return AlreadyExistsSnafu { path: p }.fail();
+        }
+        self.modify(p, |fs, p| fs.create_dir(p))
+    }
+
+    fn create_dir_all(&mut self, p: &Path) -> Result<()> {
+        if let Route::Synthesized = self.route(p)? {
+            return Ok(());
+        }
+        self.modify(p, |fs, p| fs.create_dir_all(p))
+    }
+
+    fn remove_file(&mut self, p: &Path) -> Result<()> {
+        self.modify(p, |fs, p| fs.remove_file(p))
+    }
+
+    fn remove_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.check_no_mounts(p)?;
+        self.modify(p, |fs, p| fs.remove_dir_all(p))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let target = match self.route(to)? {
+            Route::Mounted(i, inner) => (i, inner),
+            Route::Synthesized => return FileInUseSnafu { path: to }.fail(),
+            Route::Missing => return NotFoundSnafu { path: to }.fail(),
+        };
+        match self.route(from)? {
+            Route::Mounted(i, _) if i != target.0 => {
+                return CrossesDevicesSnafu { path: from }.fail()
+            }
+            Route::Missing => return NotFoundSnafu { path: from }.fail(),
+            _ => {}
+        }
+        self.check_no_mounts(from)?;
+        self.check_no_mounts(to)?;
+        self.modify(from, |fs, from| fs.rename(from, &target.1))
+    }
+
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()> {
+        self.modify(p, |fs, p| fs.set_permissions(p, mode))
+    }
+
+    fn set_times(
+        &mut self,
+        p: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<()> {
+        self.modify(p, |fs, p| fs.set_times(p, accessed, modified))
+    }
+
+    fn sync_all(&mut self, p: &Path) -> Result<()> {
+        self.sync(p, |fs, p| fs.sync_all(p))
+    }
+
+    fn sync_data(&mut self, p: &Path) -> Result<()> {
+        self.sync(p, |fs, p| fs.sync_data(p))
+    }
+}
@@ -0,0 +1,384 @@
+//! Tracking of the files a generator writes, for removing stale outputs.
+//!
+//! An `OutputTrackingFs` records every file written below an output root.
+//! At the end of a run, `prune_stale` removes the files that the previous run
+//! wrote but this run did not, along with any directories left empty, and
+//! saves the files written this run to a manifest in the output directory
+//! for next time. Files that were never written through the wrapper, such as
+//! files added by hand, are never removed.
+//!
+//! The manifest is a text file listing one path per line, relative to the
+//! output root and separated by `/`. Lines that are absolute or contain `.`
+//! or `..` are ignored, so a tampered manifest cannot remove files outside
+//! the output root. Writing a file below the output root whose path is not
+//! valid UTF-8 fails, as it could not be listed in the manifest.
+//!
+//! Paths are compared as the wrapped filesystem resolves them (see
+//! `XfsReadOnly::canonical_path`), so a file written through a relative path
+//! or one containing `.` counts as the same output.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::Path;
+//! use inscenerator_xfs::mockfs::MockFS;
+//! use inscenerator_xfs::outputs::OutputTrackingFs;
+//! use inscenerator_xfs::{Xfs, XfsReadOnly};
+//!
+//! let mut fs = MockFS::new();
+//! fs.create_dir_all(Path::new("/public/old")).unwrap();
+//!
+//! // The first run writes two pages.
+//! let mut run = OutputTrackingFs::new(fs.unsafe_clone_mut(), Path::new("/public"));
+//! run.writer(Path::new("/public/index.html")).unwrap();
+//! run.writer(Path::new("/public/old/page.html")).unwrap();
+//! run.prune_stale().unwrap();
+//!
+//! // The second run no longer writes the old page.
+//! let mut run = OutputTrackingFs::new(fs.unsafe_clone_mut(), Path::new("/public"));
+//! run.writer(Path::new("/public/index.html")).unwrap();
+//! let removed = run.prune_stale().unwrap();
+//! assert_eq!(removed, vec![Path::new("/public/old/page.html"), Path::new("/public/old")]);
+//! assert!(fs.is_file(Path::new("/public/index.html")));
+//! ```
+
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::{GeneralSnafu, Result, Xfs, XfsError, XfsMetadata, XfsReadDir, XfsReadOnly, XfsWrite};
+
+/// The default name of the manifest, within the output root.
+pub const MANIFEST_NAME: &str = ".xfs-outputs";
+
+/// Formats a relative path for the manifest.
+fn manifest_line(rel: &Path) -> String {
+    rel.iter()
+        .map(|c| c.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Wraps a filesystem, recording the files written below an output root.
+///
+/// Clones share the record of written files.
+pub struct OutputTrackingFs<F: ?Sized> {
+    root: PathBuf,
+    manifest: PathBuf,
+    written: Arc<Mutex<BTreeSet<PathBuf>>>,
+    inner: Box<F>,
+}
+
+impl<F: ?Sized> OutputTrackingFs<F> {
+    /// Tracks the files written below `root`, keeping the manifest in
+    /// `root/.xfs-outputs`.
+    pub fn new(inner: Box<F>, root: &Path) -> OutputTrackingFs<F> {
+        Self::with_manifest(inner, root, &root.join(MANIFEST_NAME))
+    }
+
+    /// Tracks the files written below `root`, keeping the manifest at
+    /// `manifest`.
+    pub fn with_manifest(inner: Box<F>, root: &Path, manifest: &Path) -> OutputTrackingFs<F> {
+        OutputTrackingFs {
+            root: root.to_path_buf(),
+            manifest: manifest.to_path_buf(),
+            written: Arc::new(Mutex::new(BTreeSet::new())),
+            inner,
+        }
+    }
+
+    /// Returns the files written below the output root in this run, sorted.
+    pub fn written(&self) -> Vec<PathBuf> {
+        let written = self.written.lock().unwrap();
+        written.iter().map(|rel| self.root.join(rel)).collect()
+    }
+
+    /// Returns the wrapped filesystem.
+    pub fn into_inner(self) -> Box<F> {
+        self.inner
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> OutputTrackingFs<F> {
+    /// Returns `p` relative to the output root, or `None` if it is outside
+    /// the root or is the manifest. Paths are compared as the wrapped
+    /// filesystem resolves them, so that every way of writing a path gives
+    /// the same result.
+    fn relative(&self, p: &Path) -> Option<PathBuf> {
+        let p = self.inner.canonical_path(p);
+        if p == self.inner.canonical_path(&self.manifest) {
+            return None;
+        }
+        match p.strip_prefix(self.inner.canonical_path(&self.root)) {
+            Ok(rel) if !rel.as_os_str().is_empty() => Some(rel.to_path_buf()),
+            _ => None,
+        }
+    }
+
+    /// Fails if `p` would be recorded as an output but cannot be listed in
+    /// the manifest, which is UTF-8 text.
+    fn check_trackable(&self, p: &Path) -> Result<()> {
+        match self.relative(p) {
+            Some(rel) if rel.to_str().is_none() => GeneralSnafu {
+                message: format!("cannot track output with a non-UTF-8 path {}", p.display()),
+            }
+            .fail(),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_written(&self, p: &Path) {
+        if let Some(rel) = self.relative(p) {
+            self.written.lock().unwrap().insert(rel);
+        }
+    }
+
+    /// Forgets the files written at or below `p`.
+    fn forget(&self, p: &Path) {
+        if let Some(rel) = self.relative(p) {
+            self.written
+                .lock()
+                .unwrap()
+                .retain(|w| !w.starts_with(&rel));
+        } else if self.inner.canonical_path(p) == self.inner.canonical_path(&self.root) {
+            self.written.lock().unwrap().clear();
+        }
+    }
+
+    /// Returns every file below the directory `p`.
+    fn files_below(&self, p: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = vec![];
+        let mut pending = vec![p.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            for entry in self.inner.read_dir(&dir)? {
+                let entry = entry?;
+                if entry.metadata()?.is_dir() {
+                    pending.push(entry.path());
+                } else {
+                    files.push(entry.path());
+                }
+            }
+        }
+        Ok(files)
+    }
+}
+
+impl<F: Xfs + ?Sized> OutputTrackingFs<F> {
+    /// Returns the files listed in the manifest of the previous run, or none
+    /// if there is no manifest.
+    fn previous(&self) -> Result<BTreeSet<PathBuf>> {
+        match self.inner.read_all_lines(&self.manifest) {
+            Ok(lines) => Ok(lines
+                .iter()
+                .filter(|line| !line.is_empty())
+                .map(|line| line.split('/').collect::<PathBuf>())
+                // A line that would resolve outside the output root, such as
+                // `../important.txt`, never names an output; skip it.
+                .filter(|rel| rel.components().all(|c| matches!(c, Component::Normal(_))))
+                .collect()),
+            Err(XfsError::NotFound { .. }) => Ok(BTreeSet::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the files written by the previous run but not by this one
+    /// that still exist, without removing them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest cannot be read.
+    pub fn stale(&self) -> Result<Vec<PathBuf>> {
+        let written = self.written.lock().unwrap().clone();
+        Ok(self
+            .previous()?
+            .difference(&written)
+            .map(|rel| self.root.join(rel))
+            .filter(|p| self.inner.is_file(p))
+            .collect())
+    }
+
+    /// Saves the files written in this run as the manifest, replacing the
+    /// previous run's.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest cannot be written.
+    pub fn save_manifest(&mut self) -> Result<()> {
+        let contents: String = self
+            .written
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|rel| format!("{}\n", manifest_line(rel)))
+            .collect();
+        if let Some(parent) = self.manifest.parent() {
+            if !parent.as_os_str().is_empty() {
+                self.inner.create_dir_all(parent)?;
+            }
+        }
+        let mut w = self.inner.writer(&self.manifest)?;
+        w.write_all(contents.as_bytes())
+            .and_then(|_| w.flush())
+            .map_err(|e| XfsError::IoError {
+                path: self.manifest.clone(),
+                source: e,
+            })
+    }
+
+    /// Removes the files written by the previous run but not by this one,
+    /// then any directories below the output root left empty, and saves the
+    /// manifest. Returns the removed files and directories, in the order
+    /// they were removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest cannot be read or written, or a
+    /// stale file cannot be removed.
+    pub fn prune_stale(&mut self) -> Result<Vec<PathBuf>> {
+        let mut removed = vec![];
+        for p in self.stale()? {
+            if self.relative(&p).is_none() {
+                continue;
+            }
+            self.inner.remove_file(&p)?;
+            removed.push(p.clone());
+            let mut dir = p.parent();
+            while let Some(d) = dir {
+                if self.relative(d).is_none() || self.inner.read_dir(d)?.next().is_some() {
+                    break;
+                }
+                self.inner.remove_dir_all(d)?;
+                removed.push(d.to_path_buf());
+                dir = d.parent();
+            }
+        }
+        self.save_manifest()?;
+        Ok(removed)
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> XfsReadOnly for OutputTrackingFs<F> {
+    fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
+        Box::new(OutputTrackingFs {
+            root: self.root.clone(),
+            manifest: self.manifest.clone(),
+            written: self.written.clone(),
+            inner: self.inner.unsafe_clone(),
+        })
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        self.inner.canonical_path(p)
+    }
+
+    fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
+        self.inner.read_dir(p)
+    }
+
+    fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
+        self.inner.reader(p)
+    }
+
+    fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
+        self.inner.read_all_lines(p)
+    }
+
+    fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        self.inner.metadata(p)
+    }
+}
+
+impl<F: Xfs + ?Sized> Xfs for OutputTrackingFs<F> {
+    fn unsafe_clone_mut(&mut self) -> Box<dyn Xfs> {
+        Box::new(OutputTrackingFs {
+            root: self.root.clone(),
+            manifest: self.manifest.clone(),
+            written: self.written.clone(),
+            inner: self.inner.unsafe_clone_mut(),
+        })
+    }
+
+    fn writer(&mut self, p: &Path) -> Result<Box<dyn XfsWrite>> {
+        self.check_trackable(p)?;
+        let w = self.inner.writer(p)?;
+        self.record_written(p);
+        Ok(w)
+    }
+
+    fn create_dir(&mut self, p: &Path) -> Result<()> {
+        self.inner.create_dir(p)
+    }
+
+    fn create_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.inner.create_dir_all(p)
+    }
+
+    fn remove_file(&mut self, p: &Path) -> Result<()> {
+        self.inner.remove_file(p)?;
+        self.forget(p);
+        Ok(())
+    }
+
+    fn remove_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.inner.remove_dir_all(p)?;
+        self.forget(p);
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        // Files moved into the output root count as written there: a file,
+        // the outputs within a directory moved inside the root, or every file
+        // of a directory moved in from outside it.
+        let moved: Vec<PathBuf> = if self.relative(to).is_none() || !self.inner.is_dir(from) {
+            vec![to.to_path_buf()]
+        } else if let Some(rel) = self.relative(from) {
+            self.written
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|w| w.strip_prefix(&rel).ok())
+                .map(|suffix| to.join(suffix))
+                .collect()
+        } else {
+            self.files_below(from)?
+                .iter()
+                .filter_map(|f| f.strip_prefix(from).ok())
+                .map(|suffix| to.join(suffix))
+                .collect()
+        };
+        for p in &moved {
+            self.check_trackable(p)?;
+        }
+        self.inner.rename(from, to)?;
+        self.forget(from);
+        for p in moved {
+            if self.inner.is_file(&p) {
+                self.record_written(&p);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()> {
+        self.inner.set_permissions(p, mode)
+    }
+
+    fn set_times(
+        &mut self,
+        p: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<()> {
+        self.inner.set_times(p, accessed, modified)
+    }
+
+    fn sync_all(&mut self, p: &Path) -> Result<()> {
+        self.inner.sync_all(p)
+    }
+
+    fn sync_data(&mut self, p: &Path) -> Result<()> {
+        self.inner.sync_data(p)
+    }
+}
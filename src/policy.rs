@@ -0,0 +1,369 @@
+//! A wrapper restricting which paths of another filesystem can be read and
+//! written, for handing a limited view to untrusted code such as plugins.
+//!
+//! Reads and writes are governed by separate `Rules`, each a list of glob
+//! patterns (see the `glob` module) that allow access and a list that deny
+//! it. A path is accessible if it matches an allow pattern and no deny
+//! pattern. Paths are resolved with the wrapped filesystem's
+//! `canonical_path` before matching, so that a path written another way,
+//! relative or with `.` and `..`, matches the same rules, and `..` cannot be
+//! used to escape an allowed subtree. Relative paths that still start with
+//! `..` afterwards are always denied.
+//!
+//! Patterns starting with `/` are matched against the resolved path.
+//! Relative patterns are anchored at the wrapped filesystem's current
+//! directory, `canonical_path(".")`, which is the root for `MockFS`: they
+//! match the resolved path relative to it, and never match paths outside it.
+//!
+//! Disallowed paths fail with `PermissionDenied`, or with `NotFound` for
+//! paths that cannot be read when `hide_denied` is set, and are left out of
+//! `read_dir` listings.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::Path;
+//! use inscenerator_xfs::mockfs::MockFS;
+//! use inscenerator_xfs::policy::{Policy, PolicyFs, Rules};
+//! use inscenerator_xfs::{Xfs, XfsError, XfsReadOnly};
+//!
+//! let mut inner = MockFS::new();
+//! inner.add_file(Path::new("/plugin/config.toml"), "").unwrap();
+//! inner.add_file(Path::new("/secrets/key"), "").unwrap();
+//!
+//! let policy = Policy {
+//!     read: Rules::new(&["/plugin/**"], &[]).unwrap(),
+//!     write: Rules::new(&["/plugin/out/**"], &[]).unwrap(),
+//!     hide_denied: true,
+//! };
+//! let mut fs = PolicyFs::new(Box::new(inner), policy);
+//!
+//! assert!(fs.is_file(Path::new("/plugin/config.toml")));
+//! assert!(matches!(
+//!     fs.reader(Path::new("/secrets/key")),
+//!     Err(XfsError::NotFound { .. })
+//! ));
+//! assert!(matches!(
+//!     fs.writer(Path::new("/plugin/config.toml")),
+//!     Err(XfsError::PermissionDenied { .. })
+//! ));
+//! fs.create_dir_all(Path::new("/plugin/out")).unwrap();
+//! ```
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::glob::{self, Glob};
+use crate::{Result, Xfs, XfsError, XfsMetadata, XfsReadDir, XfsReadOnly, XfsWrite};
+
+/// Allow and deny patterns for one kind of access.
+///
+/// The default rules allow nothing.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    pub allow: Vec<Glob>,
+    pub deny: Vec<Glob>,
+}
+
+impl Rules {
+    /// Compiles rules from allow and deny patterns.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any pattern is invalid.
+    pub fn new(allow: &[&str], deny: &[&str]) -> Result<Rules> {
+        let compile =
+            |patterns: &[&str]| patterns.iter().map(|p| Glob::new(p)).collect::<Result<_>>();
+        Ok(Rules {
+            allow: compile(allow)?,
+            deny: compile(deny)?,
+        })
+    }
+
+    /// Rules that allow every absolute path.
+    pub fn allow_all() -> Rules {
+        Rules {
+            allow: vec![Glob::new("/**").unwrap()],
+            deny: vec![],
+        }
+    }
+
+    /// Returns true if `p` matches an allow pattern and no deny pattern.
+    /// Paths that start with `..` after resolving `.` and `..` are never
+    /// allowed.
+    pub fn allows(&self, p: &Path) -> bool {
+        !glob::escapes(p)
+            && self.allow.iter().any(|g| g.matches(p))
+            && !self.deny.iter().any(|g| g.matches(p))
+    }
+
+    /// Like `allows`, but with relative patterns anchored at `dir`, so that
+    /// they match `p` relative to `dir` and never match an absolute `p`
+    /// outside it. Patterns starting with `/` are matched against `p`.
+    pub fn allows_from(&self, p: &Path, dir: &Path) -> bool {
+        let relative = if p.has_root() {
+            p.strip_prefix(dir).ok()
+        } else {
+            Some(p)
+        };
+        let matches = |g: &Glob| {
+            if Path::new(g.as_str()).has_root() {
+                g.matches(p)
+            } else {
+                relative.is_some_and(|rel| g.matches(rel))
+            }
+        };
+        !glob::escapes(p) && self.allow.iter().any(matches) && !self.deny.iter().any(matches)
+    }
+}
+
+/// The access policy enforced by a `PolicyFs`.
+///
+/// The default policy allows nothing.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    pub read: Rules,
+    pub write: Rules,
+    /// Whether paths that cannot be read are reported as `NotFound` rather
+    /// than `PermissionDenied`, hiding their existence.
+    pub hide_denied: bool,
+}
+
+/// Wraps a filesystem, allowing access only to the paths permitted by a
+/// `Policy`.
+///
+/// Wrapping an `XfsReadOnly` gives a restricted read-only view. Clones share
+/// the policy.
+pub struct PolicyFs<F: ?Sized> {
+    policy: Arc<Policy>,
+    inner: Box<F>,
+}
+
+impl<F: ?Sized> PolicyFs<F> {
+    pub fn new(inner: Box<F>, policy: Policy) -> PolicyFs<F> {
+        PolicyFs {
+            policy: Arc::new(policy),
+            inner,
+        }
+    }
+
+    /// Returns the policy being enforced.
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Returns the wrapped filesystem.
+    pub fn into_inner(self) -> Box<F> {
+        self.inner
+    }
+}
+
+/// Returns true if `rules` allow `p`, with both resolved by `fs`.
+fn allows_in<F: XfsReadOnly + ?Sized>(fs: &F, rules: &Rules, p: &Path) -> bool {
+    rules.allows_from(&fs.canonical_path(p), &fs.canonical_path(Path::new(".")))
+}
+
+impl<F: XfsReadOnly + ?Sized> PolicyFs<F> {
+    /// Returns true if `rules` allow `p`, resolved the way the wrapped
+    /// filesystem resolves it.
+    fn allows(&self, rules: &Rules, p: &Path) -> bool {
+        allows_in(self.inner.as_ref(), rules, p)
+    }
+
+    fn denied(&self, p: &Path) -> XfsError {
+        if self.policy.hide_denied && !self.allows(&self.policy.read, p) {
+            XfsError::NotFound {
+                path: p.to_path_buf(),
+            }
+        } else {
+            XfsError::PermissionDenied {
+                path: p.to_path_buf(),
+            }
+        }
+    }
+
+    fn check_read(&self, p: &Path) -> Result<()> {
+        if self.allows(&self.policy.read, p) {
+            Ok(())
+        } else {
+            Err(self.denied(p))
+        }
+    }
+
+    fn check_write(&self, p: &Path) -> Result<()> {
+        if self.allows(&self.policy.write, p) {
+            Ok(())
+        } else {
+            Err(self.denied(p))
+        }
+    }
+
+    /// Checks that `p` and, if it is a directory, everything below it are
+    /// allowed by `rules`.
+    fn check_tree(&self, p: &Path, rules: &Rules) -> Result<()> {
+        if !self.allows(rules, p) {
+            return Err(self.denied(p));
+        }
+        if !self.inner.is_dir(p) {
+            return Ok(());
+        }
+        let mut pending = vec![p.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            for entry in self.inner.read_dir(&dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if !self.allows(rules, &path) {
+                    return Err(XfsError::PermissionDenied {
+                        path: p.to_path_buf(),
+                    });
+                }
+                if entry.metadata()?.is_dir() {
+                    pending.push(path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `p` and, if it is a directory, everything below it may be
+    /// written, as removing or moving it affects the whole subtree.
+    fn check_write_tree(&self, p: &Path) -> Result<()> {
+        self.check_tree(p, &self.policy.write)
+    }
+
+    /// Checks that `p` and, if it is a directory, everything below it may be
+    /// read, as moving it elsewhere could make it readable.
+    fn check_read_tree(&self, p: &Path) -> Result<()> {
+        self.check_tree(p, &self.policy.read)
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> XfsReadOnly for PolicyFs<F> {
+    fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
+        Box::new(PolicyFs {
+            policy: self.policy.clone(),
+            inner: self.inner.unsafe_clone(),
+        })
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        self.inner.canonical_path(p)
+    }
+
+    fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
+        self.check_read(p)?;
+        let policy = self.policy.clone();
+        let inner = self.inner.unsafe_clone();
+        let entries = self.inner.read_dir(p)?.filter(move |entry| match entry {
+            Ok(entry) => allows_in(inner.as_ref(), &policy.read, &entry.path()),
+            Err(_) => true,
+        });
+        Ok(Box::new(entries))
+    }
+
+    fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
+        self.check_read(p)?;
+        self.inner.reader(p)
+    }
+
+    fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
+        self.check_read(p)?;
+        self.inner.read_all_lines(p)
+    }
+
+    fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        self.check_read(p)?;
+        self.inner.metadata(p)
+    }
+}
+
+impl<F: Xfs + ?Sized> Xfs for PolicyFs<F> {
+    fn unsafe_clone_mut(&mut self) -> Box<dyn Xfs> {
+        Box::new(PolicyFs {
+            policy: self.policy.clone(),
+            inner: self.inner.unsafe_clone_mut(),
+        })
+    }
+
+    fn writer(&mut self, p: &Path) -> Result<Box<dyn XfsWrite>> {
+        self.check_write(p)?;
+        self.inner.writer(p)
+    }
+
+    fn create_dir(&mut self, p: &Path) -> Result<()> {
+        self.check_write(p)?;
+        self.inner.create_dir(p)
+    }
+
+    fn create_dir_all(&mut self, p: &Path) -> Result<()> {
+        // Every directory that would be created must be writable. Whether an
+        // ancestor exists is only looked up if the policy lets the caller read
+        // or write it, so that hidden paths stay hidden. Below an ancestor
+        // that cannot be looked up, the directories are created one at a time,
+        // failing as `create_dir` would if it is missing.
+        self.check_write(p)?;
+        let mut missing = vec![];
+        for ancestor in p.ancestors() {
+            if ancestor.as_os_str().is_empty()
+                || !(self.allows(&self.policy.read, ancestor)
+                    || self.allows(&self.policy.write, ancestor))
+                || self.inner.exists(ancestor)
+            {
+                break;
+            }
+            self.check_write(ancestor)?;
+            missing.push(ancestor);
+        }
+        if missing.is_empty() {
+            return self.inner.create_dir_all(p);
+        }
+        for dir in missing.into_iter().rev() {
+            self.inner.create_dir(dir)?;
+        }
+        Ok(())
+    }
+
+    fn remove_file(&mut self, p: &Path) -> Result<()> {
+        self.check_write(p)?;
+        self.inner.remove_file(p)
+    }
+
+    fn remove_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.check_write_tree(p)?;
+        self.inner.remove_dir_all(p)
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        self.check_read_tree(from)?;
+        self.check_write_tree(from)?;
+        self.check_write(to)?;
+        self.inner.rename(from, to)
+    }
+
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()> {
+        self.check_write(p)?;
+        self.inner.set_permissions(p, mode)
+    }
+
+    fn set_times(
+        &mut self,
+        p: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<()> {
+        self.check_write(p)?;
+        self.inner.set_times(p, accessed, modified)
+    }
+
+    fn sync_all(&mut self, p: &Path) -> Result<()> {
+        self.check_read(p)?;
+        self.inner.sync_all(p)
+    }
+
+    fn sync_data(&mut self, p: &Path) -> Result<()> {
+        self.check_read(p)?;
+        self.inner.sync_data(p)
+    }
+}
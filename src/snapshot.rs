@@ -0,0 +1,226 @@
+//! Snapshot (golden file) assertions for filesystem state.
+//!
+//! A snapshot is a txtar archive stored on disk describing every file and empty
+//! directory below some root. Binary files are recorded by their size and
+//! SHA-256 hash, as a single `<binary data: ...>` line.
+//!
+//! Text files are recorded as they are, except that a text file without a
+//! final newline starts with a `<no newline at end of file>` line, and a text
+//! file whose first line could be mistaken for one of these markers starts
+//! with a `<text>` line. Every distinct file therefore has a distinct
+//! snapshot.
+//!
+//! Setting the environment variable `XFS_UPDATE_SNAPSHOTS=1` makes
+//! `assert_snapshot` write the current state to the snapshot file instead of
+//! comparing against it.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::hash::hash_bytes;
+use crate::txtar::Archive;
+use crate::{Result, XfsError, XfsReadOnly};
+
+/// The environment variable that switches `assert_snapshot` to update mode.
+pub const UPDATE_ENV_VAR: &str = "XFS_UPDATE_SNAPSHOTS";
+
+const BINARY_MARKER: &str = "<binary data:";
+const NO_NEWLINE_MARKER: &str = "<no newline at end of file>";
+const TEXT_MARKER: &str = "<text>";
+
+/// Renders the contents of a file, marking those that the txtar format
+/// alone cannot tell apart.
+fn render_contents(data: Vec<u8>) -> String {
+    let text = match String::from_utf8(data) {
+        Ok(text) => text,
+        Err(e) => {
+            let data = e.as_bytes();
+            return format!(
+                "{} {} bytes, sha256 {}>\n",
+                BINARY_MARKER,
+                data.len(),
+                hash_bytes(data)
+            );
+        }
+    };
+    if !text.is_empty() && !text.ends_with('\n') {
+        return format!("{}\n{}\n", NO_NEWLINE_MARKER, text);
+    }
+    let first_line = text.lines().next().unwrap_or("");
+    if first_line.starts_with(BINARY_MARKER)
+        || first_line == NO_NEWLINE_MARKER
+        || first_line == TEXT_MARKER
+    {
+        return format!("{}\n{}", TEXT_MARKER, text);
+    }
+    text
+}
+
+/// Renders the tree below `root` in snapshot form.
+///
+/// # Errors
+///
+/// Returns an error if the tree cannot be read, contains file names that
+/// are not valid UTF-8, or contains a text file with a line that looks like a
+/// txtar header.
+pub fn render(fs: &dyn XfsReadOnly, root: &Path) -> Result<String> {
+    let archive = Archive::from_fs_with(fs, root, &|_, data| Ok(render_contents(data)))?;
+    Ok(archive.format())
+}
+
+/// Compares two snapshots, returning a readable per-file diff if they differ.
+pub fn diff(expected: &str, actual: &str) -> Option<String> {
+    let expected: BTreeMap<String, String> = Archive::parse(expected)
+        .files
+        .into_iter()
+        .map(|f| (f.name, f.data))
+        .collect();
+    let actual: BTreeMap<String, String> = Archive::parse(actual)
+        .files
+        .into_iter()
+        .map(|f| (f.name, f.data))
+        .collect();
+
+    let mut out = String::new();
+    for (name, data) in &expected {
+        match actual.get(name) {
+            None => out.push_str(&format!("--- missing: {}\n", name)),
+            Some(actual_data) if actual_data != data => {
+                out.push_str(&format!("~~~ changed: {}\n", name));
+                out.push_str(&diff_lines(data, actual_data));
+            }
+            Some(_) => {}
+        }
+    }
+    for name in actual.keys() {
+        if !expected.contains_key(name) {
+            out.push_str(&format!("+++ unexpected: {}\n", name));
+        }
+    }
+
+    if out.is_empty() {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+/// A line diff based on the longest common subsequence, with removed lines
+/// prefixed by `-`, added lines by `+` and common lines indented.
+fn diff_lines(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+
+    // lcs[i][j] is the length of the LCS of a[i..] and b[j..].
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                usize::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out.push_str(&format!("  {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("- {}\n", a[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+ {}\n", b[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+/// Writes the tree below `root` to the snapshot file at `snapshot` on the real
+/// filesystem, creating parent directories as needed.
+pub fn write_snapshot(fs: &dyn XfsReadOnly, root: &Path, snapshot: &Path) -> Result<()> {
+    let actual = render(fs, root)?;
+    if let Some(parent) = snapshot.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| XfsError::IoError {
+            path: parent.to_path_buf(),
+            source: e,
+        })?;
+    }
+    std::fs::write(snapshot, actual).map_err(|e| XfsError::IoError {
+        path: snapshot.to_path_buf(),
+        source: e,
+    })
+}
+
+fn update_requested() -> bool {
+    match std::env::var(UPDATE_ENV_VAR) {
+        Ok(v) => !v.is_empty() && v != "0",
+        Err(_) => false,
+    }
+}
+
+/// Asserts that the tree below `root` matches the snapshot stored at
+/// `snapshot` on the real filesystem.
+///
+/// # Panics
+///
+/// Panics with a per-file diff if the tree does not match, or if the snapshot
+/// does not exist. If `XFS_UPDATE_SNAPSHOTS` is set the snapshot is written
+/// instead and the assertion passes.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use inscenerator_xfs::mockfs::MockFS;
+/// use inscenerator_xfs::snapshot::assert_snapshot;
+///
+/// let mut fs = MockFS::new();
+/// fs.add_file(Path::new("site/index.html"), "<h1>Hello</h1>\n").unwrap();
+/// assert_snapshot(&fs, Path::new("site"), Path::new("tests/snapshots/site.txtar"));
+/// ```
+pub fn assert_snapshot(fs: &dyn XfsReadOnly, root: &Path, snapshot: &Path) {
+    assert_snapshot_with(fs, root, snapshot, update_requested())
+}
+
+/// Like `assert_snapshot`, but writes the snapshot if and only if `update`
+/// is true, ignoring `XFS_UPDATE_SNAPSHOTS`.
+///
+/// # Panics
+///
+/// Panics with a per-file diff if `update` is false and the tree does not
+/// match, or if the snapshot does not exist.
+pub fn assert_snapshot_with(fs: &dyn XfsReadOnly, root: &Path, snapshot: &Path, update: bool) {
+    if update {
+        write_snapshot(fs, root, snapshot).unwrap_or_else(|e| panic!("{}", e));
+        return;
+    }
+
+    let actual = render(fs, root)
+        .unwrap_or_else(|e| panic!("failed to render snapshot of {}: {}", root.display(), e));
+
+    let expected = match std::fs::read_to_string(snapshot) {
+        Ok(expected) => expected,
+        Err(e) => panic!(
+            "failed to read snapshot {}: {}\nRun with {}=1 to create it. Actual state:\n{}",
+            snapshot.display(),
+            e,
+            UPDATE_ENV_VAR,
+            actual
+        ),
+    };
+
+    if let Some(d) = diff(&expected, &actual) {
+        panic!(
+            "filesystem does not match snapshot {}:\n{}Run with {}=1 to update it.",
+            snapshot.display(),
+            d,
+            UPDATE_ENV_VAR
+        );
+    }
+}
@@ -0,0 +1,689 @@
+//! A strict, expectation-based mock filesystem.
+//!
+//! Unlike `MockFS`, which behaves like a real filesystem, a `StrictFs` only
+//! accepts the calls a test has scripted in advance. Each expectation is
+//! satisfied by exactly one matching call, and calls may arrive in any order.
+//! A call without a matching expectation panics immediately, and expectations
+//! still unsatisfied when the last handle is dropped panic as well.
+//!
+//! Note that the provided `exists`, `is_dir` and `is_file` methods are
+//! implemented with `metadata`, so they are matched by `expect_metadata`.
+//! The provided `copy_file`, `copy_recursive` and `move_recursive` methods
+//! are instead matched by their own expectations, rather than by the calls
+//! they would otherwise be made of.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::Write;
+//! use std::path::Path;
+//! use inscenerator_xfs::strict::StrictFs;
+//! use inscenerator_xfs::{Xfs, XfsReadOnly};
+//!
+//! let mut fs = StrictFs::new();
+//! fs.expect_read_all_lines("a.txt").returning("hello\nworld\n");
+//! fs.expect_writer("b.txt").expecting_contents("HELLO\n");
+//! fs.expect_remove_file("a.txt");
+//!
+//! let lines = fs.read_all_lines(Path::new("a.txt")).unwrap();
+//! writeln!(fs.writer(Path::new("b.txt")).unwrap(), "{}", lines[0].to_uppercase()).unwrap();
+//! fs.remove_file(Path::new("a.txt")).unwrap();
+//! ```
+
+use std::fmt;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use crate::mockfs::IntoMockContents;
+use crate::{
+    Overwrite, Result, Xfs, XfsDirEntry, XfsEntryResult, XfsError, XfsMetadata, XfsReadDir,
+    XfsReadOnly, XfsWrite,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Call {
+    ReadDir(PathBuf),
+    Reader(PathBuf),
+    ReadAllLines(PathBuf),
+    Metadata(PathBuf),
+    Writer(PathBuf),
+    CreateDir(PathBuf),
+    CreateDirAll(PathBuf),
+    RemoveFile(PathBuf),
+    RemoveDirAll(PathBuf),
+    Rename(PathBuf, PathBuf),
+    SetPermissions(PathBuf, u32),
+    SetTimes(PathBuf),
+    SyncAll(PathBuf),
+    SyncData(PathBuf),
+    CopyFile(PathBuf, PathBuf, Overwrite),
+    CopyRecursive(PathBuf, PathBuf, Overwrite),
+    MoveRecursive(PathBuf, PathBuf, Overwrite),
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Call::ReadDir(p) => write!(f, "read_dir({:?})", p),
+            Call::Reader(p) => write!(f, "reader({:?})", p),
+            Call::ReadAllLines(p) => write!(f, "read_all_lines({:?})", p),
+            Call::Metadata(p) => write!(f, "metadata({:?})", p),
+            Call::Writer(p) => write!(f, "writer({:?})", p),
+            Call::CreateDir(p) => write!(f, "create_dir({:?})", p),
+            Call::CreateDirAll(p) => write!(f, "create_dir_all({:?})", p),
+            Call::RemoveFile(p) => write!(f, "remove_file({:?})", p),
+            Call::RemoveDirAll(p) => write!(f, "remove_dir_all({:?})", p),
+            Call::Rename(from, to) => write!(f, "rename({:?}, {:?})", from, to),
+            Call::SetPermissions(p, mode) => write!(f, "set_permissions({:?}, {:#o})", p, mode),
+            Call::SetTimes(p) => write!(f, "set_times({:?}, ..)", p),
+            Call::SyncAll(p) => write!(f, "sync_all({:?})", p),
+            Call::SyncData(p) => write!(f, "sync_data({:?})", p),
+            Call::CopyFile(from, to, overwrite) => {
+                write!(f, "copy_file({:?}, {:?}, {:?})", from, to, overwrite)
+            }
+            Call::CopyRecursive(from, to, overwrite) => {
+                write!(f, "copy_recursive({:?}, {:?}, {:?})", from, to, overwrite)
+            }
+            Call::MoveRecursive(from, to, overwrite) => {
+                write!(f, "move_recursive({:?}, {:?}, {:?})", from, to, overwrite)
+            }
+        }
+    }
+}
+
+enum Response {
+    Contents(Vec<u8>),
+    Metadata { is_dir: bool },
+    Entries(Vec<String>),
+    Error(XfsError),
+}
+
+/// The data written through a writer returned for an expected call.
+type Written = Arc<Mutex<Vec<u8>>>;
+
+struct ExpectedCall {
+    call: Call,
+    response: Option<Response>,
+    expected_contents: Option<Vec<u8>>,
+    written: Option<Written>,
+    satisfied: bool,
+}
+
+#[derive(Default)]
+struct State {
+    expectations: Vec<ExpectedCall>,
+}
+
+impl State {
+    fn describe_remaining(&self) -> String {
+        let remaining: Vec<String> = self
+            .expectations
+            .iter()
+            .filter(|e| !e.satisfied)
+            .map(|e| format!("  {}\n", e.call))
+            .collect();
+        if remaining.is_empty() {
+            "  (none)\n".to_string()
+        } else {
+            remaining.concat()
+        }
+    }
+
+    /// Describes every way in which the expectations have not been met, or
+    /// returns `None` if they all have.
+    fn failures(&self) -> Option<String> {
+        let mut out = String::new();
+        for e in self.expectations.iter().filter(|e| !e.satisfied) {
+            out.push_str(&format!("  expected call never made: {}\n", e.call));
+        }
+        for e in &self.expectations {
+            let (expected, written) = match (&e.expected_contents, &e.written) {
+                (Some(expected), Some(written)) => (expected, written.lock().unwrap()),
+                _ => continue,
+            };
+            if *expected != *written {
+                out.push_str(&format!(
+                    "  {} wrote {:?}, expected {:?}\n",
+                    e.call,
+                    String::from_utf8_lossy(&written),
+                    String::from_utf8_lossy(expected)
+                ));
+            }
+        }
+        if out.is_empty() {
+            None
+        } else {
+            Some(out)
+        }
+    }
+}
+
+/// A mock filesystem that only accepts scripted calls.
+///
+/// Handles created with `unsafe_clone` and `unsafe_clone_mut` share the same
+/// expectations, which are verified when the last handle is dropped.
+pub struct StrictFs {
+    state: Arc<Mutex<State>>,
+}
+
+/// A single expected call, returned by the `expect_*` methods of `StrictFs` so
+/// that its response can be configured.
+pub struct Expectation<'a> {
+    fs: &'a StrictFs,
+    index: usize,
+}
+
+impl<'a> Expectation<'a> {
+    fn update(
+        self,
+        name: &str,
+        allowed: fn(&Call) -> bool,
+        f: impl FnOnce(&mut ExpectedCall),
+    ) -> Self {
+        {
+            let mut state = self.fs.lock();
+            let e = &mut state.expectations[self.index];
+            if !allowed(&e.call) {
+                let call = e.call.to_string();
+                drop(state);
+                panic!("{} cannot be used with an expectation of {}", name, call);
+            }
+            f(e);
+        }
+        self
+    }
+
+    /// Sets the contents returned by an expected `reader` or `read_all_lines`
+    /// call. Without this an empty file is returned.
+    pub fn returning<C: IntoMockContents>(self, contents: C) -> Self {
+        let contents = contents.into_mock_contents();
+        self.update(
+            "returning",
+            |c| matches!(c, Call::Reader(_) | Call::ReadAllLines(_)),
+            |e| e.response = Some(Response::Contents(contents)),
+        )
+    }
+
+    /// Makes an expected `metadata` call report a file.
+    pub fn returning_file(self) -> Self {
+        self.update(
+            "returning_file",
+            |c| matches!(c, Call::Metadata(_)),
+            |e| e.response = Some(Response::Metadata { is_dir: false }),
+        )
+    }
+
+    /// Makes an expected `metadata` call report a directory.
+    pub fn returning_dir(self) -> Self {
+        self.update(
+            "returning_dir",
+            |c| matches!(c, Call::Metadata(_)),
+            |e| e.response = Some(Response::Metadata { is_dir: true }),
+        )
+    }
+
+    /// Sets the entries listed by an expected `read_dir` call. Names ending in
+    /// `/` are reported as directories and all others as files. Without this
+    /// the directory is empty.
+    pub fn returning_entries<I, S>(self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let names = names.into_iter().map(|s| s.as_ref().to_string()).collect();
+        self.update(
+            "returning_entries",
+            |c| matches!(c, Call::ReadDir(_)),
+            |e| e.response = Some(Response::Entries(names)),
+        )
+    }
+
+    /// Makes the expected call fail with `error`.
+    pub fn returning_error(self, error: XfsError) -> Self {
+        self.update(
+            "returning_error",
+            |_| true,
+            |e| e.response = Some(Response::Error(error)),
+        )
+    }
+
+    /// Requires that everything written through the writer returned by an
+    /// expected `writer` call equals `contents`, checked on verification.
+    pub fn expecting_contents<C: IntoMockContents>(self, contents: C) -> Self {
+        let contents = contents.into_mock_contents();
+        self.update(
+            "expecting_contents",
+            |c| matches!(c, Call::Writer(_)),
+            |e| e.expected_contents = Some(contents),
+        )
+    }
+}
+
+impl StrictFs {
+    /// Creates a filesystem with no expectations, on which every call fails.
+    pub fn new() -> StrictFs {
+        StrictFs {
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    fn share(&self) -> StrictFs {
+        StrictFs {
+            state: self.state.clone(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic from an unexpected call never holds the lock, but a panic
+        // in user code might, so poisoning is ignored.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn expect(&self, call: Call) -> Expectation<'_> {
+        let mut state = self.lock();
+        state.expectations.push(ExpectedCall {
+            call,
+            response: None,
+            expected_contents: None,
+            written: None,
+            satisfied: false,
+        });
+        Expectation {
+            fs: self,
+            index: state.expectations.len() - 1,
+        }
+    }
+
+    pub fn expect_read_dir<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::ReadDir(p.as_ref().to_path_buf()))
+    }
+
+    pub fn expect_reader<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::Reader(p.as_ref().to_path_buf()))
+    }
+
+    pub fn expect_read_all_lines<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::ReadAllLines(p.as_ref().to_path_buf()))
+    }
+
+    /// Expects a `metadata` call, which must be given a response with
+    /// `returning_file`, `returning_dir` or `returning_error`.
+    pub fn expect_metadata<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::Metadata(p.as_ref().to_path_buf()))
+    }
+
+    pub fn expect_writer<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::Writer(p.as_ref().to_path_buf()))
+    }
+
+    pub fn expect_create_dir<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::CreateDir(p.as_ref().to_path_buf()))
+    }
+
+    pub fn expect_create_dir_all<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::CreateDirAll(p.as_ref().to_path_buf()))
+    }
+
+    pub fn expect_remove_file<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::RemoveFile(p.as_ref().to_path_buf()))
+    }
+
+    pub fn expect_remove_dir_all<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::RemoveDirAll(p.as_ref().to_path_buf()))
+    }
+
+    pub fn expect_rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Expectation<'_> {
+        self.expect(Call::Rename(
+            from.as_ref().to_path_buf(),
+            to.as_ref().to_path_buf(),
+        ))
+    }
+
+    pub fn expect_set_permissions<P: AsRef<Path>>(&self, p: P, mode: u32) -> Expectation<'_> {
+        self.expect(Call::SetPermissions(p.as_ref().to_path_buf(), mode))
+    }
+
+    /// Expects a `set_times` call on `p`, whatever times it sets.
+    pub fn expect_set_times<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::SetTimes(p.as_ref().to_path_buf()))
+    }
+
+    pub fn expect_sync_all<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::SyncAll(p.as_ref().to_path_buf()))
+    }
+
+    pub fn expect_sync_data<P: AsRef<Path>>(&self, p: P) -> Expectation<'_> {
+        self.expect(Call::SyncData(p.as_ref().to_path_buf()))
+    }
+
+    pub fn expect_copy_file<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+        overwrite: Overwrite,
+    ) -> Expectation<'_> {
+        self.expect(Call::CopyFile(
+            from.as_ref().to_path_buf(),
+            to.as_ref().to_path_buf(),
+            overwrite,
+        ))
+    }
+
+    pub fn expect_copy_recursive<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+        overwrite: Overwrite,
+    ) -> Expectation<'_> {
+        self.expect(Call::CopyRecursive(
+            from.as_ref().to_path_buf(),
+            to.as_ref().to_path_buf(),
+            overwrite,
+        ))
+    }
+
+    pub fn expect_move_recursive<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+        overwrite: Overwrite,
+    ) -> Expectation<'_> {
+        self.expect(Call::MoveRecursive(
+            from.as_ref().to_path_buf(),
+            to.as_ref().to_path_buf(),
+            overwrite,
+        ))
+    }
+
+    /// Checks that every expectation has been satisfied and that every
+    /// expected writer received the expected contents.
+    ///
+    /// # Panics
+    ///
+    /// Panics listing every unmet expectation.
+    pub fn verify(&self) {
+        let failures = self.lock().failures();
+        if let Some(failures) = failures {
+            panic!("StrictFs expectations not met:\n{}", failures);
+        }
+    }
+
+    /// Consumes the first unsatisfied expectation matching `call`, panicking
+    /// if there is none.
+    fn consume(&self, call: Call) -> (Option<Response>, Option<Written>) {
+        let mut state = self.lock();
+        let found = state
+            .expectations
+            .iter_mut()
+            .find(|e| !e.satisfied && e.call == call);
+        match found {
+            Some(e) => {
+                e.satisfied = true;
+                if let Call::Writer(_) = call {
+                    e.written = Some(Arc::new(Mutex::new(vec![])));
+                }
+                (e.response.take(), e.written.clone())
+            }
+            None => {
+                let remaining = state.describe_remaining();
+                drop(state);
+                panic!(
+                    "unexpected call to StrictFs: {}\nremaining expectations:\n{}",
+                    call, remaining
+                );
+            }
+        }
+    }
+
+    /// Handles a call whose only possible responses are success or an error.
+    fn unit(&self, call: Call) -> Result<()> {
+        match self.consume(call).0 {
+            Some(Response::Error(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn contents(&self, call: Call) -> Result<Vec<u8>> {
+        match self.consume(call).0 {
+            Some(Response::Error(e)) => Err(e),
+            Some(Response::Contents(data)) => Ok(data),
+            _ => Ok(vec![]),
+        }
+    }
+}
+
+impl Default for StrictFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for StrictFs {
+    fn drop(&mut self) {
+        if Arc::strong_count(&self.state) == 1 && !std::thread::panicking() {
+            self.verify();
+        }
+    }
+}
+
+struct StrictWriter {
+    data: Written,
+}
+
+impl Write for StrictWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.data.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Syncing a writer is not a call on the filesystem, so it needs no
+/// expectation.
+impl XfsWrite for StrictWriter {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct StrictMetadata {
+    is_dir: bool,
+}
+
+impl XfsMetadata for StrictMetadata {
+    fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+
+    fn size(&self) -> u64 {
+        0
+    }
+
+    fn mode(&self) -> u32 {
+        if self.is_dir {
+            0o755
+        } else {
+            0o644
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        None
+    }
+
+    fn accessed(&self) -> Option<SystemTime> {
+        None
+    }
+
+    fn created(&self) -> Option<SystemTime> {
+        None
+    }
+}
+
+struct StrictDirEntry {
+    path: PathBuf,
+    metadata: StrictMetadata,
+}
+
+impl XfsDirEntry for StrictDirEntry {
+    fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn metadata(&self) -> Result<Box<dyn XfsMetadata>> {
+        Ok(Box::new(self.metadata.clone()))
+    }
+}
+
+impl XfsReadOnly for StrictFs {
+    fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
+        Box::new(self.share())
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        // Calls are matched by path, so only paths that compare equal name
+        // the same entry.
+        p.components().collect()
+    }
+
+    fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
+        let names = match self.consume(Call::ReadDir(p.to_path_buf())).0 {
+            Some(Response::Error(e)) => return Err(e),
+            Some(Response::Entries(names)) => names,
+            _ => vec![],
+        };
+        let entries: Vec<XfsEntryResult> = names
+            .into_iter()
+            .map(|name| {
+                let (name, is_dir) = match name.strip_suffix('/') {
+                    Some(name) => (name.to_string(), true),
+                    None => (name, false),
+                };
+                let entry: Box<dyn XfsDirEntry> = Box::new(StrictDirEntry {
+                    path: p.join(name),
+                    metadata: StrictMetadata { is_dir },
+                });
+                Ok(entry)
+            })
+            .collect();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
+        let data = self.contents(Call::Reader(p.to_path_buf()))?;
+        Ok(Box::new(Cursor::new(data)))
+    }
+
+    fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
+        let data = self.contents(Call::ReadAllLines(p.to_path_buf()))?;
+        let s = String::from_utf8(data).map_err(|_| XfsError::InvalidUtf8 {
+            path: p.to_path_buf(),
+        })?;
+        Ok(s.lines().map(|s| s.to_string()).collect())
+    }
+
+    fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        let call = Call::Metadata(p.to_path_buf());
+        match self.consume(call.clone()).0 {
+            Some(Response::Error(e)) => Err(e),
+            Some(Response::Metadata { is_dir }) => Ok(Box::new(StrictMetadata { is_dir })),
+            _ => panic!(
+                "expectation of {} has no response; use returning_file, returning_dir or returning_error",
+                call
+            ),
+        }
+    }
+}
+
+impl Xfs for StrictFs {
+    fn unsafe_clone_mut(&mut self) -> Box<dyn Xfs> {
+        Box::new(self.share())
+    }
+
+    fn writer(&mut self, p: &Path) -> Result<Box<dyn XfsWrite>> {
+        match self.consume(Call::Writer(p.to_path_buf())) {
+            (Some(Response::Error(e)), _) => Err(e),
+            (_, Some(data)) => Ok(Box::new(StrictWriter { data })),
+            (_, None) => unreachable!("writer expectations always capture their data"),
+        }
+    }
+
+    fn create_dir(&mut self, p: &Path) -> Result<()> {
+        self.unit(Call::CreateDir(p.to_path_buf()))
+    }
+
+    fn create_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.unit(Call::CreateDirAll(p.to_path_buf()))
+    }
+
+    fn remove_file(&mut self, p: &Path) -> Result<()> {
+        self.unit(Call::RemoveFile(p.to_path_buf()))
+    }
+
+    fn remove_dir_all(&mut self, p: &Path) -> Result<()> {
+        self.unit(Call::RemoveDirAll(p.to_path_buf()))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        self.unit(Call::Rename(from.to_path_buf(), to.to_path_buf()))
+    }
+
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()> {
+        self.unit(Call::SetPermissions(p.to_path_buf(), mode))
+    }
+
+    fn set_times(
+        &mut self,
+        p: &Path,
+        _accessed: Option<SystemTime>,
+        _modified: Option<SystemTime>,
+    ) -> Result<()> {
+        self.unit(Call::SetTimes(p.to_path_buf()))
+    }
+
+    fn sync_all(&mut self, p: &Path) -> Result<()> {
+        self.unit(Call::SyncAll(p.to_path_buf()))
+    }
+
+    fn sync_data(&mut self, p: &Path) -> Result<()> {
+        self.unit(Call::SyncData(p.to_path_buf()))
+    }
+
+    fn copy_file(&mut self, from: &Path, to: &Path, overwrite: Overwrite) -> Result<()> {
+        self.unit(Call::CopyFile(
+            from.to_path_buf(),
+            to.to_path_buf(),
+            overwrite,
+        ))
+    }
+
+    fn copy_recursive(&mut self, from: &Path, to: &Path, overwrite: Overwrite) -> Result<()> {
+        self.unit(Call::CopyRecursive(
+            from.to_path_buf(),
+            to.to_path_buf(),
+            overwrite,
+        ))
+    }
+
+    fn move_recursive(&mut self, from: &Path, to: &Path, overwrite: Overwrite) -> Result<()> {
+        self.unit(Call::MoveRecursive(
+            from.to_path_buf(),
+            to.to_path_buf(),
+            overwrite,
+        ))
+    }
+}
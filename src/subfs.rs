@@ -0,0 +1,246 @@
+//! A wrapper exposing one directory of another filesystem as its root, like
+//! `chroot`.
+//!
+//! Paths are resolved lexically against the scope root, so `/a` and `a` both
+//! name `<root>/a`, and a `..` that would leave the scope fails with
+//! `PathOutsideSandbox`. Paths returned by `read_dir` and carried by errors
+//! are translated back into the scoped namespace.
+//!
+//! Symbolic links are followed by the wrapped filesystem and are not
+//! confined.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::Path;
+//! use inscenerator_xfs::mockfs::MockFS;
+//! use inscenerator_xfs::subfs::SubFs;
+//! use inscenerator_xfs::{Xfs, XfsError, XfsReadOnly};
+//!
+//! let mut inner = MockFS::new();
+//! inner.add_file(Path::new("/output/site/index.html"), "").unwrap();
+//! inner.add_file(Path::new("/secrets/key"), "").unwrap();
+//!
+//! let site = SubFs::new(Box::new(inner), Path::new("/output/site"));
+//! assert!(site.is_file(Path::new("/index.html")));
+//! assert!(matches!(
+//!     site.reader(Path::new("../../secrets/key")),
+//!     Err(XfsError::PathOutsideSandbox { .. })
+//! ));
+//! ```
+
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::{
+    PathOutsideSandboxSnafu, Result, Xfs, XfsDirEntry, XfsMetadata, XfsReadDir, XfsReadOnly,
+    XfsWrite,
+};
+
+/// Wraps a filesystem, exposing only the tree below one of its directories.
+///
+/// The scope root does not have to exist when the wrapper is created.
+pub struct SubFs<F: ?Sized> {
+    root: PathBuf,
+    inner: Box<F>,
+}
+
+impl<F: ?Sized> SubFs<F> {
+    pub fn new(inner: Box<F>, root: &Path) -> SubFs<F> {
+        SubFs {
+            root: root.to_path_buf(),
+            inner,
+        }
+    }
+
+    /// Returns the directory of the wrapped filesystem that is the root of
+    /// this view.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the wrapped filesystem.
+    pub fn into_inner(self) -> Box<F> {
+        self.inner
+    }
+
+    /// Maps a path in the scoped namespace to the wrapped filesystem.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PathOutsideSandbox` if `p` leaves the scope root through `..`.
+    pub fn to_inner(&self, p: &Path) -> Result<PathBuf> {
+        let mut rel = PathBuf::new();
+        for c in p.components() {
+            match c {
+                Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+                Component::ParentDir => {
+                    if !rel.pop() {
+                        return PathOutsideSandboxSnafu { path: p }.fail();
+                    }
+                }
+                Component::Normal(name) => rel.push(name),
+            }
+        }
+        Ok(self.root.join(rel))
+    }
+
+    /// Maps a path of the wrapped filesystem into the scoped namespace, or
+    /// returns `None` if it is outside the scope root.
+    fn to_scoped(&self, p: &Path) -> Option<PathBuf> {
+        p.strip_prefix(&self.root)
+            .ok()
+            .map(|rel| Path::new("/").join(rel))
+    }
+
+    fn scoped<T>(&self, r: Result<T>) -> Result<T> {
+        r.map_err(|e| e.map_path(|path| self.to_scoped(&path).unwrap_or(path)))
+    }
+}
+
+/// A directory entry whose path has been translated into the scoped
+/// namespace.
+struct SubDirEntry {
+    path: PathBuf,
+    inner: Box<dyn XfsDirEntry>,
+}
+
+impl XfsDirEntry for SubDirEntry {
+    fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn metadata(&self) -> Result<Box<dyn XfsMetadata>> {
+        self.inner.metadata()
+    }
+}
+
+impl<F: XfsReadOnly + ?Sized> XfsReadOnly for SubFs<F> {
+    fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
+        Box::new(SubFs {
+            root: self.root.clone(),
+            inner: self.inner.unsafe_clone(),
+        })
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        let inner = match self.to_inner(p) {
+            Ok(inner) => self.inner.canonical_path(&inner),
+            Err(_) => return p.to_path_buf(),
+        };
+        // Paths the wrapped filesystem resolves to outside the scope, such
+        // as through symbolic links, are left in its namespace.
+        match inner.strip_prefix(self.inner.canonical_path(&self.root)) {
+            Ok(rel) => Path::new("/").join(rel),
+            Err(_) => inner,
+        }
+    }
+
+    fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
+        let dir = self.to_inner(p)?;
+        let entries = self.scoped(self.inner.read_dir(&dir))?;
+        // Entries are listed under the path as given, as other backends do.
+        let scoped_dir = p.to_path_buf();
+        let entries = entries.map(move |entry| {
+            let entry = entry?;
+            let inner_path = entry.path();
+            let path = match inner_path.strip_prefix(&dir) {
+                Ok(rel) => scoped_dir.join(rel),
+                Err(_) => match inner_path.file_name() {
+                    Some(name) => scoped_dir.join(name),
+                    None => inner_path,
+                },
+            };
+            Ok(Box::new(SubDirEntry { path, inner: entry }) as Box<dyn XfsDirEntry>)
+        });
+        Ok(Box::new(entries))
+    }
+
+    fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
+        self.scoped(self.inner.reader(&self.to_inner(p)?))
+    }
+
+    fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
+        self.scoped(self.inner.read_all_lines(&self.to_inner(p)?))
+    }
+
+    fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        self.scoped(self.inner.metadata(&self.to_inner(p)?))
+    }
+}
+
+impl<F: Xfs + ?Sized> Xfs for SubFs<F> {
+    fn unsafe_clone_mut(&mut self) -> Box<dyn Xfs> {
+        Box::new(SubFs {
+            root: self.root.clone(),
+            inner: self.inner.unsafe_clone_mut(),
+        })
+    }
+
+    fn writer(&mut self, p: &Path) -> Result<Box<dyn XfsWrite>> {
+        let p = self.to_inner(p)?;
+        let r = self.inner.writer(&p);
+        self.scoped(r)
+    }
+
+    fn create_dir(&mut self, p: &Path) -> Result<()> {
+        let p = self.to_inner(p)?;
+        let r = self.inner.create_dir(&p);
+        self.scoped(r)
+    }
+
+    fn create_dir_all(&mut self, p: &Path) -> Result<()> {
+        let p = self.to_inner(p)?;
+        let r = self.inner.create_dir_all(&p);
+        self.scoped(r)
+    }
+
+    fn remove_file(&mut self, p: &Path) -> Result<()> {
+        let p = self.to_inner(p)?;
+        let r = self.inner.remove_file(&p);
+        self.scoped(r)
+    }
+
+    fn remove_dir_all(&mut self, p: &Path) -> Result<()> {
+        let p = self.to_inner(p)?;
+        let r = self.inner.remove_dir_all(&p);
+        self.scoped(r)
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let from = self.to_inner(from)?;
+        let to = self.to_inner(to)?;
+        let r = self.inner.rename(&from, &to);
+        self.scoped(r)
+    }
+
+    fn set_permissions(&mut self, p: &Path, mode: u32) -> Result<()> {
+        let p = self.to_inner(p)?;
+        let r = self.inner.set_permissions(&p, mode);
+        self.scoped(r)
+    }
+
+    fn set_times(
+        &mut self,
+        p: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<()> {
+        let p = self.to_inner(p)?;
+        let r = self.inner.set_times(&p, accessed, modified);
+        self.scoped(r)
+    }
+
+    fn sync_all(&mut self, p: &Path) -> Result<()> {
+        let p = self.to_inner(p)?;
+        let r = self.inner.sync_all(&p);
+        self.scoped(r)
+    }
+
+    fn sync_data(&mut self, p: &Path) -> Result<()> {
+        let p = self.to_inner(p)?;
+        let r = self.inner.sync_data(&p);
+        self.scoped(r)
+    }
+}
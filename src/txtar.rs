@@ -0,0 +1,272 @@
+//! A plain-text format for multi-file fixtures, modelled on Go's txtar.
+//!
+//! An archive is an optional comment followed by a sequence of files. Each file
+//! starts with a header line `-- path --` and its contents are all the lines up
+//! to the next header. A header whose path ends in `/` and which has no
+//! contents denotes an empty directory.
+//!
+//! ```text
+//! This comment is ignored when loading.
+//! -- src/main.rs --
+//! fn main() {}
+//! -- README.md --
+//! # Hello
+//! -- target/ --
+//! ```
+//!
+//! Formatting adds a trailing newline to any non-empty file that lacks one, so
+//! such files do not round-trip exactly. Only UTF-8 contents can be stored,
+//! and contents with a line that looks like a header cannot be stored at all,
+//! as it would be read back as the start of another file.
+
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::mockfs::MockFS;
+use crate::{GeneralSnafu, InvalidUtf8Snafu, Result, Xfs, XfsError, XfsReadOnly};
+
+/// A parsed txtar archive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Archive {
+    pub comment: String,
+    pub files: Vec<ArchiveFile>,
+}
+
+/// A single file, or empty directory, in an `Archive`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveFile {
+    pub name: String,
+    pub data: String,
+}
+
+impl ArchiveFile {
+    /// Returns true if this entry denotes an empty directory.
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/') && self.data.is_empty()
+    }
+}
+
+fn header_name(line: &str) -> Option<&str> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let name = line.strip_prefix("-- ")?.strip_suffix(" --")?.trim();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+impl Archive {
+    /// Parses an archive. Parsing never fails; text that is not a header is
+    /// part of either the comment or the preceding file.
+    pub fn parse(s: &str) -> Archive {
+        let mut archive = Archive::default();
+        let mut current: Option<ArchiveFile> = None;
+        for line in s.split_inclusive('\n') {
+            if let Some(name) = header_name(line) {
+                archive.files.extend(current.take());
+                current = Some(ArchiveFile {
+                    name: name.to_string(),
+                    data: String::new(),
+                });
+                continue;
+            }
+            match &mut current {
+                Some(f) => f.data.push_str(line),
+                None => archive.comment.push_str(line),
+            }
+        }
+        archive.files.extend(current);
+        archive
+    }
+
+    /// Formats the archive as text.
+    pub fn format(&self) -> String {
+        let mut s = String::new();
+        push_with_newline(&mut s, &self.comment);
+        for f in &self.files {
+            s.push_str(&format!("-- {} --\n", f.name));
+            push_with_newline(&mut s, &f.data);
+        }
+        s
+    }
+
+    /// Builds an archive from every file and empty directory below `root`.
+    ///
+    /// Entries are sorted by path so the output does not depend on the order
+    /// in which the filesystem lists directories.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any file or file name is not valid UTF-8, if any
+    /// file contains a line that looks like a header, or if reading fails.
+    pub fn from_fs(fs: &dyn XfsReadOnly, root: &Path) -> Result<Archive> {
+        Self::from_fs_with(fs, root, &|path, data| {
+            String::from_utf8(data).map_err(|_| XfsError::InvalidUtf8 {
+                path: path.to_path_buf(),
+            })
+        })
+    }
+
+    /// Like `from_fs`, but converts file contents to text using `to_text`,
+    /// which may choose to accept binary data.
+    pub(crate) fn from_fs_with(
+        fs: &dyn XfsReadOnly,
+        root: &Path,
+        to_text: &dyn Fn(&Path, Vec<u8>) -> Result<String>,
+    ) -> Result<Archive> {
+        let mut files = vec![];
+        collect(fs, root, root, to_text, &mut files)?;
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Archive {
+            comment: String::new(),
+            files,
+        })
+    }
+
+    /// Writes every entry of the archive into `fs` below `root`, creating
+    /// parent directories as needed and replacing existing files.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeneralError`, before writing anything, if any entry name is
+    /// absolute or contains `.` or `..` components, as it could otherwise be
+    /// written outside `root`.
+    pub fn write_to(&self, fs: &mut dyn Xfs, root: &Path) -> Result<()> {
+        if let Some(f) = self.files.iter().find(|f| {
+            !Path::new(&f.name)
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+        }) {
+            return GeneralSnafu {
+                message: format!("invalid txtar entry name {:?}", f.name),
+            }
+            .fail();
+        }
+        for f in &self.files {
+            let path = root.join(&f.name);
+            if f.is_dir() {
+                fs.create_dir_all(&path)?;
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                fs.create_dir_all(parent)?;
+            }
+            let mut w = fs.writer(&path)?;
+            w.write_all(f.data.as_bytes())
+                .and_then(|_| w.flush())
+                .map_err(|e| XfsError::IoError {
+                    path: path.clone(),
+                    source: e,
+                })?;
+        }
+        Ok(())
+    }
+}
+
+fn push_with_newline(s: &mut String, data: &str) {
+    s.push_str(data);
+    if !data.is_empty() && !data.ends_with('\n') {
+        s.push('\n');
+    }
+}
+
+fn relative_name(root: &Path, p: &Path) -> Result<String> {
+    let rel = p.strip_prefix(root).unwrap_or(p);
+    let parts: Option<Vec<&str>> = rel.iter().map(|c| c.to_str()).collect();
+    match parts {
+        Some(parts) => Ok(parts.join("/")),
+        None => InvalidUtf8Snafu { path: p }.fail(),
+    }
+}
+
+fn collect(
+    fs: &dyn XfsReadOnly,
+    root: &Path,
+    dir: &Path,
+    to_text: &dyn Fn(&Path, Vec<u8>) -> Result<String>,
+    files: &mut Vec<ArchiveFile>,
+) -> Result<()> {
+    let mut empty = true;
+    for de in fs.read_dir(dir)? {
+        empty = false;
+        let de = de?;
+        let path: PathBuf = de.path();
+        if de.metadata()?.is_dir() {
+            collect(fs, root, &path, to_text, files)?;
+            continue;
+        }
+        let mut data = vec![];
+        fs.reader(&path)?
+            .read_to_end(&mut data)
+            .map_err(|e| XfsError::IoError {
+                path: path.clone(),
+                source: e,
+            })?;
+        let data = to_text(&path, data)?;
+        if let Some(line) = data
+            .split_inclusive('\n')
+            .find(|l| header_name(l).is_some())
+        {
+            return Err(XfsError::GeneralError {
+                message: format!(
+                    "{} contains the line {:?}, which would be read back as a txtar header",
+                    path.display(),
+                    line.trim_end()
+                ),
+            });
+        }
+        files.push(ArchiveFile {
+            name: relative_name(root, &path)?,
+            data,
+        });
+    }
+    if empty && dir != root {
+        files.push(ArchiveFile {
+            name: format!("{}/", relative_name(root, dir)?),
+            data: String::new(),
+        });
+    }
+    Ok(())
+}
+
+impl MockFS {
+    /// Builds a filesystem from a txtar archive.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use inscenerator_xfs::mockfs::MockFS;
+    /// use inscenerator_xfs::XfsReadOnly;
+    ///
+    /// let fs = MockFS::from_txtar(
+    ///     "-- src/main.rs --\n\
+    ///      fn main() {}\n\
+    ///      -- out/ --\n",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(fs.get_str(Path::new("src/main.rs")).unwrap(), "fn main() {}\n");
+    /// assert!(fs.is_dir(Path::new("out")));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the archive contains conflicting or invalid paths.
+    pub fn from_txtar(s: &str) -> Result<MockFS> {
+        let mut fs = MockFS::new();
+        Archive::parse(s).write_to(&mut fs, Path::new(""))?;
+        Ok(fs)
+    }
+
+    /// Formats the whole filesystem as a txtar archive.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any file or file name is not valid UTF-8, or if any
+    /// file contains a line that looks like a header.
+    pub fn to_txtar(&self) -> Result<String> {
+        Ok(Archive::from_fs(self, Path::new(""))?.format())
+    }
+}
@@ -0,0 +1,131 @@
+//! A read-only filesystem layering several others, as used for theme
+//! overrides.
+//!
+//! Layers are given in order of precedence. Each path resolves to the first
+//! layer containing it, and listing a directory merges the entries of every
+//! layer in which the path is a directory, with earlier layers hiding later
+//! entries of the same name. All layers share one namespace; wrap a layer in
+//! a `SubFs` to use one of its directories.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::{Path, PathBuf};
+//! use inscenerator_xfs::mockfs::MockFS;
+//! use inscenerator_xfs::union::UnionFs;
+//! use inscenerator_xfs::XfsReadOnly;
+//!
+//! let mut project = MockFS::new();
+//! project.add_file(Path::new("/page.html"), "custom").unwrap();
+//! let mut theme = MockFS::new();
+//! theme.add_file(Path::new("/page.html"), "default").unwrap();
+//! theme.add_file(Path::new("/base.html"), "base").unwrap();
+//!
+//! let fs = UnionFs::new(vec![Box::new(project), Box::new(theme)]);
+//! assert_eq!(fs.read_all_lines(Path::new("/page.html")).unwrap(), vec!["custom"]);
+//! assert_eq!(fs.layer_of(Path::new("/base.html")).unwrap(), 1);
+//! ```
+
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::{copy, NotFoundSnafu, Result, XfsMetadata, XfsReadDir, XfsReadOnly};
+
+/// Layers read-only filesystems, resolving each path to the first layer
+/// containing it.
+pub struct UnionFs {
+    layers: Vec<Box<dyn XfsReadOnly>>,
+}
+
+impl UnionFs {
+    /// Creates a union of `layers`, the first taking precedence.
+    pub fn new(layers: Vec<Box<dyn XfsReadOnly>>) -> UnionFs {
+        UnionFs { layers }
+    }
+
+    /// Returns the number of layers.
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Returns the index of the layer that serves `p`, with its metadata.
+    fn resolve(&self, p: &Path) -> Result<(usize, Box<dyn XfsMetadata>)> {
+        let mut first_error = None;
+        for (i, layer) in self.layers.iter().enumerate() {
+            match layer.metadata(p) {
+                Ok(md) => return Ok((i, md)),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => NotFoundSnafu { path: p }.fail(),
+        }
+    }
+
+    /// Returns the index of the layer that serves `p`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first layer's error if no layer contains `p`.
+    pub fn layer_of(&self, p: &Path) -> Result<usize> {
+        Ok(self.resolve(p)?.0)
+    }
+}
+
+impl XfsReadOnly for UnionFs {
+    fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
+        Box::new(UnionFs {
+            layers: self.layers.iter().map(|l| l.unsafe_clone()).collect(),
+        })
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        // The layers share one namespace, so the first layer's resolution
+        // stands for all of them.
+        match self.layers.first() {
+            Some(layer) => layer.canonical_path(p),
+            None => copy::normalize(p),
+        }
+    }
+
+    fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
+        let (first, md) = self.resolve(p)?;
+        if !md.is_dir() {
+            return self.layers[first].read_dir(p);
+        }
+        let mut seen = HashSet::new();
+        let mut entries = vec![];
+        for layer in &self.layers[first..] {
+            if !layer.is_dir(p) {
+                continue;
+            }
+            for entry in layer.read_dir(p)? {
+                if let Ok(entry) = &entry {
+                    if !seen.insert(entry.path().file_name().map(|n| n.to_os_string())) {
+                        continue;
+                    }
+                }
+                entries.push(entry);
+            }
+        }
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
+        let (i, _) = self.resolve(p)?;
+        self.layers[i].reader(p)
+    }
+
+    fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
+        let (i, _) = self.resolve(p)?;
+        self.layers[i].read_all_lines(p)
+    }
+
+    fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        Ok(self.resolve(p)?.1)
+    }
+}
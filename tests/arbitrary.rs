@@ -0,0 +1,118 @@
+#![cfg(feature = "proptest")]
+
+use inscenerator_xfs::arbitrary::{tree, FsTree, TreeConfig};
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::XfsReadOnly;
+use proptest::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Walks the filesystem, returning (depth, fan-out, is_dir, size) for each entry.
+fn walk(fs: &dyn XfsReadOnly, dir: &Path, depth: usize, out: &mut Vec<(usize, usize, bool)>) {
+    let entries: Vec<PathBuf> = fs
+        .read_dir(dir)
+        .unwrap()
+        .map(|de| de.unwrap().path())
+        .collect();
+    out.push((depth, entries.len(), true));
+    for p in entries {
+        if fs.is_dir(&p) {
+            walk(fs, &p, depth + 1, out);
+        } else {
+            out.push((depth + 1, 0, false));
+        }
+    }
+}
+
+fn small_config() -> TreeConfig {
+    TreeConfig {
+        max_depth: 2,
+        max_fan_out: 3,
+        max_file_size: 16,
+        ..TreeConfig::default()
+    }
+}
+
+proptest! {
+    #[test]
+    fn generated_trees_respect_limits(fs in any_with::<MockFS>(small_config())) {
+        let mut entries = vec![];
+        walk(&fs, Path::new("/"), 0, &mut entries);
+        for (depth, fan_out, is_dir) in entries {
+            prop_assert!(fan_out <= 3);
+            if is_dir {
+                prop_assert!(depth <= 2);
+            } else {
+                prop_assert!(depth <= 3);
+            }
+        }
+    }
+
+    #[test]
+    fn generated_files_respect_size(t in tree(small_config())) {
+        fn check(t: &FsTree) -> bool {
+            match t {
+                FsTree::File(data) => data.len() <= 16,
+                FsTree::Directory(entries) => entries.values().all(check),
+            }
+        }
+        prop_assert!(check(&t));
+    }
+
+    #[test]
+    fn mockfs_matches_tree(t in tree(TreeConfig::default())) {
+        let fs = t.to_mockfs();
+        fn check(fs: &MockFS, path: &Path, t: &FsTree) -> bool {
+            match t {
+                FsTree::File(data) => fs.get(path).unwrap() == *data,
+                FsTree::Directory(entries) => {
+                    fs.is_dir(path)
+                        && fs.read_dir(path).unwrap().count() == entries.len()
+                        && entries.iter().all(|(name, child)| check(fs, &path.join(name), child))
+                }
+            }
+        }
+        prop_assert!(check(&fs, Path::new("/"), &t));
+    }
+}
+
+#[test]
+fn test_flat_config_has_only_files() {
+    let config = TreeConfig {
+        max_depth: 0,
+        ..TreeConfig::default()
+    };
+    let mut runner = proptest::test_runner::TestRunner::deterministic();
+    for _ in 0..20 {
+        let t = tree(config.clone())
+            .new_tree(&mut runner)
+            .unwrap()
+            .current();
+        match t {
+            FsTree::Directory(entries) => {
+                assert!(entries.values().all(|e| matches!(e, FsTree::File(_))))
+            }
+            FsTree::File(_) => panic!("root must be a directory"),
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_names() {
+    let config = TreeConfig {
+        non_utf8_names: true,
+        ..TreeConfig::default()
+    };
+    let mut runner = proptest::test_runner::TestRunner::deterministic();
+    let found = (0..50).any(|_| {
+        let t = tree(config.clone())
+            .new_tree(&mut runner)
+            .unwrap()
+            .current();
+        match t {
+            FsTree::Directory(entries) => entries.keys().any(|k| k.to_str().is_none()),
+            FsTree::File(_) => false,
+        }
+    });
+    assert!(found, "expected some generated names to be invalid UTF-8");
+}
@@ -0,0 +1,139 @@
+use inscenerator_xfs::conformance::{self, Fixture};
+use inscenerator_xfs::dryrun::DryRunFs;
+use inscenerator_xfs::ifchanged::WriteIfChangedFs;
+use inscenerator_xfs::latency::{LatencyConfig, LatencyFs};
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::mount::MountFs;
+use inscenerator_xfs::outputs::OutputTrackingFs;
+use inscenerator_xfs::policy::{Policy, PolicyFs, Rules};
+use inscenerator_xfs::subfs::SubFs;
+use inscenerator_xfs::{OsFs, Xfs};
+use std::path::Path;
+
+#[cfg(unix)]
+fn osfs_fixture() -> Fixture {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path().to_path_buf();
+    // The alias reaches the root through a symbolic link.
+    let link_dir = tempfile::tempdir().unwrap();
+    let alias = link_dir.path().join("link");
+    std::os::unix::fs::symlink(&root, &alias).unwrap();
+    Fixture::new(Box::new(OsFs {}), root)
+        .with_alias(alias)
+        .with_guard((temp_dir, link_dir))
+}
+
+#[cfg(not(unix))]
+fn osfs_fixture() -> Fixture {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path().to_path_buf();
+    Fixture::new(Box::new(OsFs {}), root).with_guard(temp_dir)
+}
+
+fn mockfs_fixture() -> Fixture {
+    Fixture::new(Box::new(MockFS::new()), "/".into()).with_alias("".into())
+}
+
+fn mockfs_subdir_fixture() -> Fixture {
+    let mut fs = MockFS::new();
+    fs.create_dir_all(Path::new("work")).unwrap();
+    Fixture::new(Box::new(fs), "work".into()).with_alias("/work".into())
+}
+
+fn dryrun_fixture() -> Fixture {
+    let mut inner = MockFS::new();
+    inner.create_dir_all(Path::new("/work")).unwrap();
+    let fs = DryRunFs::new(Box::new(inner));
+    Fixture::new(Box::new(fs), "/work".into()).with_alias("work".into())
+}
+
+fn dryrun_osfs_fixture() -> Fixture {
+    let mut fixture = osfs_fixture();
+    fixture.fs = Box::new(DryRunFs::new(Box::new(OsFs {})));
+    fixture
+}
+
+fn ifchanged_fixture() -> Fixture {
+    let fs = WriteIfChangedFs::new(Box::new(MockFS::new()));
+    Fixture::new(Box::new(fs), "/".into()).with_alias("".into())
+}
+
+fn latency_fixture() -> Fixture {
+    let fs = LatencyFs::new(Box::new(MockFS::new()), LatencyConfig::default());
+    Fixture::new(Box::new(fs), "/".into()).with_alias("".into())
+}
+
+fn mount_fixture() -> Fixture {
+    let mut fs = MountFs::new();
+    fs.mount(Path::new("/"), Box::new(MockFS::new())).unwrap();
+    fs.mount(Path::new("/work"), Box::new(MockFS::new()))
+        .unwrap();
+    Fixture::new(Box::new(fs), "/work".into()).with_alias("work".into())
+}
+
+fn outputs_fixture() -> Fixture {
+    let fs = OutputTrackingFs::new(Box::new(MockFS::new()), Path::new("/"));
+    Fixture::new(Box::new(fs), "/".into()).with_alias("".into())
+}
+
+fn policy_fixture() -> Fixture {
+    let policy = Policy {
+        read: Rules::allow_all(),
+        write: Rules::allow_all(),
+        hide_denied: false,
+    };
+    let fs = PolicyFs::new(Box::new(MockFS::new()), policy);
+    Fixture::new(Box::new(fs), "/".into()).with_alias("".into())
+}
+
+fn subfs_fixture() -> Fixture {
+    let mut inner = MockFS::new();
+    inner.create_dir_all(Path::new("/output/site")).unwrap();
+    let fs = SubFs::new(Box::new(inner), Path::new("/output/site"));
+    Fixture::new(Box::new(fs), "/".into()).with_alias("".into())
+}
+
+mod osfs {
+    inscenerator_xfs::xfs_conformance_tests!(super::osfs_fixture);
+}
+
+mod mockfs {
+    inscenerator_xfs::xfs_conformance_tests!(super::mockfs_fixture);
+}
+
+mod dryrun {
+    inscenerator_xfs::xfs_conformance_tests!(super::dryrun_fixture);
+}
+
+mod dryrun_osfs {
+    inscenerator_xfs::xfs_conformance_tests!(super::dryrun_osfs_fixture);
+}
+
+mod ifchanged {
+    inscenerator_xfs::xfs_conformance_tests!(super::ifchanged_fixture);
+}
+
+mod latency {
+    inscenerator_xfs::xfs_conformance_tests!(super::latency_fixture);
+}
+
+mod mount {
+    inscenerator_xfs::xfs_conformance_tests!(super::mount_fixture);
+}
+
+mod outputs {
+    inscenerator_xfs::xfs_conformance_tests!(super::outputs_fixture);
+}
+
+mod policy {
+    inscenerator_xfs::xfs_conformance_tests!(super::policy_fixture);
+}
+
+mod subfs {
+    inscenerator_xfs::xfs_conformance_tests!(super::subfs_fixture);
+}
+
+#[test]
+fn test_run_all_mockfs_relative_root() {
+    conformance::run_all(&mockfs_subdir_fixture);
+}
@@ -0,0 +1,181 @@
+use std::path::Path;
+
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::mount::MountFs;
+use inscenerator_xfs::subfs::SubFs;
+use inscenerator_xfs::{OsFs, Overwrite, Xfs, XfsError, XfsReadOnly};
+
+fn mounted() -> (MountFs, MockFS, MockFS) {
+    let mut build = MockFS::new();
+    build
+        .add_file(Path::new("/site/index.html"), "index")
+        .unwrap();
+    build
+        .add_file(Path::new("/site/blog/post.html"), "post")
+        .unwrap();
+    let mut public = MockFS::new();
+    let mut fs = MountFs::new();
+    fs.mount(Path::new("/build"), build.unsafe_clone_mut())
+        .unwrap();
+    fs.mount(Path::new("/public"), public.unsafe_clone_mut())
+        .unwrap();
+    (fs, build, public)
+}
+
+#[test]
+fn test_move_recursive_across_mounts_copies_and_removes() {
+    let (mut fs, build, public) = mounted();
+    assert!(matches!(
+        fs.rename(Path::new("/build/site"), Path::new("/public/site")),
+        Err(XfsError::CrossesDevices { .. })
+    ));
+    fs.move_recursive(
+        Path::new("/build/site"),
+        Path::new("/public/site"),
+        Overwrite::Error,
+    )
+    .unwrap();
+    assert!(!build.exists(Path::new("/site")));
+    assert_eq!(
+        public
+            .read_all_lines(Path::new("/site/blog/post.html"))
+            .unwrap(),
+        vec!["post"]
+    );
+}
+
+#[test]
+fn test_copy_recursive_across_mounts() {
+    let (mut fs, build, public) = mounted();
+    fs.copy_recursive(
+        Path::new("/build/site"),
+        Path::new("/public/www"),
+        Overwrite::Error,
+    )
+    .unwrap();
+    assert!(build.is_file(Path::new("/site/index.html")));
+    assert!(public.is_file(Path::new("/www/index.html")));
+}
+
+#[test]
+fn test_trait_copy_recursive_on_mockfs() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/a/x.txt"), "x").unwrap();
+    fs.copy_recursive(Path::new("/a"), Path::new("/b"), Overwrite::Error)
+        .unwrap();
+    assert_eq!(fs.read_all_lines(Path::new("/b/x.txt")).unwrap(), vec!["x"]);
+}
+
+#[test]
+fn test_paths_are_compared_as_the_backend_resolves_them() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/a/x.txt"), "x").unwrap();
+    // On a MockFS relative paths are relative to the root, so `a/b` is
+    // inside `/a`.
+    assert!(matches!(
+        fs.copy_recursive(Path::new("/a"), Path::new("a/b"), Overwrite::Error),
+        Err(XfsError::GeneralError { .. })
+    ));
+    assert!(!fs.exists(Path::new("/a/b")));
+    assert!(matches!(
+        fs.move_recursive(Path::new("/a"), Path::new("a/./b"), Overwrite::Error),
+        Err(XfsError::GeneralError { .. })
+    ));
+    // Copying a file onto itself must not truncate it.
+    fs.copy_file(
+        Path::new("/a/x.txt"),
+        Path::new("a/x.txt"),
+        Overwrite::Merge,
+    )
+    .unwrap();
+    fs.move_recursive(Path::new("/a"), Path::new("a"), Overwrite::Error)
+        .unwrap();
+    assert_eq!(fs.read_all_lines(Path::new("/a/x.txt")).unwrap(), vec!["x"]);
+}
+
+#[test]
+fn test_move_recursive_merge_replaces_files_across_mounts() {
+    let (mut fs, build, public) = mounted();
+    fs.create_dir_all(Path::new("/public/site")).unwrap();
+    fs.writer(Path::new("/public/site/index.html")).unwrap();
+    fs.writer(Path::new("/public/site/extra.html")).unwrap();
+    fs.move_recursive(
+        Path::new("/build/site"),
+        Path::new("/public/site"),
+        Overwrite::Merge,
+    )
+    .unwrap();
+    assert!(!build.exists(Path::new("/site")));
+    assert_eq!(
+        public
+            .read_all_lines(Path::new("/site/index.html"))
+            .unwrap(),
+        vec!["index"]
+    );
+    assert!(public.is_file(Path::new("/site/extra.html")));
+    assert!(public.is_file(Path::new("/site/blog/post.html")));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_copy_recursive_does_not_follow_directory_links() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path();
+    std::fs::create_dir_all(root.join("src/a")).unwrap();
+    std::fs::write(root.join("src/a/x.txt"), "x").unwrap();
+    std::os::unix::fs::symlink("..", root.join("src/a/loop")).unwrap();
+    std::os::unix::fs::symlink("x.txt", root.join("src/a/link.txt")).unwrap();
+
+    let mut fs = OsFs {};
+    fs.copy_recursive(&root.join("src"), &root.join("dst"), Overwrite::Error)
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(root.join("dst/a/x.txt")).unwrap(),
+        "x"
+    );
+    assert_eq!(
+        std::fs::read_to_string(root.join("dst/a/link.txt")).unwrap(),
+        "x"
+    );
+    let copied_link = std::fs::symlink_metadata(root.join("dst/a/loop")).unwrap();
+    assert!(copied_link.is_dir());
+    assert_eq!(
+        std::fs::read_dir(root.join("dst/a/loop")).unwrap().count(),
+        0
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_move_recursive_across_mounts_does_not_follow_directory_links() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path();
+    std::fs::create_dir_all(root.join("build/site/a")).unwrap();
+    std::fs::create_dir_all(root.join("public")).unwrap();
+    std::fs::write(root.join("build/site/a/x.txt"), "x").unwrap();
+    std::os::unix::fs::symlink("..", root.join("build/site/a/loop")).unwrap();
+
+    let mut fs = MountFs::new();
+    for name in ["build", "public"] {
+        let scoped = SubFs::new(Box::new(OsFs {}), &root.join(name));
+        fs.mount(&Path::new("/").join(name), Box::new(scoped))
+            .unwrap();
+    }
+    fs.move_recursive(
+        Path::new("/build/site"),
+        Path::new("/public/site"),
+        Overwrite::Error,
+    )
+    .unwrap();
+    assert!(!root.join("build/site").exists());
+    assert_eq!(
+        std::fs::read_to_string(root.join("public/site/a/x.txt")).unwrap(),
+        "x"
+    );
+    assert_eq!(
+        std::fs::read_dir(root.join("public/site/a/loop"))
+            .unwrap()
+            .count(),
+        0
+    );
+}
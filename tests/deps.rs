@@ -0,0 +1,222 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use inscenerator_xfs::clock::ManualClock;
+use inscenerator_xfs::deps::{Dependency, DependencySet, ReadTrackingFs};
+use inscenerator_xfs::hash::hash_bytes;
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::strict::StrictFs;
+use inscenerator_xfs::{Xfs, XfsReadOnly};
+
+fn site() -> MockFS {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/content/a.md"), "a").unwrap();
+    fs.add_file(Path::new("/content/b.md"), "b").unwrap();
+    fs.add_file(Path::new("/theme.css"), "css").unwrap();
+    fs
+}
+
+/// Reads through a tracking wrapper the way a page build would.
+fn build(fs: &MockFS) -> DependencySet {
+    let tracking = ReadTrackingFs::new(fs.unsafe_clone());
+    for entry in tracking.read_dir(Path::new("/content")).unwrap() {
+        let path = entry.unwrap().path();
+        let mut data = vec![];
+        tracking
+            .reader(&path)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+    }
+    assert!(!tracking.exists(Path::new("/content/_index.md")));
+    tracking.dependencies()
+}
+
+#[test]
+fn test_records_reads_listings_and_lookups() {
+    let fs = site();
+    let deps = build(&fs);
+    let recorded: Vec<(PathBuf, Dependency)> = deps
+        .iter()
+        .map(|(p, d)| (p.to_path_buf(), d.clone()))
+        .collect();
+    assert_eq!(
+        recorded,
+        vec![
+            (
+                PathBuf::from("/content"),
+                Dependency::Listing {
+                    names: vec!["a.md".to_string(), "b.md".to_string()]
+                }
+            ),
+            (PathBuf::from("/content/_index.md"), Dependency::Missing),
+            (
+                PathBuf::from("/content/a.md"),
+                Dependency::Contents {
+                    hash: hash_bytes(b"a")
+                }
+            ),
+            (
+                PathBuf::from("/content/b.md"),
+                Dependency::Contents {
+                    hash: hash_bytes(b"b")
+                }
+            ),
+        ]
+    );
+    assert!(deps.is_valid(&fs));
+    assert!(deps.stale(&fs).is_empty());
+}
+
+#[test]
+fn test_changes_invalidate_dependencies() {
+    let mut fs = site();
+    let deps = build(&fs);
+
+    let mut changed = fs.unsafe_clone_mut();
+    // Files that were not read do not matter.
+    changed
+        .writer(Path::new("/theme.css"))
+        .unwrap()
+        .write_all(b"new")
+        .unwrap();
+    assert!(deps.is_valid(&fs));
+
+    changed
+        .writer(Path::new("/content/a.md"))
+        .unwrap()
+        .write_all(b"changed")
+        .unwrap();
+    assert_eq!(deps.stale(&fs), vec![PathBuf::from("/content/a.md")]);
+
+    changed
+        .writer(Path::new("/content/_index.md"))
+        .unwrap()
+        .write_all(b"")
+        .unwrap();
+    assert_eq!(
+        deps.stale(&fs),
+        vec![
+            PathBuf::from("/content"),
+            PathBuf::from("/content/_index.md"),
+            PathBuf::from("/content/a.md"),
+        ]
+    );
+    assert!(!deps.is_valid(&fs));
+}
+
+#[test]
+fn test_failed_reads_record_what_was_found() {
+    let fs = site();
+    let tracking = ReadTrackingFs::new(fs.unsafe_clone());
+    assert!(tracking.reader(Path::new("/content")).is_err());
+    assert!(tracking.read_dir(Path::new("/theme.css")).is_err());
+    assert!(tracking.read_all_lines(Path::new("/missing")).is_err());
+    let deps = tracking.take_dependencies();
+    assert_eq!(
+        deps.iter().collect::<Vec<_>>(),
+        vec![
+            (Path::new("/content"), &Dependency::Kind { is_dir: true }),
+            (Path::new("/missing"), &Dependency::Missing),
+            (Path::new("/theme.css"), &Dependency::Kind { is_dir: false }),
+        ]
+    );
+    assert!(tracking.dependencies().is_empty());
+}
+
+#[test]
+fn test_read_all_lines_hashes_the_whole_file() {
+    let mut fs = site();
+    let tracking = ReadTrackingFs::new(fs.unsafe_clone());
+    tracking.read_all_lines(Path::new("/theme.css")).unwrap();
+
+    // Changing only the line endings changes the dependency.
+    let mut changed = fs.unsafe_clone_mut();
+    changed
+        .writer(Path::new("/theme.css"))
+        .unwrap()
+        .write_all(b"css\n")
+        .unwrap();
+    assert!(!tracking.dependencies().is_valid(&fs));
+}
+
+#[test]
+fn test_read_all_lines_reads_the_file_once() {
+    // A second read would be an unexpected call, and could see different
+    // contents than the lines returned.
+    let inner = StrictFs::new();
+    inner.expect_reader("/a.txt").returning("one\r\ntwo");
+    let tracking = ReadTrackingFs::new(Box::new(inner));
+    assert_eq!(
+        tracking.read_all_lines(Path::new("/a.txt")).unwrap(),
+        vec!["one", "two"]
+    );
+    assert_eq!(
+        tracking.dependencies().iter().collect::<Vec<_>>(),
+        vec![(
+            Path::new("/a.txt"),
+            &Dependency::Contents {
+                hash: hash_bytes(b"one\r\ntwo")
+            }
+        )]
+    );
+}
+
+#[test]
+fn test_metadata_records_the_fields_read() {
+    let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+    let mut fs = MockFS::with_clock(clock.clone());
+    fs.add_file(Path::new("/content/a.md"), "a").unwrap();
+    fs.add_file(Path::new("/theme.css"), "css").unwrap();
+
+    let tracking = ReadTrackingFs::new(fs.unsafe_clone());
+    assert_eq!(
+        tracking.metadata(Path::new("/theme.css")).unwrap().size(),
+        3
+    );
+    for entry in tracking.read_dir(Path::new("/content")).unwrap() {
+        entry.unwrap().metadata().unwrap().modified();
+    }
+    let deps = tracking.dependencies();
+    let recorded: Vec<(&Path, &Dependency)> = deps.iter().collect();
+    assert_eq!(
+        recorded,
+        vec![
+            (
+                Path::new("/content"),
+                &Dependency::Listing {
+                    names: vec!["a.md".to_string()]
+                }
+            ),
+            (
+                Path::new("/content/a.md"),
+                &Dependency::Kind { is_dir: false }
+            ),
+            (
+                Path::new("/content/a.md"),
+                &Dependency::Modified {
+                    modified: Some(SystemTime::UNIX_EPOCH)
+                }
+            ),
+            (Path::new("/theme.css"), &Dependency::Kind { is_dir: false }),
+            (Path::new("/theme.css"), &Dependency::Size { size: 3 }),
+        ]
+    );
+
+    // Touching a file changes only its modification time.
+    clock.advance(Duration::from_secs(1));
+    fs.writer(Path::new("/content/a.md"))
+        .unwrap()
+        .write_all(b"a")
+        .unwrap();
+    fs.writer(Path::new("/theme.css"))
+        .unwrap()
+        .write_all(b"body")
+        .unwrap();
+    assert_eq!(
+        deps.stale(&fs),
+        vec![PathBuf::from("/content/a.md"), PathBuf::from("/theme.css")]
+    );
+}
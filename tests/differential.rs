@@ -0,0 +1,144 @@
+//! Differential testing of `MockFS` against `OsFs`.
+//!
+//! Random sequences of operations are applied to a `MockFS` and to an `OsFs`
+//! rooted in a temporary directory. Every operation must produce the same
+//! result on both, and both must end up with the same tree.
+
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::snapshot::render;
+use inscenerator_xfs::{OsFs, Xfs, XfsError};
+use proptest::prelude::*;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+enum Op {
+    Write(String, String),
+    Read(String),
+    ReadAllLines(String),
+    ReadDir(String),
+    Metadata(String),
+    CreateDir(String),
+    CreateDirAll(String),
+    RemoveFile(String),
+    RemoveDirAll(String),
+    Rename(String, String),
+}
+
+/// The observable outcome of an operation, comparable across backends.
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Ok,
+    Data(Vec<u8>),
+    Lines(Vec<String>),
+    Entries(Vec<(String, bool)>),
+    IsDir(bool),
+    Err(&'static str),
+}
+
+fn error_kind(e: &XfsError) -> &'static str {
+    match e {
+        XfsError::NotFound { .. } => "NotFound",
+        XfsError::AlreadyExists { .. } => "AlreadyExists",
+        XfsError::NotADirectory { .. } => "NotADirectory",
+        XfsError::NotAFile { .. } => "NotAFile",
+        XfsError::DirectoryNotEmpty { .. } => "DirectoryNotEmpty",
+        XfsError::PermissionDenied { .. } => "PermissionDenied",
+        XfsError::ReadOnlyFilesystem { .. } => "ReadOnlyFilesystem",
+        XfsError::CrossesDevices { .. } => "CrossesDevices",
+        XfsError::TooManyOpenFiles { .. } => "TooManyOpenFiles",
+        XfsError::FileInUse { .. } => "FileInUse",
+        XfsError::PathOutsideSandbox { .. } => "PathOutsideSandbox",
+        XfsError::InvalidUtf8 { .. } => "InvalidUtf8",
+        // Errors without a dedicated variant are only required to be errors.
+        XfsError::IoError { .. } | XfsError::GeneralError { .. } | XfsError::UserError { .. } => {
+            "Other"
+        }
+    }
+}
+
+fn apply(fs: &mut dyn Xfs, root: &Path, op: &Op) -> Outcome {
+    let p = |rel: &str| root.join(rel);
+    let result = match op {
+        Op::Write(path, data) => fs.writer(&p(path)).map(|mut w| {
+            w.write_all(data.as_bytes()).unwrap();
+            w.flush().unwrap();
+            Outcome::Ok
+        }),
+        Op::Read(path) => fs.reader(&p(path)).map(|mut r| {
+            let mut data = vec![];
+            r.read_to_end(&mut data).unwrap();
+            Outcome::Data(data)
+        }),
+        Op::ReadAllLines(path) => fs.read_all_lines(&p(path)).map(Outcome::Lines),
+        Op::ReadDir(path) => fs.read_dir(&p(path)).map(|entries| {
+            let mut entries: Vec<(String, bool)> = entries
+                .map(|de| {
+                    let de = de.unwrap();
+                    let name = de
+                        .path()
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned();
+                    (name, de.metadata().unwrap().is_dir())
+                })
+                .collect();
+            entries.sort();
+            Outcome::Entries(entries)
+        }),
+        Op::Metadata(path) => fs.metadata(&p(path)).map(|md| Outcome::IsDir(md.is_dir())),
+        Op::CreateDir(path) => fs.create_dir(&p(path)).map(|_| Outcome::Ok),
+        Op::CreateDirAll(path) => fs.create_dir_all(&p(path)).map(|_| Outcome::Ok),
+        Op::RemoveFile(path) => fs.remove_file(&p(path)).map(|_| Outcome::Ok),
+        Op::RemoveDirAll(path) => fs.remove_dir_all(&p(path)).map(|_| Outcome::Ok),
+        Op::Rename(from, to) => fs.rename(&p(from), &p(to)).map(|_| Outcome::Ok),
+    };
+    result.unwrap_or_else(|e| Outcome::Err(error_kind(&e)))
+}
+
+fn path_strategy() -> impl Strategy<Value = String> {
+    prop::collection::vec(prop::sample::select(vec!["a", "b", "c"]), 1..4)
+        .prop_map(|parts| parts.join("/"))
+}
+
+fn op_strategy() -> impl Strategy<Value = Op> {
+    let data = prop::sample::select(vec!["", "x", "one\ntwo\n", "hello"]).prop_map(String::from);
+    prop_oneof![
+        3 => (path_strategy(), data).prop_map(|(p, d)| Op::Write(p, d)),
+        1 => path_strategy().prop_map(Op::Read),
+        1 => path_strategy().prop_map(Op::ReadAllLines),
+        1 => path_strategy().prop_map(Op::ReadDir),
+        1 => path_strategy().prop_map(Op::Metadata),
+        2 => path_strategy().prop_map(Op::CreateDir),
+        2 => path_strategy().prop_map(Op::CreateDirAll),
+        1 => path_strategy().prop_map(Op::RemoveFile),
+        1 => path_strategy().prop_map(Op::RemoveDirAll),
+        2 => (path_strategy(), path_strategy()).prop_map(|(a, b)| Op::Rename(a, b)),
+    ]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn mockfs_matches_osfs(ops in prop::collection::vec(op_strategy(), 1..20)) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let os_root = temp_dir.path().to_path_buf();
+        let mut os_fs = OsFs {};
+        let mock_root = PathBuf::from("/");
+        let mut mock_fs = MockFS::new();
+
+        for (i, op) in ops.iter().enumerate() {
+            let os_outcome = apply(&mut os_fs, &os_root, op);
+            let mock_outcome = apply(&mut mock_fs, &mock_root, op);
+            prop_assert_eq!(os_outcome, mock_outcome, "operation {} ({:?}) differs", i, op);
+        }
+
+        prop_assert_eq!(
+            render(&os_fs, &os_root).unwrap(),
+            render(&mock_fs, &mock_root).unwrap(),
+            "final trees differ"
+        );
+    }
+}
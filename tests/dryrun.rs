@@ -0,0 +1,188 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use inscenerator_xfs::dryrun::{Change, DryRunFs};
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::{OsFs, Xfs, XfsReadOnly};
+
+fn project() -> (DryRunFs<MockFS>, String) {
+    let mut real = MockFS::new();
+    real.add_file(Path::new("/site/index.html"), "index")
+        .unwrap();
+    real.add_file(Path::new("/site/blog/post.html"), "post")
+        .unwrap();
+    real.add_file(Path::new("/config.toml"), "config").unwrap();
+    let snapshot = real.to_txtar().unwrap();
+    (DryRunFs::new(Box::new(real)), snapshot)
+}
+
+fn write(fs: &mut dyn Xfs, p: &str, data: &str) {
+    fs.writer(Path::new(p))
+        .unwrap()
+        .write_all(data.as_bytes())
+        .unwrap();
+}
+
+fn read(fs: &dyn XfsReadOnly, p: &str) -> Vec<String> {
+    fs.read_all_lines(Path::new(p)).unwrap()
+}
+
+fn list(fs: &dyn XfsReadOnly, p: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs
+        .read_dir(Path::new(p))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Asserts that the wrapped filesystem was not modified.
+fn assert_unchanged(fs: DryRunFs<MockFS>, snapshot: &str) {
+    assert_eq!(fs.into_inner().to_txtar().unwrap(), snapshot);
+}
+
+#[test]
+fn test_writes_are_visible_but_not_applied() {
+    let (mut fs, snapshot) = project();
+    write(&mut fs, "/site/index.html", "new index");
+    write(&mut fs, "/site/about.html", "about");
+    write(&mut fs, "/site/about.html", "about us");
+
+    assert_eq!(read(&fs, "/site/index.html"), vec!["new index"]);
+    assert_eq!(read(&fs, "/site/about.html"), vec!["about us"]);
+    assert_eq!(read(&fs, "/site/blog/post.html"), vec!["post"]);
+    assert_eq!(
+        list(&fs, "/site"),
+        vec![
+            PathBuf::from("/site/about.html"),
+            PathBuf::from("/site/blog"),
+            PathBuf::from("/site/index.html"),
+        ]
+    );
+    assert_eq!(
+        fs.changes(),
+        vec![
+            Change::ModifyFile {
+                path: "/site/index.html".into()
+            },
+            Change::CreateFile {
+                path: "/site/about.html".into()
+            },
+        ]
+    );
+    assert_unchanged(fs, &snapshot);
+}
+
+#[test]
+fn test_removed_paths_are_hidden() {
+    let (mut fs, snapshot) = project();
+    fs.remove_dir_all(Path::new("/site/blog")).unwrap();
+    fs.remove_file(Path::new("/config.toml")).unwrap();
+    assert!(!fs.exists(Path::new("/site/blog")));
+    assert!(!fs.exists(Path::new("/site/blog/post.html")));
+    assert!(!fs.exists(Path::new("/config.toml")));
+    assert_eq!(list(&fs, "/site"), vec![PathBuf::from("/site/index.html")]);
+
+    // A directory created in place of a removed one starts out empty.
+    fs.create_dir(Path::new("/site/blog")).unwrap();
+    assert!(list(&fs, "/site/blog").is_empty());
+
+    assert_eq!(
+        fs.plan(),
+        "delete directory /site/blog\n\
+         delete file /config.toml\n\
+         create directory /site/blog\n"
+    );
+    assert_unchanged(fs, &snapshot);
+}
+
+#[test]
+fn test_rename_moves_unchanged_trees() {
+    let (mut fs, snapshot) = project();
+    fs.rename(Path::new("/site/blog"), Path::new("/blog"))
+        .unwrap();
+    assert!(!fs.exists(Path::new("/site/blog")));
+    assert_eq!(read(&fs, "/blog/post.html"), vec!["post"]);
+    assert_eq!(fs.plan(), "rename /site/blog to /blog\n");
+    assert_unchanged(fs, &snapshot);
+}
+
+#[test]
+fn test_attribute_changes_are_visible() {
+    let (mut fs, snapshot) = project();
+    fs.set_permissions(Path::new("/site/index.html"), 0o600)
+        .unwrap();
+    assert_eq!(
+        fs.metadata(Path::new("/site/index.html")).unwrap().mode() & 0o777,
+        0o600
+    );
+    assert_eq!(read(&fs, "/site/index.html"), vec!["index"]);
+    // Copying the file into the overlay does not hide its siblings.
+    assert_eq!(list(&fs, "/site").len(), 2);
+    assert_eq!(fs.plan(), "set permissions of /site/index.html to 600\n");
+    assert_unchanged(fs, &snapshot);
+}
+
+#[test]
+fn test_clones_share_the_plan() {
+    let (mut fs, _snapshot) = project();
+    let mut clone = fs.unsafe_clone_mut();
+    write(clone.as_mut(), "/site/new.html", "");
+    assert!(fs.is_file(Path::new("/site/new.html")));
+    assert_eq!(fs.plan(), "create file /site/new.html\n");
+}
+
+#[test]
+fn test_renamed_files_are_read_from_the_wrapped_filesystem() {
+    let (mut fs, snapshot) = project();
+    fs.rename(Path::new("/site"), Path::new("/moved")).unwrap();
+    fs.rename(Path::new("/moved/blog"), Path::new("/blog"))
+        .unwrap();
+    assert_eq!(read(&fs, "/blog/post.html"), vec!["post"]);
+    assert_eq!(
+        fs.metadata(Path::new("/moved/index.html")).unwrap().size(),
+        5
+    );
+
+    // Changing a renamed file copies it into the overlay first.
+    fs.set_permissions(Path::new("/blog/post.html"), 0o600)
+        .unwrap();
+    assert_eq!(read(&fs, "/blog/post.html"), vec!["post"]);
+    assert_eq!(
+        fs.metadata(Path::new("/blog/post.html")).unwrap().mode() & 0o777,
+        0o600
+    );
+    write(&mut fs, "/moved/index.html", "new index");
+    assert_eq!(read(&fs, "/moved/index.html"), vec!["new index"]);
+    assert_unchanged(fs, &snapshot);
+}
+
+#[test]
+fn test_paths_are_resolved_like_the_wrapped_filesystem() {
+    // OsFs resolves relative paths from the current directory, which the
+    // dry run must do as well. Nothing is written to disk.
+    let mut fs = DryRunFs::new(Box::new(OsFs {}));
+    let name = "xfs-dry-run-probe.txt";
+    let absolute = std::env::current_dir().unwrap().join(name);
+    assert!(!absolute.exists());
+
+    write(&mut fs, name, "probe");
+    assert!(fs.is_file(&absolute));
+    assert_eq!(read(&fs, absolute.to_str().unwrap()), vec!["probe"]);
+    let listed: Vec<PathBuf> = fs
+        .read_dir(Path::new("."))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.file_name() == Some(name.as_ref()))
+        .collect();
+    assert_eq!(listed, vec![Path::new(".").join(name)]);
+
+    fs.remove_file(&absolute).unwrap();
+    assert!(!fs.exists(Path::new(name)));
+    assert_eq!(
+        fs.plan(),
+        format!("create file {}\ndelete file {}\n", name, absolute.display())
+    );
+    assert!(!absolute.exists());
+}
@@ -0,0 +1,168 @@
+use std::io::Write;
+use std::path::Path;
+
+use inscenerator_xfs::mockfs::{CrashMode, MockFS};
+use inscenerator_xfs::{Xfs, XfsReadOnly, XfsWrite};
+
+fn write(fs: &mut MockFS, p: &str, data: &str) {
+    fs.writer(Path::new(p))
+        .unwrap()
+        .write_all(data.as_bytes())
+        .unwrap();
+}
+
+/// Saves `data` to `p` by writing a temporary file and renaming it into place,
+/// optionally skipping the syncs that make this safe.
+fn save(fs: &mut MockFS, p: &str, data: &str, sync_file: bool, sync_dir: bool) {
+    let tmp = format!("{}.tmp", p);
+    write(fs, &tmp, data);
+    if sync_file {
+        fs.sync_all(Path::new(&tmp)).unwrap();
+    }
+    fs.rename(Path::new(&tmp), Path::new(p)).unwrap();
+    if sync_dir {
+        fs.sync_all(Path::new(p).parent().unwrap()).unwrap();
+    }
+}
+
+fn tracked_fs() -> MockFS {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/data/config"), "old").unwrap();
+    fs.enable_durability_tracking();
+    fs
+}
+
+#[test]
+fn test_unsynced_changes_are_lost() {
+    let mut fs = tracked_fs();
+    write(&mut fs, "/data/config", "new");
+    write(&mut fs, "/data/other", "other");
+    fs.create_dir(Path::new("/data/sub")).unwrap();
+
+    fs.simulate_crash();
+
+    assert_eq!(fs.get_str(Path::new("/data/config")).unwrap(), "old");
+    assert!(!fs.exists(Path::new("/data/other")));
+    assert!(!fs.exists(Path::new("/data/sub")));
+}
+
+#[test]
+fn test_synced_changes_survive() {
+    let mut fs = tracked_fs();
+    write(&mut fs, "/data/config", "new");
+    fs.sync_data(Path::new("/data/config")).unwrap();
+    write(&mut fs, "/data/other", "other");
+    fs.sync_all(Path::new("/data/other")).unwrap();
+    fs.sync_all(Path::new("/data")).unwrap();
+
+    fs.simulate_crash();
+
+    assert_eq!(fs.get_str(Path::new("/data/config")).unwrap(), "new");
+    assert_eq!(fs.get_str(Path::new("/data/other")).unwrap(), "other");
+}
+
+#[test]
+fn test_writer_sync_survives() {
+    let mut fs = tracked_fs();
+    let mut w = fs.writer(Path::new("/data/config")).unwrap();
+    w.write_all(b"new").unwrap();
+    w.sync_all().unwrap();
+    w.write_all(b" and more").unwrap();
+    drop(w);
+
+    fs.simulate_crash();
+
+    assert_eq!(fs.get_str(Path::new("/data/config")).unwrap(), "new");
+}
+
+#[test]
+fn test_removal_is_undone_without_directory_sync() {
+    let mut fs = tracked_fs();
+    fs.remove_file(Path::new("/data/config")).unwrap();
+    fs.simulate_crash();
+    assert_eq!(fs.get_str(Path::new("/data/config")).unwrap(), "old");
+
+    fs.remove_file(Path::new("/data/config")).unwrap();
+    fs.sync_all(Path::new("/data")).unwrap();
+    fs.simulate_crash();
+    assert!(!fs.exists(Path::new("/data/config")));
+}
+
+#[test]
+fn test_synced_file_in_unsynced_directory_is_lost() {
+    let mut fs = tracked_fs();
+    fs.create_dir(Path::new("/data/sub")).unwrap();
+    write(&mut fs, "/data/sub/a", "a");
+    fs.sync_all(Path::new("/data/sub/a")).unwrap();
+    fs.sync_all(Path::new("/data/sub")).unwrap();
+
+    fs.simulate_crash();
+
+    assert!(!fs.exists(Path::new("/data/sub")));
+}
+
+#[test]
+fn test_atomic_save_survives_crash() {
+    let mut fs = tracked_fs();
+    save(&mut fs, "/data/config", "new", true, true);
+    fs.simulate_crash();
+    assert_eq!(fs.get_str(Path::new("/data/config")).unwrap(), "new");
+}
+
+#[test]
+fn test_save_without_directory_sync_may_keep_old_contents() {
+    let mut fs = tracked_fs();
+    save(&mut fs, "/data/config", "new", true, false);
+    fs.simulate_crash();
+    assert_eq!(fs.get_str(Path::new("/data/config")).unwrap(), "old");
+    assert!(!fs.exists(Path::new("/data/config.tmp")));
+}
+
+#[test]
+fn test_save_without_file_sync_may_leave_empty_file() {
+    let mut fs = tracked_fs();
+    save(&mut fs, "/data/config", "new", false, true);
+    fs.simulate_crash();
+    assert_eq!(fs.get_str(Path::new("/data/config")).unwrap(), "");
+
+    let mut fs = tracked_fs();
+    save(&mut fs, "/data/config", "new", false, false);
+    fs.simulate_crash_with(CrashMode::KeepDirectoryChanges);
+    assert_eq!(fs.get_str(Path::new("/data/config")).unwrap(), "");
+}
+
+#[test]
+fn test_recovery_after_crash() {
+    let mut fs = tracked_fs();
+    write(&mut fs, "/data/config.tmp", "partial");
+    fs.sync_all(Path::new("/data/config.tmp")).unwrap();
+    fs.sync_all(Path::new("/data")).unwrap();
+    fs.simulate_crash();
+
+    // Recovery discards the leftover temporary file.
+    if fs.exists(Path::new("/data/config.tmp")) {
+        fs.remove_file(Path::new("/data/config.tmp")).unwrap();
+        fs.sync_all(Path::new("/data")).unwrap();
+    }
+    fs.simulate_crash();
+
+    assert!(!fs.exists(Path::new("/data/config.tmp")));
+    assert_eq!(fs.get_str(Path::new("/data/config")).unwrap(), "old");
+}
+
+#[test]
+fn test_clones_share_tracking() {
+    let mut fs = MockFS::new();
+    let mut clone = fs.unsafe_clone_mut();
+    fs.enable_durability_tracking();
+    assert!(fs.is_tracking_durability());
+    clone.writer(Path::new("/a")).unwrap();
+    fs.simulate_crash();
+    assert!(!clone.exists(Path::new("/a")));
+}
+
+#[test]
+#[should_panic(expected = "simulate_crash requires enable_durability_tracking")]
+fn test_crash_requires_tracking() {
+    MockFS::new().simulate_crash();
+}
@@ -0,0 +1,78 @@
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::{Xfs, XfsError, XfsReadOnly};
+
+fn is_read_only<T>(r: inscenerator_xfs::Result<T>) -> bool {
+    matches!(r, Err(XfsError::ReadOnlyFilesystem { .. }))
+}
+
+fn frozen_fs() -> MockFS {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/a.txt"), "a").unwrap();
+    fs.create_dir(Path::new("/d")).unwrap();
+    fs.freeze();
+    fs
+}
+
+#[test]
+fn test_frozen_rejects_all_mutations() {
+    let mut fs = frozen_fs();
+    assert!(fs.is_frozen());
+    assert!(is_read_only(fs.writer(Path::new("/a.txt"))));
+    assert!(is_read_only(fs.writer(Path::new("/b.txt"))));
+    assert!(is_read_only(fs.create_dir(Path::new("/e"))));
+    assert!(is_read_only(fs.create_dir_all(Path::new("/e/f"))));
+    assert!(is_read_only(fs.remove_file(Path::new("/a.txt"))));
+    assert!(is_read_only(fs.remove_dir_all(Path::new("/d"))));
+    assert!(is_read_only(
+        fs.rename(Path::new("/a.txt"), Path::new("/c.txt"))
+    ));
+    assert!(is_read_only(fs.set_permissions(Path::new("/a.txt"), 0o600)));
+    assert!(is_read_only(fs.set_times(
+        Path::new("/a.txt"),
+        None,
+        Some(SystemTime::UNIX_EPOCH)
+    )));
+
+    assert_eq!(fs.get_str(Path::new("/a.txt")).unwrap(), "a");
+    assert!(fs.is_dir(Path::new("/d")));
+    assert!(!fs.exists(Path::new("/b.txt")));
+}
+
+#[test]
+fn test_frozen_allows_reads() {
+    let mut fs = frozen_fs();
+    assert_eq!(fs.read_all_lines(Path::new("/a.txt")).unwrap(), vec!["a"]);
+    assert_eq!(fs.read_dir(Path::new("/")).unwrap().count(), 2);
+    fs.sync_all(Path::new("/a.txt")).unwrap();
+}
+
+#[test]
+fn test_freeze_shared_with_clones() {
+    let mut fs = MockFS::new();
+    let mut handle = fs.unsafe_clone_mut();
+    fs.freeze();
+    assert!(is_read_only(handle.create_dir(Path::new("/e"))));
+    fs.unfreeze();
+    handle.create_dir(Path::new("/e")).unwrap();
+}
+
+#[test]
+fn test_freeze_blocks_open_writers() {
+    let mut fs = MockFS::new();
+    let mut w = fs.writer(Path::new("/a.txt")).unwrap();
+    fs.freeze();
+    let err = w.write_all(b"late").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::ReadOnlyFilesystem);
+    assert_eq!(fs.get_str(Path::new("/a.txt")).unwrap(), "");
+}
+
+#[test]
+fn test_setup_helpers_ignore_freeze() {
+    let mut fs = frozen_fs();
+    fs.add_file(Path::new("/b.txt"), "b").unwrap();
+    assert_eq!(fs.get_str(Path::new("/b.txt")).unwrap(), "b");
+}
@@ -256,13 +256,13 @@ fn test_mockfs_rename_error() {
 }
 
 #[test]
-fn test_mockfs_copy_recursive() {
+fn test_mockfs_copy_from() {
     let mut fs1 = MockFS::new();
     fs1.add_file(Path::new("dir/a.txt"), "a").unwrap();
     fs1.add_file(Path::new("dir/subdir/b.txt"), "b").unwrap();
 
     let mut fs2 = MockFS::new();
-    fs2.copy_recursive(&fs1, Path::new("dir"), Path::new("copied"))
+    fs2.copy_from(&fs1, Path::new("dir"), Path::new("copied"))
         .unwrap();
 
     assert!(fs2.is_file(Path::new("copied/a.txt")));
@@ -0,0 +1,135 @@
+use std::io::{Read, Write};
+use std::path::Path;
+
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::{Xfs, XfsError, XfsReadOnly};
+
+fn fs_with_file() -> MockFS {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/a.txt"), "hello").unwrap();
+    fs
+}
+
+#[test]
+fn test_handles_counted_until_dropped() {
+    let mut fs = fs_with_file();
+    let p = Path::new("/a.txt");
+    assert_eq!(fs.open_handles(p).unwrap(), 0);
+
+    let r1 = fs.reader(p).unwrap();
+    let r2 = fs.reader(p).unwrap();
+    let w = fs.writer(Path::new("/b.txt")).unwrap();
+    assert_eq!(fs.open_handles(p).unwrap(), 2);
+    assert_eq!(fs.open_handles(Path::new("/b.txt")).unwrap(), 1);
+    assert_eq!(fs.total_open_handles(), 3);
+
+    drop(r1);
+    drop(w);
+    assert_eq!(fs.open_handles(p).unwrap(), 1);
+    drop(r2);
+    assert_eq!(fs.total_open_handles(), 0);
+    fs.assert_no_open_handles();
+}
+
+#[test]
+fn test_read_all_lines_does_not_leak() {
+    let fs = fs_with_file();
+    fs.read_all_lines(Path::new("/a.txt")).unwrap();
+    fs.assert_no_open_handles();
+}
+
+#[test]
+fn test_handles_follow_renames() {
+    let mut fs = fs_with_file();
+    let _r = fs.reader(Path::new("/a.txt")).unwrap();
+    fs.rename(Path::new("/a.txt"), Path::new("/b.txt")).unwrap();
+    assert_eq!(fs.open_handles(Path::new("/b.txt")).unwrap(), 1);
+}
+
+#[test]
+#[should_panic(expected = "MockFS has open handles:\n  /a.txt: 1\n")]
+fn test_assert_no_open_handles_reports_paths() {
+    let fs = fs_with_file();
+    let _r = fs.reader(Path::new("/a.txt")).unwrap();
+    fs.assert_no_open_handles();
+}
+
+#[test]
+#[should_panic(expected = "(removed file): 1")]
+fn test_assert_no_open_handles_reports_removed_files() {
+    let mut fs = fs_with_file();
+    let _r = fs.reader(Path::new("/a.txt")).unwrap();
+    fs.remove_file(Path::new("/a.txt")).unwrap();
+    fs.assert_no_open_handles();
+}
+
+#[test]
+fn test_open_file_limit() {
+    let mut fs = fs_with_file();
+    fs.set_open_file_limit(Some(2));
+    let p = Path::new("/a.txt");
+    let _r1 = fs.reader(p).unwrap();
+    let w = fs.writer(Path::new("/b.txt")).unwrap();
+    assert!(matches!(
+        fs.reader(p),
+        Err(XfsError::TooManyOpenFiles { .. })
+    ));
+    assert!(matches!(
+        fs.read_all_lines(p),
+        Err(XfsError::TooManyOpenFiles { .. })
+    ));
+    // A failed open must not truncate an existing file.
+    assert!(matches!(
+        fs.writer(p),
+        Err(XfsError::TooManyOpenFiles { .. })
+    ));
+    assert_eq!(fs.get_str(p).unwrap(), "hello");
+
+    drop(w);
+    let mut s = String::new();
+    fs.reader(p).unwrap().read_to_string(&mut s).unwrap();
+    assert_eq!(s, "hello");
+}
+
+#[test]
+fn test_posix_allows_removing_open_files() {
+    let mut fs = fs_with_file();
+    let mut r = fs.reader(Path::new("/a.txt")).unwrap();
+    fs.remove_file(Path::new("/a.txt")).unwrap();
+    let mut s = String::new();
+    r.read_to_string(&mut s).unwrap();
+    assert_eq!(s, "hello");
+}
+
+#[test]
+fn test_windows_file_locking() {
+    let mut fs = fs_with_file();
+    fs.set_windows_file_locking(true);
+    fs.create_dir(Path::new("/d")).unwrap();
+    let mut w = fs.writer(Path::new("/d/b.txt")).unwrap();
+    w.write_all(b"b").unwrap();
+    let r = fs.reader(Path::new("/a.txt")).unwrap();
+
+    assert!(matches!(
+        fs.remove_file(Path::new("/a.txt")),
+        Err(XfsError::FileInUse { .. })
+    ));
+    assert!(matches!(
+        fs.rename(Path::new("/a.txt"), Path::new("/c.txt")),
+        Err(XfsError::FileInUse { .. })
+    ));
+    assert!(matches!(
+        fs.rename(Path::new("/d/b.txt"), Path::new("/a.txt")),
+        Err(XfsError::FileInUse { .. })
+    ));
+    assert!(matches!(
+        fs.remove_dir_all(Path::new("/d")),
+        Err(XfsError::FileInUse { .. })
+    ));
+
+    drop(r);
+    drop(w);
+    fs.rename(Path::new("/a.txt"), Path::new("/c.txt")).unwrap();
+    fs.remove_dir_all(Path::new("/d")).unwrap();
+    assert!(fs.exists(Path::new("/c.txt")));
+}
@@ -0,0 +1,41 @@
+use std::path::Path;
+
+use inscenerator_xfs::hash::{hash_bytes, hash_file, Digest};
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::XfsError;
+
+#[test]
+fn test_known_digests() {
+    assert_eq!(
+        hash_bytes(b"").to_string(),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hash_bytes(b"abc").to_string(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
+#[test]
+fn test_hash_file_matches_hash_bytes() {
+    let mut fs = MockFS::new();
+    let data: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    fs.add_r(Path::new("big.bin"), data.clone()).unwrap();
+    assert_eq!(
+        hash_file(&fs, Path::new("big.bin")).unwrap(),
+        hash_bytes(&data)
+    );
+    assert!(matches!(
+        hash_file(&fs, Path::new("missing")),
+        Err(XfsError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_digest_parses_its_display() {
+    let digest = hash_bytes(b"abc");
+    assert_eq!(digest.to_string().parse::<Digest>().unwrap(), digest);
+    assert!("abc".parse::<Digest>().is_err());
+    assert!("zz".repeat(32).parse::<Digest>().is_err());
+    assert!(format!("+0{}", "00".repeat(31)).parse::<Digest>().is_err());
+}
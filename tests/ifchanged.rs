@@ -0,0 +1,107 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use inscenerator_xfs::clock::ManualClock;
+use inscenerator_xfs::ifchanged::{write_if_changed, WriteIfChangedFs};
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::{Xfs, XfsError, XfsReadOnly};
+
+fn modified(fs: &dyn XfsReadOnly, p: &str) -> SystemTime {
+    fs.metadata(Path::new(p)).unwrap().modified().unwrap()
+}
+
+#[test]
+fn test_write_if_changed_keeps_mtime() {
+    let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+    let mut fs = MockFS::with_clock(Arc::new(clock.clone()));
+    let p = Path::new("/out.txt");
+    assert!(write_if_changed(&mut fs, p, b"one").unwrap());
+    let first = modified(&fs, "/out.txt");
+
+    clock.advance(Duration::from_secs(1));
+    assert!(!write_if_changed(&mut fs, p, b"one").unwrap());
+    assert_eq!(modified(&fs, "/out.txt"), first);
+
+    assert!(write_if_changed(&mut fs, p, b"two").unwrap());
+    assert!(modified(&fs, "/out.txt") > first);
+    assert_eq!(fs.read_all_lines(p).unwrap(), vec!["two"]);
+
+    assert!(matches!(
+        write_if_changed(&mut fs, Path::new("/missing/out.txt"), b""),
+        Err(XfsError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_wrapper_reports_changed_files() {
+    let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+    let mut real = MockFS::with_clock(Arc::new(clock.clone()));
+    real.add_file(Path::new("/site/same.html"), "same").unwrap();
+    real.add_file(Path::new("/site/edited.html"), "old")
+        .unwrap();
+    let before = modified(&real, "/site/same.html");
+    clock.advance(Duration::from_secs(1));
+
+    let mut fs = WriteIfChangedFs::new(real.unsafe_clone_mut());
+    for (p, data) in [
+        ("/site/same.html", "same"),
+        ("/site/edited.html", "new"),
+        ("/site/new.html", "new"),
+    ] {
+        let mut w = fs.writer(Path::new(p)).unwrap();
+        w.write_all(data.as_bytes()).unwrap();
+    }
+    assert_eq!(
+        fs.changed(),
+        vec![
+            PathBuf::from("/site/edited.html"),
+            PathBuf::from("/site/new.html"),
+        ]
+    );
+    assert_eq!(fs.unchanged(), vec![PathBuf::from("/site/same.html")]);
+    assert_eq!(modified(&real, "/site/same.html"), before);
+    assert_eq!(
+        real.read_all_lines(Path::new("/site/edited.html")).unwrap(),
+        vec!["new"]
+    );
+}
+
+#[test]
+fn test_buffered_writes_appear_on_flush() {
+    let mut real = MockFS::new();
+    real.add_file(Path::new("/a.txt"), "old").unwrap();
+    let mut fs = WriteIfChangedFs::new(real.unsafe_clone_mut());
+    let mut w = fs.writer(Path::new("/a.txt")).unwrap();
+    w.write_all(b"new").unwrap();
+    assert_eq!(
+        real.read_all_lines(Path::new("/a.txt")).unwrap(),
+        vec!["old"]
+    );
+    w.flush().unwrap();
+    assert_eq!(
+        real.read_all_lines(Path::new("/a.txt")).unwrap(),
+        vec!["new"]
+    );
+
+    // Dropping a writer without writing truncates the file, as usual.
+    drop(w);
+    drop(fs.writer(Path::new("/a.txt")).unwrap());
+    assert!(real.read_all_lines(Path::new("/a.txt")).unwrap().is_empty());
+    assert_eq!(fs.changed(), vec![PathBuf::from("/a.txt")]);
+}
+
+#[test]
+fn test_writer_for_read_only_file_fails_when_opened() {
+    let mut real = MockFS::new();
+    real.add_file(Path::new("/a.txt"), "old").unwrap();
+    real.set_permissions(Path::new("/a.txt"), 0o444).unwrap();
+    let mut fs = WriteIfChangedFs::new(real.unsafe_clone_mut());
+    assert!(matches!(
+        fs.writer(Path::new("/a.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(fs.changed().is_empty());
+    assert!(fs.unchanged().is_empty());
+}
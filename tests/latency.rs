@@ -0,0 +1,145 @@
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use inscenerator_xfs::latency::{Jitter, LatencyConfig, LatencyFs, Operation, SleepFn};
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::{Xfs, XfsReadOnly};
+
+/// Wraps a `MockFS` holding `/a.txt`, recording delays instead of sleeping.
+fn recording_fs(config: LatencyConfig) -> (LatencyFs<MockFS>, Arc<Mutex<Vec<Duration>>>) {
+    let mut inner = MockFS::new();
+    inner.add_file(Path::new("/a.txt"), "0123456789").unwrap();
+    let delays = Arc::new(Mutex::new(vec![]));
+    let recorded = delays.clone();
+    let sleep: SleepFn = Arc::new(move |d| recorded.lock().unwrap().push(d));
+    (
+        LatencyFs::with_sleep(Box::new(inner), config, sleep),
+        delays,
+    )
+}
+
+#[test]
+fn test_fixed_latency_per_operation() {
+    let mut config = LatencyConfig {
+        latency: Duration::from_millis(5),
+        ..LatencyConfig::default()
+    };
+    config
+        .per_operation
+        .insert(Operation::Rename, Duration::from_millis(50));
+    let (mut fs, delays) = recording_fs(config);
+
+    assert!(fs.exists(Path::new("/a.txt")));
+    fs.create_dir(Path::new("/d")).unwrap();
+    fs.rename(Path::new("/a.txt"), Path::new("/d/a.txt"))
+        .unwrap();
+
+    assert_eq!(
+        *delays.lock().unwrap(),
+        vec![
+            Duration::from_millis(5),
+            Duration::from_millis(5),
+            Duration::from_millis(50)
+        ]
+    );
+}
+
+#[test]
+fn test_bandwidth_limits() {
+    let (mut fs, delays) = recording_fs(LatencyConfig {
+        read_bandwidth: Some(10),
+        write_bandwidth: Some(100),
+        ..LatencyConfig::default()
+    });
+
+    let mut s = String::new();
+    fs.reader(Path::new("/a.txt"))
+        .unwrap()
+        .read_to_string(&mut s)
+        .unwrap();
+    assert_eq!(s, "0123456789");
+    assert_eq!(*delays.lock().unwrap(), vec![Duration::from_secs(1)]);
+
+    delays.lock().unwrap().clear();
+    fs.writer(Path::new("/b.txt"))
+        .unwrap()
+        .write_all(&[0; 50])
+        .unwrap();
+    assert_eq!(*delays.lock().unwrap(), vec![Duration::from_millis(500)]);
+}
+
+#[test]
+fn test_jitter_is_bounded_and_reproducible() {
+    let config = LatencyConfig {
+        latency: Duration::from_millis(100),
+        jitter: Jitter::Uniform {
+            fraction: 0.5,
+            seed: 42,
+        },
+        ..LatencyConfig::default()
+    };
+    let run = || {
+        let (fs, delays) = recording_fs(config.clone());
+        for _ in 0..50 {
+            fs.metadata(Path::new("/a.txt")).unwrap();
+        }
+        let delays = delays.lock().unwrap().clone();
+        delays
+    };
+
+    let first = run();
+    assert_eq!(first, run());
+    assert!(first
+        .iter()
+        .all(|d| *d >= Duration::from_millis(50) && *d <= Duration::from_millis(150)));
+    assert!(first.iter().any(|d| *d != first[0]));
+}
+
+#[test]
+fn test_jitter_with_extreme_fractions_does_not_panic() {
+    for fraction in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, f64::MAX, 1e30] {
+        let (fs, delays) = recording_fs(LatencyConfig {
+            latency: Duration::from_millis(100),
+            jitter: Jitter::Uniform { fraction, seed: 7 },
+            ..LatencyConfig::default()
+        });
+        for _ in 0..20 {
+            fs.metadata(Path::new("/a.txt")).unwrap();
+        }
+        let delays = delays.lock().unwrap().clone();
+        assert_eq!(delays.len(), 20);
+        if fraction.is_nan() {
+            assert!(delays.iter().all(|d| *d == Duration::from_millis(100)));
+        } else {
+            assert!(delays.iter().all(|d| d.is_zero() || *d == Duration::MAX));
+        }
+    }
+}
+
+#[test]
+fn test_clones_share_configuration() {
+    let (mut fs, delays) = recording_fs(LatencyConfig {
+        latency: Duration::from_millis(1),
+        ..LatencyConfig::default()
+    });
+    let mut clone = fs.unsafe_clone_mut();
+    clone.create_dir(Path::new("/d")).unwrap();
+    assert!(fs.unsafe_clone().is_dir(Path::new("/d")));
+    assert_eq!(delays.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_really_sleeps() {
+    let fs = LatencyFs::new(
+        Box::new(MockFS::new()),
+        LatencyConfig {
+            latency: Duration::from_millis(20),
+            ..LatencyConfig::default()
+        },
+    );
+    let start = Instant::now();
+    fs.metadata(Path::new("/")).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
@@ -0,0 +1,42 @@
+use inscenerator_xfs::{mockfs, XfsReadOnly};
+use std::path::Path;
+
+#[test]
+fn test_mockfs_macro_nested() {
+    let fs = mockfs!({
+        "src": {
+            "main.rs": "fn main(){}",
+            "util": { "mod.rs": String::from("pub fn f() {}") },
+        },
+        "empty_dir": {},
+        "bin": b"\x00\x01",
+    });
+
+    assert_eq!(fs.get_str(Path::new("src/main.rs")).unwrap(), "fn main(){}");
+    assert_eq!(
+        fs.get_str(Path::new("src/util/mod.rs")).unwrap(),
+        "pub fn f() {}"
+    );
+    assert!(fs.is_dir(Path::new("empty_dir")));
+    assert_eq!(fs.read_dir(Path::new("empty_dir")).unwrap().count(), 0);
+    assert_eq!(fs.get(Path::new("bin")).unwrap(), vec![0, 1]);
+}
+
+#[test]
+fn test_mockfs_macro_without_outer_braces() {
+    let fs = mockfs! { "a.txt": "a", "b": {} };
+    assert!(fs.is_file(Path::new("a.txt")));
+    assert!(fs.is_dir(Path::new("b")));
+}
+
+#[test]
+fn test_mockfs_macro_empty() {
+    let fs = mockfs!({});
+    assert_eq!(fs.read_dir(Path::new("")).unwrap().count(), 0);
+}
+
+#[test]
+#[should_panic]
+fn test_mockfs_macro_conflict_panics() {
+    let _fs = mockfs!({ "a": "file", "a": {} });
+}
@@ -0,0 +1,220 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use inscenerator_xfs::hash::hash_bytes;
+use inscenerator_xfs::manifest::{Entry, Manifest, Mismatch};
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::{
+    OsFs, Result, Xfs, XfsDirEntry, XfsError, XfsMetadata, XfsReadDir, XfsReadOnly,
+};
+
+fn release() -> MockFS {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/release/index.html"), "<html>")
+        .unwrap();
+    fs.add_file(Path::new("/release/css/site v2.css"), "body {}")
+        .unwrap();
+    fs.create_dir_all(Path::new("/release/empty")).unwrap();
+    fs
+}
+
+#[test]
+fn test_manifest_lists_every_entry() {
+    let fs = release();
+    let manifest = Manifest::from_fs(&fs, Path::new("/release")).unwrap();
+    assert_eq!(
+        manifest.iter().collect::<Vec<_>>(),
+        vec![
+            ("css", &Entry::Dir),
+            (
+                "css/site v2.css",
+                &Entry::File {
+                    hash: hash_bytes(b"body {}"),
+                    size: 7
+                }
+            ),
+            ("empty", &Entry::Dir),
+            (
+                "index.html",
+                &Entry::File {
+                    hash: hash_bytes(b"<html>"),
+                    size: 6
+                }
+            ),
+        ]
+    );
+    assert_eq!(
+        manifest.format(),
+        format!(
+            "dir css\nfile {} 7 css/site v2.css\ndir empty\nfile {} 6 index.html\n",
+            hash_bytes(b"body {}"),
+            hash_bytes(b"<html>")
+        )
+    );
+}
+
+#[test]
+fn test_manifest_round_trips_through_a_file() {
+    let mut fs = release();
+    let manifest = Manifest::from_fs(&fs, Path::new("/release")).unwrap();
+    manifest.save(&mut fs, Path::new("/release.sums")).unwrap();
+    assert_eq!(
+        Manifest::load(&fs, Path::new("/release.sums")).unwrap(),
+        manifest
+    );
+
+    // Names that would not be read back as written are rejected.
+    for name in ["a\nb.txt", "a.txt\r", "a\rb.txt"] {
+        let mut fs = release();
+        fs.add_file(&Path::new("/release").join(name), "").unwrap();
+        assert!(
+            matches!(
+                Manifest::from_fs(&fs, Path::new("/release")),
+                Err(XfsError::GeneralError { .. })
+            ),
+            "{:?}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_parse_rejects_malformed_lines() {
+    assert!(Manifest::parse("\n\n").unwrap().is_empty());
+    for text in [
+        "dir\n",
+        "file abc 1 a.txt\n",
+        &format!("file {} -1 a.txt\n", hash_bytes(b"")),
+        &format!("file {} 0\n", hash_bytes(b"")),
+        "symlink a b\n",
+    ] {
+        assert!(
+            matches!(Manifest::parse(text), Err(XfsError::GeneralError { .. })),
+            "{:?}",
+            text
+        );
+    }
+}
+
+#[test]
+fn test_verify_reports_differences() {
+    let mut fs = release();
+    let manifest = Manifest::from_fs(&fs, Path::new("/release")).unwrap();
+    assert!(manifest
+        .verify(&fs, Path::new("/release"))
+        .unwrap()
+        .is_empty());
+
+    fs.writer(Path::new("/release/index.html"))
+        .unwrap()
+        .write_all(b"<HTML>")
+        .unwrap();
+    fs.remove_dir_all(Path::new("/release/empty")).unwrap();
+    fs.add_file(Path::new("/release/extra.js"), "").unwrap();
+    let mismatches = manifest.verify(&fs, Path::new("/release")).unwrap();
+    assert_eq!(
+        mismatches,
+        vec![
+            Mismatch::Missing {
+                name: "empty".to_string()
+            },
+            Mismatch::Unexpected {
+                name: "extra.js".to_string()
+            },
+            Mismatch::Changed {
+                name: "index.html".to_string()
+            },
+        ]
+    );
+    assert_eq!(mismatches[2].to_string(), "changed index.html");
+}
+
+#[test]
+fn test_manifests_agree_across_backends() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path();
+    std::fs::create_dir_all(root.join("css")).unwrap();
+    std::fs::create_dir_all(root.join("empty")).unwrap();
+    std::fs::write(root.join("index.html"), "<html>").unwrap();
+    std::fs::write(root.join("css/site v2.css"), "body {}").unwrap();
+    assert_eq!(
+        Manifest::from_fs(&OsFs {}, root).unwrap(),
+        Manifest::from_fs(&release(), Path::new("/release")).unwrap()
+    );
+}
+
+/// Wraps `OsFs`, giving directory entries metadata that follows symbolic
+/// links, as `metadata` does.
+struct FollowingFs;
+
+struct FollowingDirEntry {
+    path: PathBuf,
+}
+
+impl XfsDirEntry for FollowingDirEntry {
+    fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn metadata(&self) -> Result<Box<dyn XfsMetadata>> {
+        OsFs {}.metadata(&self.path)
+    }
+}
+
+impl XfsReadOnly for FollowingFs {
+    fn unsafe_clone(&self) -> Box<dyn XfsReadOnly> {
+        Box::new(FollowingFs)
+    }
+
+    fn canonical_path(&self, p: &Path) -> PathBuf {
+        OsFs {}.canonical_path(p)
+    }
+
+    fn read_dir(&self, p: &Path) -> Result<XfsReadDir> {
+        let entries: Vec<_> = OsFs {}
+            .read_dir(p)?
+            .map(|entry| {
+                let entry: Box<dyn XfsDirEntry> = Box::new(FollowingDirEntry {
+                    path: entry?.path(),
+                });
+                Ok(entry)
+            })
+            .collect();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn reader(&self, p: &Path) -> Result<Box<dyn Read>> {
+        OsFs {}.reader(p)
+    }
+
+    fn read_all_lines(&self, p: &Path) -> Result<Vec<String>> {
+        OsFs {}.read_all_lines(p)
+    }
+
+    fn metadata(&self, p: &Path) -> Result<Box<dyn XfsMetadata>> {
+        OsFs {}.metadata(p)
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_manifest_does_not_follow_directory_links() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path();
+    std::fs::create_dir_all(root.join("a")).unwrap();
+    std::fs::write(root.join("index.html"), "<html>").unwrap();
+    std::os::unix::fs::symlink("..", root.join("a/up")).unwrap();
+    std::os::unix::fs::symlink("index.html", root.join("link.html")).unwrap();
+
+    let mut expected = Manifest::new();
+    expected.insert("a", Entry::Dir);
+    expected.insert("a/up", Entry::Dir);
+    let file = Entry::File {
+        hash: hash_bytes(b"<html>"),
+        size: 6,
+    };
+    expected.insert("index.html", file);
+    expected.insert("link.html", file);
+    assert_eq!(Manifest::from_fs(&OsFs {}, root).unwrap(), expected);
+    assert_eq!(Manifest::from_fs(&FollowingFs, root).unwrap(), expected);
+}
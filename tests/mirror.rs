@@ -0,0 +1,219 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use inscenerator_xfs::clock::ManualClock;
+use inscenerator_xfs::manifest::Manifest;
+use inscenerator_xfs::mirror::{sync, Compare, SyncOptions, SyncReport};
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::{OsFs, Xfs, XfsError, XfsReadOnly};
+
+fn build() -> MockFS {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/site/index.html"), "index").unwrap();
+    fs.add_file(Path::new("/site/blog/post.html"), "post")
+        .unwrap();
+    fs.create_dir_all(Path::new("/site/empty")).unwrap();
+    fs
+}
+
+fn write(fs: &mut dyn Xfs, p: &str, data: &str) {
+    fs.writer(Path::new(p))
+        .unwrap()
+        .write_all(data.as_bytes())
+        .unwrap();
+}
+
+fn paths(v: &[&str]) -> Vec<PathBuf> {
+    v.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn test_sync_copies_then_skips_unchanged() {
+    let src = build();
+    let mut dst = MockFS::new();
+    let options = SyncOptions::default();
+    let report = sync(
+        &src,
+        Path::new("/site"),
+        &mut dst,
+        Path::new("/public"),
+        &options,
+    )
+    .unwrap();
+    assert_eq!(
+        report,
+        SyncReport {
+            created: paths(&[
+                "/public",
+                "/public/blog",
+                "/public/blog/post.html",
+                "/public/empty",
+                "/public/index.html",
+            ]),
+            bytes_copied: 9,
+            ..SyncReport::default()
+        }
+    );
+    assert_eq!(
+        Manifest::from_fs(&dst, Path::new("/public")).unwrap(),
+        Manifest::from_fs(&src, Path::new("/site")).unwrap()
+    );
+
+    let report = sync(
+        &src,
+        Path::new("/site"),
+        &mut dst,
+        Path::new("/public"),
+        &options,
+    )
+    .unwrap();
+    assert!(report.is_unchanged());
+    assert_eq!(report.unchanged, 2);
+}
+
+#[test]
+fn test_sync_updates_and_deletes() {
+    let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+    let mut src = MockFS::with_clock(Arc::new(clock.clone()));
+    src.add_file(Path::new("/site/a.html"), "a").unwrap();
+    src.add_file(Path::new("/site/b.html"), "b").unwrap();
+    src.add_file(Path::new("/site/dir/c.html"), "c").unwrap();
+    let mut dst = MockFS::new();
+    let options = SyncOptions::default();
+    sync(&src, Path::new("/site"), &mut dst, Path::new("/"), &options).unwrap();
+    dst.add_file(Path::new("/extra.txt"), "extra").unwrap();
+
+    clock.advance(Duration::from_secs(1));
+    write(&mut src, "/site/a.html", "A");
+    src.remove_file(Path::new("/site/b.html")).unwrap();
+    src.remove_dir_all(Path::new("/site/dir")).unwrap();
+    write(&mut src, "/site/dir", "now a file");
+
+    // Without `delete`, only the entry that changed type is removed.
+    let report = sync(&src, Path::new("/site"), &mut dst, Path::new("/"), &options).unwrap();
+    assert_eq!(report.updated, paths(&["/a.html"]));
+    assert_eq!(report.deleted, paths(&["/dir"]));
+    assert_eq!(report.created, paths(&["/dir"]));
+    assert!(dst.is_file(Path::new("/b.html")));
+
+    let options = SyncOptions {
+        delete: true,
+        ..SyncOptions::default()
+    };
+    let report = sync(&src, Path::new("/site"), &mut dst, Path::new("/"), &options).unwrap();
+    assert_eq!(report.deleted, paths(&["/b.html", "/extra.txt"]));
+    assert_eq!(
+        Manifest::from_fs(&dst, Path::new("/")).unwrap(),
+        Manifest::from_fs(&src, Path::new("/site")).unwrap()
+    );
+}
+
+#[test]
+fn test_compare_by_contents_ignores_times() {
+    let src = build();
+    let clock = ManualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(100));
+    let mut dst = MockFS::with_clock(Arc::new(clock));
+    write(&mut dst, "/index.html", "index");
+    write(&mut dst, "/empty", "file");
+    // Same size, different contents.
+    dst.create_dir_all(Path::new("/blog")).unwrap();
+    write(&mut dst, "/blog/post.html", "POST");
+
+    let options = SyncOptions {
+        compare: Compare::Contents,
+        delete: false,
+    };
+    let report = sync(&src, Path::new("/site"), &mut dst, Path::new("/"), &options).unwrap();
+    assert_eq!(report.unchanged, 1);
+    assert_eq!(report.updated, paths(&["/blog/post.html"]));
+    assert_eq!(
+        dst.read_all_lines(Path::new("/blog/post.html")).unwrap(),
+        vec!["post"]
+    );
+
+    // By size and time, the file with a different time is copied again.
+    let report = sync(
+        &src,
+        Path::new("/site"),
+        &mut dst,
+        Path::new("/"),
+        &SyncOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(report.updated, paths(&["/index.html"]));
+}
+
+#[test]
+fn test_sync_to_os_filesystem() {
+    let src = build();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let public = temp_dir.path().join("public");
+    let mut dst = OsFs {};
+    let options = SyncOptions {
+        delete: true,
+        ..SyncOptions::default()
+    };
+    sync(&src, Path::new("/site"), &mut dst, &public, &options).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(public.join("blog/post.html")).unwrap(),
+        "post"
+    );
+    let report = sync(&src, Path::new("/site"), &mut dst, &public, &options).unwrap();
+    assert!(report.is_unchanged());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sync_does_not_follow_directory_links() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src = temp_dir.path().join("src");
+    std::fs::create_dir_all(src.join("a")).unwrap();
+    std::fs::write(src.join("a/x.txt"), "x").unwrap();
+    std::os::unix::fs::symlink("..", src.join("a/loop")).unwrap();
+
+    let mut dst = MockFS::new();
+    dst.add_file(Path::new("/pub/a/loop/stale.txt"), "")
+        .unwrap();
+    let options = SyncOptions {
+        delete: true,
+        ..SyncOptions::default()
+    };
+    sync(&OsFs {}, &src, &mut dst, Path::new("/pub"), &options).unwrap();
+    assert_eq!(dst.get_str(Path::new("/pub/a/x.txt")).unwrap(), "x");
+    assert!(dst.is_dir(Path::new("/pub/a/loop")));
+    assert!(!dst.exists(Path::new("/pub/a/loop/stale.txt")));
+    assert_eq!(
+        Manifest::from_fs(&dst, Path::new("/pub")).unwrap(),
+        Manifest::from_fs(&OsFs {}, &src).unwrap()
+    );
+}
+
+#[test]
+fn test_sync_errors() {
+    let src = build();
+    let mut dst = MockFS::new();
+    dst.add_file(Path::new("/file"), "").unwrap();
+    let options = SyncOptions::default();
+    assert!(matches!(
+        sync(
+            &src,
+            Path::new("/site"),
+            &mut dst,
+            Path::new("/file"),
+            &options
+        ),
+        Err(XfsError::NotADirectory { .. })
+    ));
+    assert!(matches!(
+        sync(
+            &src,
+            Path::new("/missing"),
+            &mut dst,
+            Path::new("/out"),
+            &options
+        ),
+        Err(XfsError::NotFound { .. })
+    ));
+}
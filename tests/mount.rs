@@ -0,0 +1,233 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::mount::MountFs;
+use inscenerator_xfs::{Xfs, XfsError, XfsReadOnly};
+
+fn pipeline() -> (MountFs, Box<dyn Xfs>) {
+    let mut templates = MockFS::new();
+    templates.add_file(Path::new("/page.html"), "page").unwrap();
+    let mut assets = MockFS::new();
+    assets.add_file(Path::new("/logo.png"), "logo").unwrap();
+    let mut out = MockFS::new();
+    let out_view = out.unsafe_clone_mut();
+
+    let mut fs = MountFs::new();
+    fs.mount_read_only(Path::new("/src/templates"), Box::new(templates))
+        .unwrap();
+    fs.mount_read_only(Path::new("/src/assets"), Box::new(assets))
+        .unwrap();
+    fs.mount(Path::new("/out"), Box::new(out)).unwrap();
+    (fs, out_view)
+}
+
+fn list(fs: &MountFs, p: &str) -> Vec<(PathBuf, bool)> {
+    let mut entries: Vec<_> = fs
+        .read_dir(Path::new(p))
+        .unwrap()
+        .map(|e| {
+            let e = e.unwrap();
+            (e.path(), e.metadata().unwrap().is_dir())
+        })
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn test_calls_are_routed_to_mounts() {
+    let (mut fs, out) = pipeline();
+    assert_eq!(
+        fs.read_all_lines(Path::new("/src/templates/page.html"))
+            .unwrap(),
+        vec!["page"]
+    );
+    assert!(fs.is_file(Path::new("/src/assets/logo.png")));
+    assert!(!fs.exists(Path::new("/src/assets/page.html")));
+
+    fs.writer(Path::new("/out/page.html"))
+        .unwrap()
+        .write_all(b"done")
+        .unwrap();
+    assert_eq!(
+        out.read_all_lines(Path::new("/page.html")).unwrap(),
+        vec!["done"]
+    );
+}
+
+#[test]
+fn test_longest_mount_point_wins() {
+    let mut root = MockFS::new();
+    root.add_file(Path::new("/a/b/file"), "root").unwrap();
+    let mut nested = MockFS::new();
+    nested.add_file(Path::new("/file"), "nested").unwrap();
+
+    let mut fs = MountFs::new();
+    fs.mount(Path::new("/"), Box::new(root)).unwrap();
+    fs.mount(Path::new("/a/b"), Box::new(nested)).unwrap();
+
+    assert_eq!(
+        fs.read_all_lines(Path::new("/a/b/file")).unwrap(),
+        vec!["nested"]
+    );
+    assert_eq!(
+        fs.read_all_lines(Path::new("/a/x/../b/file")).unwrap(),
+        vec!["nested"]
+    );
+    // The nested mount hides the root backend's entry of the same name.
+    assert_eq!(list(&fs, "/a"), vec![(PathBuf::from("/a/b"), true)]);
+}
+
+#[test]
+fn test_parent_directories_are_synthesized() {
+    let (mut fs, _out) = pipeline();
+    assert!(fs.is_dir(Path::new("/")));
+    assert!(fs.is_dir(Path::new("/src")));
+    assert_eq!(
+        list(&fs, "/"),
+        vec![(PathBuf::from("/out"), true), (PathBuf::from("/src"), true)]
+    );
+    assert_eq!(
+        list(&fs, "/src"),
+        vec![
+            (PathBuf::from("/src/assets"), true),
+            (PathBuf::from("/src/templates"), true)
+        ]
+    );
+    assert!(matches!(
+        fs.metadata(Path::new("/elsewhere")),
+        Err(XfsError::NotFound { .. })
+    ));
+    fs.create_dir_all(Path::new("/src")).unwrap();
+    assert!(matches!(
+        fs.create_dir(Path::new("/src")),
+        Err(XfsError::AlreadyExists { .. })
+    ));
+    assert!(matches!(
+        fs.set_permissions(Path::new("/src"), 0o700),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(matches!(
+        fs.writer(Path::new("/elsewhere/file")),
+        Err(XfsError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_read_only_mounts_reject_modification() {
+    let (mut fs, _out) = pipeline();
+    assert!(matches!(
+        fs.writer(Path::new("/src/templates/new.html")),
+        Err(XfsError::ReadOnlyFilesystem { .. })
+    ));
+    assert!(matches!(
+        fs.remove_file(Path::new("/src/templates/page.html")),
+        Err(XfsError::ReadOnlyFilesystem { .. })
+    ));
+    assert!(fs.exists(Path::new("/src/templates/page.html")));
+}
+
+#[test]
+fn test_rename_across_mounts_is_an_error() {
+    let (mut fs, _out) = pipeline();
+    fs.writer(Path::new("/out/a")).unwrap();
+    match fs.rename(Path::new("/out/a"), Path::new("/src/assets/a")) {
+        Err(XfsError::CrossesDevices { path }) => assert_eq!(path, Path::new("/out/a")),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    fs.rename(Path::new("/out/a"), Path::new("/out/b")).unwrap();
+    assert!(fs.is_file(Path::new("/out/b")));
+}
+
+#[test]
+fn test_mount_points_cannot_be_removed_or_moved() {
+    let mut root = MockFS::new();
+    root.create_dir_all(Path::new("/site/data")).unwrap();
+    let mut data = MockFS::new();
+    data.add_file(Path::new("/a.txt"), "a").unwrap();
+    let data_view = data.unsafe_clone();
+
+    let mut fs = MountFs::new();
+    fs.mount(Path::new("/"), Box::new(root)).unwrap();
+    fs.mount(Path::new("/site/data"), Box::new(data)).unwrap();
+
+    let in_use = |r: inscenerator_xfs::Result<()>, p: &str| match r {
+        Err(XfsError::FileInUse { path }) => assert_eq!(path, Path::new(p)),
+        r => panic!("unexpected result: {:?}", r),
+    };
+    in_use(fs.remove_dir_all(Path::new("/site/data/")), "/site/data/");
+    in_use(fs.remove_dir_all(Path::new("/site")), "/site");
+    in_use(fs.rename(Path::new("/site"), Path::new("/moved")), "/site");
+    in_use(
+        fs.rename(Path::new("/site/data"), Path::new("/site/data/x")),
+        "/site/data",
+    );
+    fs.create_dir(Path::new("/empty")).unwrap();
+    in_use(fs.rename(Path::new("/empty"), Path::new("/site")), "/site");
+    assert!(data_view.is_file(Path::new("/a.txt")));
+
+    fs.remove_file(Path::new("/site/data/a.txt")).unwrap();
+    assert!(!data_view.exists(Path::new("/a.txt")));
+}
+
+#[test]
+fn test_synthesized_directories_cannot_be_removed_or_moved() {
+    let (mut fs, mut out) = pipeline();
+    out.create_dir(Path::new("/empty")).unwrap();
+    let in_use = |r: inscenerator_xfs::Result<()>, p: &str| match r {
+        Err(XfsError::FileInUse { path }) => assert_eq!(path, Path::new(p)),
+        r => panic!("unexpected result: {:?}", r),
+    };
+    in_use(fs.remove_dir_all(Path::new("/src")), "/src");
+    in_use(fs.remove_dir_all(Path::new("/")), "/");
+    in_use(fs.rename(Path::new("/src"), Path::new("/out/src")), "/src");
+    in_use(
+        fs.rename(Path::new("/out/empty"), Path::new("/src")),
+        "/src",
+    );
+    assert!(fs.is_dir(Path::new("/src/templates")));
+}
+
+#[test]
+fn test_sync_succeeds_everywhere() {
+    let (mut fs, _out) = pipeline();
+    fs.writer(Path::new("/out/a")).unwrap();
+    for p in [
+        "/",
+        "/src",
+        "/src/templates",
+        "/src/templates/page.html",
+        "/out/a",
+    ] {
+        fs.sync_all(Path::new(p)).unwrap();
+        fs.sync_data(Path::new(p)).unwrap();
+    }
+    assert!(matches!(
+        fs.sync_all(Path::new("/src/templates/missing")),
+        Err(XfsError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_error_paths_are_in_the_mount_namespace() {
+    let (fs, _out) = pipeline();
+    match fs.reader(Path::new("/src/assets/missing.png")) {
+        Err(XfsError::NotFound { path }) => {
+            assert_eq!(path, Path::new("/src/assets/missing.png"))
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn test_mounting_twice_at_the_same_point_fails() {
+    let mut fs = MountFs::new();
+    fs.mount(Path::new("/a"), Box::new(MockFS::new())).unwrap();
+    assert!(matches!(
+        fs.mount(Path::new("/a/"), Box::new(MockFS::new())),
+        Err(XfsError::AlreadyExists { .. })
+    ));
+    assert_eq!(fs.mount_points().collect::<Vec<_>>(), vec![Path::new("/a")]);
+}
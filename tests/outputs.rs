@@ -0,0 +1,215 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::outputs::OutputTrackingFs;
+use inscenerator_xfs::{Xfs, XfsReadOnly};
+
+fn write(fs: &mut dyn Xfs, p: &str) {
+    fs.writer(Path::new(p))
+        .unwrap()
+        .write_all(p.as_bytes())
+        .unwrap();
+}
+
+/// Runs a generator that writes `pages` below `/public`, then prunes.
+fn run(fs: &mut MockFS, pages: &[&str]) -> Vec<PathBuf> {
+    let mut tracking = OutputTrackingFs::new(fs.unsafe_clone_mut(), Path::new("/public"));
+    for page in pages {
+        let p = Path::new(page);
+        tracking.create_dir_all(p.parent().unwrap()).unwrap();
+        write(&mut tracking, page);
+    }
+    tracking.prune_stale().unwrap()
+}
+
+#[test]
+fn test_prunes_files_not_written_again() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/public/robots.txt"), "by hand")
+        .unwrap();
+    let removed = run(
+        &mut fs,
+        &[
+            "/public/index.html",
+            "/public/blog/a/index.html",
+            "/public/blog/b/index.html",
+        ],
+    );
+    assert!(removed.is_empty());
+    assert_eq!(
+        fs.read_all_lines(Path::new("/public/.xfs-outputs"))
+            .unwrap(),
+        vec!["blog/a/index.html", "blog/b/index.html", "index.html"]
+    );
+
+    let removed = run(
+        &mut fs,
+        &["/public/index.html", "/public/blog/b/index.html"],
+    );
+    assert_eq!(
+        removed,
+        vec![
+            PathBuf::from("/public/blog/a/index.html"),
+            PathBuf::from("/public/blog/a"),
+        ]
+    );
+    assert!(fs.is_file(Path::new("/public/blog/b/index.html")));
+    // Files the generator never wrote are left alone.
+    assert!(fs.is_file(Path::new("/public/robots.txt")));
+
+    // Emptying the output removes every directory below the root.
+    let removed = run(&mut fs, &[]);
+    assert_eq!(
+        removed,
+        vec![
+            PathBuf::from("/public/blog/b/index.html"),
+            PathBuf::from("/public/blog/b"),
+            PathBuf::from("/public/blog"),
+            PathBuf::from("/public/index.html"),
+        ]
+    );
+    assert!(fs.is_dir(Path::new("/public")));
+}
+
+#[test]
+fn test_tracks_only_files_below_the_root() {
+    let mut fs = MockFS::new();
+    fs.create_dir_all(Path::new("/public/css")).unwrap();
+    fs.create_dir_all(Path::new("/cache")).unwrap();
+    let mut tracking = OutputTrackingFs::new(fs.unsafe_clone_mut(), Path::new("/public"));
+    write(&mut tracking, "/cache/state");
+    write(&mut tracking, "/public/./css/site.css");
+    write(&mut tracking, "/public/old.css");
+    tracking
+        .rename(
+            Path::new("/public/old.css"),
+            Path::new("/public/css/new.css"),
+        )
+        .unwrap();
+    write(&mut tracking, "/public/tmp");
+    tracking.remove_file(Path::new("/public/tmp")).unwrap();
+    assert_eq!(
+        tracking.written(),
+        vec![
+            PathBuf::from("/public/css/new.css"),
+            PathBuf::from("/public/css/site.css"),
+        ]
+    );
+}
+
+#[test]
+fn test_stale_previews_without_removing() {
+    let mut fs = MockFS::new();
+    run(&mut fs, &["/public/a.html", "/public/b.html"]);
+    // A stale file removed by hand is no longer reported.
+    fs.remove_file(Path::new("/public/b.html")).unwrap();
+
+    let tracking = OutputTrackingFs::new(fs.unsafe_clone_mut(), Path::new("/public"));
+    assert_eq!(
+        tracking.stale().unwrap(),
+        vec![PathBuf::from("/public/a.html")]
+    );
+    assert!(fs.is_file(Path::new("/public/a.html")));
+}
+
+#[test]
+fn test_custom_manifest_location() {
+    let mut fs = MockFS::new();
+    fs.create_dir_all(Path::new("/public")).unwrap();
+    let mut tracking = OutputTrackingFs::with_manifest(
+        fs.unsafe_clone_mut(),
+        Path::new("/public"),
+        Path::new("/.cache/outputs"),
+    );
+    write(&mut tracking, "/public/index.html");
+    tracking.save_manifest().unwrap();
+    assert_eq!(
+        fs.read_all_lines(Path::new("/.cache/outputs")).unwrap(),
+        vec!["index.html"]
+    );
+    assert!(!fs.exists(Path::new("/public/.xfs-outputs")));
+}
+
+#[test]
+fn test_manifest_lines_outside_the_root_are_ignored() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/important.txt"), "keep").unwrap();
+    fs.add_file(Path::new("/public/page.html"), "stale")
+        .unwrap();
+    fs.add_file(
+        Path::new("/public/.xfs-outputs"),
+        "../important.txt\nblog/../../important.txt\n./page.html\npage.html\n",
+    )
+    .unwrap();
+
+    let removed = run(&mut fs, &[]);
+    assert_eq!(removed, vec![PathBuf::from("/public/page.html")]);
+    assert!(fs.is_file(Path::new("/important.txt")));
+}
+
+#[test]
+fn test_paths_written_another_way_are_tracked() {
+    let mut fs = MockFS::new();
+    run(&mut fs, &["/public/a.html", "/public/b.html"]);
+
+    // MockFS resolves relative paths from the root, so `public/a.html` is the
+    // file written as `/public/a.html` last time.
+    let mut tracking = OutputTrackingFs::new(fs.unsafe_clone_mut(), Path::new("/public"));
+    write(&mut tracking, "public/a.html");
+    write(&mut tracking, "/public/./b.html");
+    assert!(tracking.prune_stale().unwrap().is_empty());
+    assert!(fs.is_file(Path::new("/public/a.html")));
+    assert!(fs.is_file(Path::new("/public/b.html")));
+}
+
+#[test]
+fn test_directories_moved_into_the_root_are_tracked() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/staging/site/index.html"), "")
+        .unwrap();
+    fs.add_file(Path::new("/staging/site/blog/a.html"), "")
+        .unwrap();
+    fs.create_dir_all(Path::new("/public")).unwrap();
+
+    let mut tracking = OutputTrackingFs::new(fs.unsafe_clone_mut(), Path::new("/public"));
+    tracking
+        .rename(Path::new("/staging/site"), Path::new("/public/site"))
+        .unwrap();
+    assert_eq!(
+        tracking.written(),
+        vec![
+            PathBuf::from("/public/site/blog/a.html"),
+            PathBuf::from("/public/site/index.html"),
+        ]
+    );
+    tracking.prune_stale().unwrap();
+
+    let removed = run(&mut fs, &[]);
+    assert_eq!(removed.len(), 4);
+    assert!(!fs.exists(Path::new("/public/site")));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_outputs_are_rejected() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut fs = MockFS::new();
+    fs.create_dir_all(Path::new("/public")).unwrap();
+    fs.add_file(Path::new("/staging/caf\u{e9}.txt"), "")
+        .unwrap();
+    let name = Path::new(OsStr::from_bytes(b"/public/caf\xe9.txt"));
+
+    let mut tracking = OutputTrackingFs::new(fs.unsafe_clone_mut(), Path::new("/public"));
+    assert!(tracking.writer(name).is_err());
+    assert!(tracking
+        .rename(Path::new("/staging/caf\u{e9}.txt"), name)
+        .is_err());
+    assert!(!fs.exists(name));
+    // Files outside the output root are not tracked, so any name is allowed.
+    tracking
+        .writer(Path::new(OsStr::from_bytes(b"/staging/\xff")))
+        .unwrap();
+}
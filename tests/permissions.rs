@@ -0,0 +1,174 @@
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::{OsFs, Xfs, XfsError, XfsReadOnly};
+use std::io::Write;
+use std::path::Path;
+
+#[test]
+fn test_mockfs_default_modes() {
+    let mut fs = MockFS::new();
+    fs.create_dir(Path::new("dir")).unwrap();
+    fs.writer(Path::new("dir/file.txt")).unwrap();
+
+    assert_eq!(fs.metadata(Path::new("dir")).unwrap().mode(), 0o755);
+    assert_eq!(
+        fs.metadata(Path::new("dir/file.txt")).unwrap().mode(),
+        0o644
+    );
+}
+
+#[test]
+fn test_mockfs_unreadable_file() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("secret.txt"), "shh").unwrap();
+    fs.set_permissions(Path::new("secret.txt"), 0o200).unwrap();
+
+    assert!(matches!(
+        fs.reader(Path::new("secret.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(matches!(
+        fs.read_all_lines(Path::new("secret.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    // Writing is still allowed.
+    fs.writer(Path::new("secret.txt"))
+        .unwrap()
+        .write_all(b"new")
+        .unwrap();
+}
+
+#[test]
+fn test_mockfs_read_only_output_dir() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("out/existing.txt"), "old").unwrap();
+    fs.set_permissions(Path::new("out"), 0o555).unwrap();
+
+    assert!(matches!(
+        fs.writer(Path::new("out/new.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(matches!(
+        fs.create_dir(Path::new("out/sub")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(matches!(
+        fs.create_dir_all(Path::new("out/a/b")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(matches!(
+        fs.remove_file(Path::new("out/existing.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(matches!(
+        fs.rename(Path::new("out/existing.txt"), Path::new("moved.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(fs.is_file(Path::new("out/existing.txt")));
+
+    // Existing files can still be rewritten, since that needs no directory access.
+    fs.writer(Path::new("out/existing.txt")).unwrap();
+}
+
+#[test]
+fn test_mockfs_unsearchable_dir() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("locked/a.txt"), "a").unwrap();
+    fs.set_permissions(Path::new("locked"), 0o644).unwrap();
+
+    // Without execute permission the names can be listed, but not used.
+    assert_eq!(fs.read_dir(Path::new("locked")).unwrap().count(), 1);
+    let denied = |r: inscenerator_xfs::Result<()>| {
+        assert!(
+            matches!(r, Err(XfsError::PermissionDenied { .. })),
+            "{:?}",
+            r
+        )
+    };
+    denied(fs.metadata(Path::new("locked/a.txt")).map(|_| ()));
+    denied(fs.reader(Path::new("locked/a.txt")).map(|_| ()));
+    denied(fs.writer(Path::new("locked/a.txt")).map(|_| ()));
+    denied(fs.writer(Path::new("locked/b.txt")).map(|_| ()));
+    denied(fs.create_dir_all(Path::new("locked/sub/dir")));
+    denied(fs.remove_file(Path::new("locked/a.txt")));
+    denied(fs.rename(Path::new("locked/a.txt"), Path::new("a.txt")));
+    assert!(!fs.exists(Path::new("locked/a.txt")));
+    assert_eq!(fs.get_str(Path::new("locked/a.txt")).unwrap(), "a");
+}
+
+#[test]
+fn test_mockfs_remove_dir_all_checks_the_whole_tree() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("out/keep/a.txt"), "a").unwrap();
+    fs.add_file(Path::new("out/b.txt"), "b").unwrap();
+    fs.set_permissions(Path::new("out/keep"), 0o555).unwrap();
+
+    match fs.remove_dir_all(Path::new("out")) {
+        Err(XfsError::PermissionDenied { path }) => assert_eq!(path, Path::new("out/keep")),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    // Nothing is removed when any part of the tree cannot be.
+    assert!(fs.is_file(Path::new("out/b.txt")));
+    assert!(fs.is_file(Path::new("out/keep/a.txt")));
+
+    fs.set_permissions(Path::new("out/keep"), 0o755).unwrap();
+    fs.remove_dir_all(Path::new("out")).unwrap();
+}
+
+#[test]
+fn test_mockfs_other_users() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("mine.txt"), "hello").unwrap();
+    fs.set_permissions(Path::new("mine.txt"), 0o640).unwrap();
+
+    // A member of the same group can read but not write.
+    fs.set_user(2000, inscenerator_xfs::mockfs::DEFAULT_GID);
+    assert!(fs.reader(Path::new("mine.txt")).is_ok());
+    assert!(matches!(
+        fs.writer(Path::new("mine.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+
+    // Anyone else can do neither, and cannot change the mode.
+    fs.set_user(2000, 2000);
+    assert!(matches!(
+        fs.reader(Path::new("mine.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(matches!(
+        fs.set_permissions(Path::new("mine.txt"), 0o666),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+
+    // Ownership can be handed over explicitly.
+    fs.set_owner(Path::new("mine.txt"), 2000, 2000).unwrap();
+    fs.set_permissions(Path::new("mine.txt"), 0o600).unwrap();
+    assert!(fs.reader(Path::new("mine.txt")).is_ok());
+}
+
+#[test]
+fn test_mockfs_permissions_shared_with_clones() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("a.txt"), "a").unwrap();
+    let mut fs_clone = fs.unsafe_clone_mut();
+    fs_clone.set_permissions(Path::new("a.txt"), 0o000).unwrap();
+
+    assert_eq!(fs.metadata(Path::new("a.txt")).unwrap().mode(), 0o000);
+    assert!(matches!(
+        fs.reader(Path::new("a.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_osfs_set_permissions() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut fs = OsFs {};
+    let path = temp_dir.path().join("test.txt");
+
+    fs.writer(&path).unwrap().write_all(b"hello").unwrap();
+    fs.set_permissions(&path, 0o400).unwrap();
+    assert_eq!(fs.metadata(&path).unwrap().mode(), 0o400);
+    fs.set_permissions(&path, 0o644).unwrap();
+    assert_eq!(fs.metadata(&path).unwrap().mode(), 0o644);
+}
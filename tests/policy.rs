@@ -0,0 +1,335 @@
+use std::path::{Path, PathBuf};
+
+use inscenerator_xfs::glob::Glob;
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::policy::{Policy, PolicyFs, Rules};
+use inscenerator_xfs::strict::StrictFs;
+use inscenerator_xfs::{Xfs, XfsError, XfsReadOnly};
+
+#[test]
+fn test_glob_matching() {
+    let cases = [
+        ("*.txt", "a.txt", true),
+        ("*.txt", "dir/a.txt", false),
+        ("**/*.txt", "a.txt", true),
+        ("**/*.txt", "dir/sub/a.txt", true),
+        ("dir/**", "dir", true),
+        ("dir/**", "dir/a/b", true),
+        ("dir/**", "dirt/a", false),
+        ("/abs/*", "/abs/a", true),
+        ("/abs/*", "abs/a", false),
+        ("a?c", "abc", true),
+        ("a?c", "ac", false),
+        ("[a-c]x", "bx", true),
+        ("[a-c]x", "dx", false),
+        ("[!a-c]x", "dx", true),
+        ("dir/**", "dir/../etc", false),
+        ("etc", "dir/../etc", true),
+        ("dir/*", "./dir/./a", true),
+        ("**/*.md", "../../x.md", false),
+        ("**", "../x", false),
+        ("*/x", "../x", false),
+        ("../*", "../x", true),
+        ("a/**/x", "a/../../x", false),
+    ];
+    for (pattern, path, expected) in cases {
+        assert_eq!(
+            Glob::new(pattern).unwrap().matches(Path::new(path)),
+            expected,
+            "{} against {}",
+            pattern,
+            path
+        );
+    }
+}
+
+#[test]
+fn test_rules_deny_paths_escaping_upwards() {
+    let rules = Rules::new(&["**", "../**"], &[]).unwrap();
+    assert!(rules.allows(Path::new("a/../b")));
+    assert!(!rules.allows(Path::new("../secret")));
+    assert!(!rules.allows(Path::new("a/../../secret")));
+    assert!(Rules::allow_all().allows(Path::new("/../etc")));
+}
+
+#[test]
+fn test_invalid_glob() {
+    assert!(Glob::new("a[bc").is_err());
+}
+
+fn inner() -> MockFS {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("/plugin/config.toml"), "config")
+        .unwrap();
+    fs.add_file(Path::new("/plugin/secret.key"), "key").unwrap();
+    fs.add_file(Path::new("/plugin/out/old.txt"), "old")
+        .unwrap();
+    fs.add_file(Path::new("/plugin/out/keep/a.txt"), "a")
+        .unwrap();
+    fs.add_file(Path::new("/other/a.txt"), "other").unwrap();
+    fs
+}
+
+fn policy(hide_denied: bool) -> Policy {
+    Policy {
+        read: Rules::new(&["/plugin/**"], &["**/*.key"]).unwrap(),
+        write: Rules::new(&["/plugin/out/**"], &["/plugin/out/keep/**"]).unwrap(),
+        hide_denied,
+    }
+}
+
+fn list(fs: &dyn XfsReadOnly, p: &str) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs
+        .read_dir(Path::new(p))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn test_reads_restricted() {
+    let fs = PolicyFs::new(Box::new(inner()), policy(false));
+    assert_eq!(
+        fs.read_all_lines(Path::new("/plugin/config.toml")).unwrap(),
+        vec!["config"]
+    );
+    assert!(matches!(
+        fs.reader(Path::new("/plugin/secret.key")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(matches!(
+        fs.metadata(Path::new("/other/a.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(matches!(
+        fs.read_dir(Path::new("/")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(matches!(
+        fs.reader(Path::new("/plugin/../other/a.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+}
+
+#[test]
+fn test_read_dir_filters_denied_entries() {
+    let fs = PolicyFs::new(Box::new(inner()), policy(false));
+    assert_eq!(
+        list(&fs, "/plugin"),
+        vec![
+            PathBuf::from("/plugin/config.toml"),
+            PathBuf::from("/plugin/out")
+        ]
+    );
+}
+
+#[test]
+fn test_hide_denied() {
+    let mut fs = PolicyFs::new(Box::new(inner()), policy(true));
+    assert!(!fs.exists(Path::new("/plugin/secret.key")));
+    assert!(matches!(
+        fs.reader(Path::new("/other/a.txt")),
+        Err(XfsError::NotFound { .. })
+    ));
+    assert!(matches!(
+        fs.writer(Path::new("/other/b.txt")),
+        Err(XfsError::NotFound { .. })
+    ));
+    // Paths that are visible but not writable are still reported as denied.
+    assert!(matches!(
+        fs.writer(Path::new("/plugin/config.toml")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+}
+
+#[test]
+fn test_writes_restricted() {
+    let mut fs = PolicyFs::new(Box::new(inner()), policy(false));
+    fs.writer(Path::new("/plugin/out/new.txt")).unwrap();
+    fs.create_dir_all(Path::new("/plugin/out/a/b")).unwrap();
+    fs.remove_file(Path::new("/plugin/out/old.txt")).unwrap();
+
+    let denied =
+        |r: inscenerator_xfs::Result<()>| matches!(r, Err(XfsError::PermissionDenied { .. }));
+    assert!(denied(fs.create_dir_all(Path::new("/plugin/other/a"))));
+    assert!(denied(fs.remove_file(Path::new("/plugin/config.toml"))));
+    assert!(denied(fs.remove_file(Path::new("/plugin/out/keep/a.txt"))));
+    // Moving or removing a directory must not affect protected descendants.
+    assert!(denied(fs.remove_dir_all(Path::new("/plugin/out"))));
+    assert!(denied(fs.rename(
+        Path::new("/plugin/out/keep"),
+        Path::new("/plugin/out/moved")
+    )));
+    assert!(denied(fs.rename(
+        Path::new("/plugin/out/new.txt"),
+        Path::new("/plugin/config.toml")
+    )));
+    fs.remove_dir_all(Path::new("/plugin/out/a")).unwrap();
+
+    let inner = fs.into_inner();
+    assert!(inner.exists(Path::new("/plugin/out/new.txt")));
+    assert!(!inner.exists(Path::new("/plugin/out/old.txt")));
+    assert!(inner.exists(Path::new("/plugin/out/keep/a.txt")));
+}
+
+#[test]
+fn test_rename_cannot_make_unreadable_files_readable() {
+    let mut inner = MockFS::new();
+    inner.add_file(Path::new("/secret/key"), "hunter2").unwrap();
+    inner
+        .add_file(Path::new("/secret/dir/key"), "hunter2")
+        .unwrap();
+    inner.create_dir_all(Path::new("/pub")).unwrap();
+    let policy = Policy {
+        read: Rules::new(&["/pub/**"], &[]).unwrap(),
+        write: Rules::new(&["/secret/**", "/pub/**"], &[]).unwrap(),
+        hide_denied: false,
+    };
+    let mut fs = PolicyFs::new(Box::new(inner), policy);
+    for from in ["/secret/key", "/secret/dir"] {
+        assert!(matches!(
+            fs.rename(Path::new(from), Path::new("/pub/key")),
+            Err(XfsError::PermissionDenied { .. })
+        ));
+    }
+    assert!(!fs.exists(Path::new("/pub/key")));
+}
+
+#[test]
+fn test_read_only_view() {
+    let inner: Box<dyn XfsReadOnly> = Box::new(inner());
+    let policy = Policy {
+        read: Rules::allow_all(),
+        ..Policy::default()
+    };
+    let fs = PolicyFs::new(inner, policy);
+    assert!(fs.is_file(Path::new("/other/a.txt")));
+    assert_eq!(
+        list(&fs, "/"),
+        vec![PathBuf::from("/other"), PathBuf::from("/plugin")]
+    );
+}
+
+#[test]
+fn test_rules_match_paths_however_written() {
+    let mut inner = MockFS::new();
+    inner
+        .add_file(Path::new("/secrets/key"), "hunter2")
+        .unwrap();
+    inner.add_file(Path::new("/pub/a.txt"), "a").unwrap();
+    let policy = Policy {
+        read: Rules::new(&["**"], &["/secrets/**"]).unwrap(),
+        write: Rules::new(&["**"], &["/secrets/**"]).unwrap(),
+        hide_denied: false,
+    };
+    let mut fs = PolicyFs::new(Box::new(inner), policy);
+    for p in [
+        "/secrets/key",
+        "secrets/key",
+        "./secrets/key",
+        "pub/../secrets/key",
+    ] {
+        assert!(
+            matches!(
+                fs.reader(Path::new(p)),
+                Err(XfsError::PermissionDenied { .. })
+            ),
+            "{}",
+            p
+        );
+        assert!(
+            matches!(
+                fs.remove_file(Path::new(p)),
+                Err(XfsError::PermissionDenied { .. })
+            ),
+            "{}",
+            p
+        );
+    }
+    assert_eq!(list(&fs, "."), vec![PathBuf::from("./pub")]);
+}
+
+#[test]
+fn test_relative_patterns_are_anchored_at_the_current_directory() {
+    let rules = Rules::new(&["src/**"], &["src/secret/**"]).unwrap();
+    let cwd = Path::new("/work");
+    assert!(rules.allows_from(Path::new("/work/src/a.rs"), cwd));
+    assert!(!rules.allows_from(Path::new("/work/src/secret/key"), cwd));
+    assert!(!rules.allows_from(Path::new("/src/a.rs"), cwd));
+    assert!(!rules.allows_from(Path::new("/other/work/src/a.rs"), cwd));
+
+    let mut inner = MockFS::new();
+    inner
+        .add_file(Path::new("/secrets/key"), "hunter2")
+        .unwrap();
+    inner.add_file(Path::new("/pub/a.txt"), "a").unwrap();
+    let policy = Policy {
+        read: Rules::new(&["pub/**", "secrets/**"], &["secrets/**"]).unwrap(),
+        write: Rules::default(),
+        hide_denied: false,
+    };
+    let fs = PolicyFs::new(Box::new(inner), policy);
+    for p in ["secrets/key", "/secrets/key", "pub/../secrets/key"] {
+        assert!(
+            matches!(
+                fs.reader(Path::new(p)),
+                Err(XfsError::PermissionDenied { .. })
+            ),
+            "{}",
+            p
+        );
+    }
+    assert!(fs.reader(Path::new("/pub/a.txt")).is_ok());
+    assert!(fs.reader(Path::new("pub/a.txt")).is_ok());
+}
+
+#[test]
+fn test_create_dir_all_does_not_look_up_hidden_ancestors() {
+    let inner = StrictFs::new();
+    inner
+        .expect_metadata("/hidden/a/b")
+        .returning_error(XfsError::NotFound {
+            path: PathBuf::from("/hidden/a/b"),
+        });
+    inner
+        .expect_metadata("/hidden/a")
+        .returning_error(XfsError::NotFound {
+            path: PathBuf::from("/hidden/a"),
+        });
+    // `/hidden` is neither readable nor writable, so it is never looked up.
+    inner
+        .expect_create_dir("/hidden/a")
+        .returning_error(XfsError::NotFound {
+            path: PathBuf::from("/hidden/a"),
+        });
+    let policy = Policy {
+        read: Rules::new(&["/pub/**"], &[]).unwrap(),
+        write: Rules::new(&["/hidden/a/**"], &[]).unwrap(),
+        hide_denied: true,
+    };
+    let mut fs = PolicyFs::new(Box::new(inner), policy);
+    assert!(matches!(
+        fs.create_dir_all(Path::new("/hidden/a/b")),
+        Err(XfsError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_create_dir_all_below_unreadable_root() {
+    let mut inner = MockFS::new();
+    inner.create_dir_all(Path::new("/pub")).unwrap();
+    let policy = Policy {
+        read: Rules::new(&["/pub/**"], &[]).unwrap(),
+        write: Rules::new(&["/pub/**", "/new/**"], &[]).unwrap(),
+        hide_denied: true,
+    };
+    let mut fs = PolicyFs::new(Box::new(inner), policy);
+    fs.create_dir_all(Path::new("/pub/a/b")).unwrap();
+    fs.create_dir_all(Path::new("/new/a")).unwrap();
+    fs.create_dir_all(Path::new("/new/a")).unwrap();
+    let inner = fs.into_inner();
+    assert!(inner.is_dir(Path::new("/pub/a/b")));
+    assert!(inner.is_dir(Path::new("/new/a")));
+}
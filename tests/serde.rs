@@ -0,0 +1,124 @@
+#![cfg(feature = "serde")]
+
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::{Xfs, XfsReadOnly};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn test_mockfs_serde_round_trip() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("src/main.rs"), "fn main() {}")
+        .unwrap();
+    fs.add_r(Path::new("bin/data"), vec![0, 159, 146, 150])
+        .unwrap();
+    fs.create_dir(Path::new("empty")).unwrap();
+    fs.set_permissions(Path::new("src/main.rs"), 0o600).unwrap();
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1234);
+    fs.set_times(Path::new("src/main.rs"), None, Some(mtime))
+        .unwrap();
+
+    let json = serde_json::to_string(&fs).unwrap();
+    let loaded: MockFS = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(loaded.tree(), fs.tree());
+    assert!(loaded.is_dir(Path::new("empty")));
+    assert_eq!(
+        loaded.get(Path::new("bin/data")).unwrap(),
+        vec![0, 159, 146, 150]
+    );
+    let md = loaded.metadata(Path::new("src/main.rs")).unwrap();
+    assert_eq!(md.mode(), 0o600);
+    assert_eq!(md.modified(), Some(mtime));
+}
+
+#[test]
+fn test_mockfs_deserialize_minimal_fixture() {
+    let fixture = r#"{
+        "directory": {
+            "entries": {
+                "hello.txt": { "file": { "contents": { "text": "Hello\n" } } },
+                "bin": { "file": { "contents": { "bytes": [0, 255] } } },
+                "locked": { "directory": { "metadata": { "mode": 320 }, "entries": {} } }
+            }
+        }
+    }"#;
+    let fs: MockFS = serde_json::from_str(fixture).unwrap();
+
+    assert_eq!(fs.get_str(Path::new("hello.txt")).unwrap(), "Hello\n");
+    assert_eq!(fs.get(Path::new("bin")).unwrap(), vec![0, 255]);
+    assert_eq!(fs.metadata(Path::new("hello.txt")).unwrap().mode(), 0o644);
+    assert_eq!(fs.metadata(Path::new("locked")).unwrap().mode(), 0o500);
+    // Missing times are fixed, so loading the same fixture gives the same tree.
+    let md = fs.metadata(Path::new("hello.txt")).unwrap();
+    assert_eq!(md.modified(), Some(SystemTime::UNIX_EPOCH));
+    assert_eq!(md.created(), Some(SystemTime::UNIX_EPOCH));
+    let again: MockFS = serde_json::from_str(fixture).unwrap();
+    assert_eq!(again.tree(), fs.tree());
+}
+
+#[test]
+fn test_mockfs_deserialize_rejects_invalid_names() {
+    for name in ["", ".", "..", "a/b", "/a", "a/"] {
+        let fixture = format!(
+            r#"{{ "directory": {{ "entries": {{ {:?}: {{ "directory": {{ "entries": {{}} }} }} }} }} }}"#,
+            name
+        );
+        assert!(
+            serde_json::from_str::<MockFS>(&fixture).is_err(),
+            "{:?}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_mockfs_deserialize_rejects_file_root() {
+    let fixture = r#"{ "file": { "contents": { "text": "oops" } } }"#;
+    assert!(serde_json::from_str::<MockFS>(fixture).is_err());
+}
+
+#[test]
+fn test_mockfs_serde_round_trip_non_self_describing() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("src/main.rs"), "fn main() {}")
+        .unwrap();
+    fs.add_r(Path::new("bin/data"), vec![0, 159, 146, 150])
+        .unwrap();
+    fs.create_dir(Path::new("empty")).unwrap();
+
+    let bytes = bincode::serialize(&fs).unwrap();
+    let loaded: MockFS = bincode::deserialize(&bytes).unwrap();
+
+    assert_eq!(loaded.tree(), fs.tree());
+    assert_eq!(
+        loaded.get(Path::new("bin/data")).unwrap(),
+        vec![0, 159, 146, 150]
+    );
+    assert_eq!(
+        loaded
+            .metadata(Path::new("src/main.rs"))
+            .unwrap()
+            .modified(),
+        fs.metadata(Path::new("src/main.rs")).unwrap().modified()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_mockfs_serde_round_trip_non_utf8_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let name = Path::new(OsStr::from_bytes(b"dir/caf\xe9.txt"));
+    let mut fs = MockFS::new();
+    fs.add_file(name, "latin-1").unwrap();
+
+    let json = serde_json::to_string(&fs).unwrap();
+    let loaded: MockFS = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.get_str(name).unwrap(), "latin-1");
+
+    let bytes = bincode::serialize(&fs).unwrap();
+    let loaded: MockFS = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(loaded.get_str(name).unwrap(), "latin-1");
+}
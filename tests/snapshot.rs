@@ -0,0 +1,137 @@
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::snapshot::{
+    assert_snapshot, assert_snapshot_with, diff, render, write_snapshot,
+};
+use inscenerator_xfs::{mockfs, Xfs};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+fn site() -> MockFS {
+    mockfs!({
+        "site": {
+            "index.html": "<h1>Hello</h1>\n",
+            "posts": { "first.html": "<p>First post</p>\n" },
+            "assets": { "logo.png": b"\x89PNG" },
+            "drafts": {},
+        },
+        "other.txt": "not part of the snapshot",
+    })
+}
+
+#[test]
+fn test_assert_snapshot_matches() {
+    assert_snapshot(
+        &site(),
+        Path::new("site"),
+        Path::new("tests/snapshots/site.txtar"),
+    );
+}
+
+/// Writes the snapshot of `site()` to a temporary directory, so that tests
+/// expecting a mismatch never touch the fixtures.
+fn temp_snapshot() -> (tempfile::TempDir, PathBuf) {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let snapshot = temp_dir.path().join("site.txtar");
+    write_snapshot(&site(), Path::new("site"), &snapshot).unwrap();
+    (temp_dir, snapshot)
+}
+
+#[test]
+#[should_panic(expected = "~~~ changed: index.html")]
+fn test_assert_snapshot_mismatch() {
+    let (_temp_dir, snapshot) = temp_snapshot();
+    let mut fs = site();
+    fs.writer(Path::new("site/index.html")).unwrap();
+    assert_snapshot_with(&fs, Path::new("site"), &snapshot, false);
+}
+
+#[test]
+#[should_panic(expected = "XFS_UPDATE_SNAPSHOTS=1 to create it")]
+fn test_assert_snapshot_missing() {
+    let (temp_dir, _snapshot) = temp_snapshot();
+    assert_snapshot_with(
+        &site(),
+        Path::new("site"),
+        &temp_dir.path().join("does_not_exist.txtar"),
+        false,
+    );
+}
+
+#[test]
+fn test_snapshot_records_binary_contents() {
+    let mut fs = site();
+    let before = render(&fs, Path::new("site")).unwrap();
+    fs.writer(Path::new("site/assets/logo.png"))
+        .unwrap()
+        .write_all(b"\x89GIF")
+        .unwrap();
+    let after = render(&fs, Path::new("site")).unwrap();
+    assert!(diff(&before, &after)
+        .unwrap()
+        .starts_with("~~~ changed: assets/logo.png\n"));
+}
+
+#[test]
+fn test_write_snapshot() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let snapshot = temp_dir.path().join("nested/site.txtar");
+    write_snapshot(&site(), Path::new("site"), &snapshot).unwrap();
+    assert_snapshot_with(&site(), Path::new("site"), &snapshot, false);
+}
+
+#[test]
+fn test_snapshot_diff() {
+    let expected = "-- a.txt --\none\ntwo\nthree\n-- gone.txt --\nx\n";
+    let actual = "-- a.txt --\none\n2\nthree\n-- new.txt --\ny\n";
+    let d = diff(expected, actual).unwrap();
+    assert_eq!(
+        d,
+        "~~~ changed: a.txt\n  one\n- two\n+ 2\n  three\n\
+         --- missing: gone.txt\n\
+         +++ unexpected: new.txt\n"
+    );
+    assert_eq!(diff(expected, expected), None);
+}
+
+#[test]
+fn test_snapshot_render_is_plain() {
+    let fs = mockfs!({ "a": { "b.txt": "quoted \"text\"\n" } });
+    assert_eq!(
+        render(&fs, Path::new("")).unwrap(),
+        "-- a/b.txt --\nquoted \"text\"\n"
+    );
+}
+
+#[test]
+fn test_snapshot_marks_missing_final_newline() {
+    let fs = mockfs!({ "with.txt": "x\n", "without.txt": "x", "empty.txt": "" });
+    assert_eq!(
+        render(&fs, Path::new("")).unwrap(),
+        "-- empty.txt --\n\
+         -- with.txt --\nx\n\
+         -- without.txt --\n<no newline at end of file>\nx\n"
+    );
+}
+
+#[test]
+fn test_snapshot_escapes_text_that_looks_like_a_marker() {
+    let rendered = |fs: &MockFS| render(fs, Path::new("")).unwrap();
+
+    let binary = rendered(&mockfs!({ "f": b"\xff" }));
+    let marker = binary.strip_prefix("-- f --\n").unwrap();
+    assert_eq!(
+        rendered(&mockfs!({ "f": marker })),
+        format!("-- f --\n<text>\n{}", marker)
+    );
+
+    for contents in ["<text>\nx\n", "<no newline at end of file>\nx\n"] {
+        assert_eq!(
+            rendered(&mockfs!({ "f": contents })),
+            format!("-- f --\n<text>\n{}", contents)
+        );
+    }
+    assert_ne!(
+        rendered(&mockfs!({ "f": "x" })),
+        rendered(&mockfs!({ "f": "<no newline at end of file>\nx\n" }))
+    );
+}
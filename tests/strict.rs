@@ -0,0 +1,162 @@
+use std::io::{Read, Write};
+use std::path::Path;
+
+use inscenerator_xfs::strict::StrictFs;
+use inscenerator_xfs::{Overwrite, Xfs, XfsError, XfsReadOnly};
+
+#[test]
+fn test_scripted_calls_succeed() {
+    let mut fs = StrictFs::new();
+    fs.expect_reader("a.txt").returning("hello");
+    fs.expect_create_dir_all("out/sub");
+    fs.expect_rename("a.txt", "b.txt");
+
+    let mut s = String::new();
+    fs.reader(Path::new("a.txt"))
+        .unwrap()
+        .read_to_string(&mut s)
+        .unwrap();
+    assert_eq!(s, "hello");
+    fs.create_dir_all(Path::new("out/sub")).unwrap();
+    fs.rename(Path::new("a.txt"), Path::new("b.txt")).unwrap();
+}
+
+#[test]
+fn test_calls_may_arrive_in_any_order() {
+    let mut fs = StrictFs::new();
+    fs.expect_remove_file("a.txt");
+    fs.expect_remove_file("b.txt");
+
+    fs.remove_file(Path::new("b.txt")).unwrap();
+    fs.remove_file(Path::new("a.txt")).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "unexpected call to StrictFs: remove_file(\"b.txt\")")]
+fn test_unexpected_call_panics() {
+    let mut fs = StrictFs::new();
+    fs.expect_remove_file("a.txt");
+    let _ = fs.remove_file(Path::new("b.txt"));
+}
+
+#[test]
+#[should_panic(expected = "unexpected call to StrictFs: remove_file(\"a.txt\")")]
+fn test_each_expectation_matches_once() {
+    let mut fs = StrictFs::new();
+    fs.expect_remove_file("a.txt");
+    fs.remove_file(Path::new("a.txt")).unwrap();
+    let _ = fs.remove_file(Path::new("a.txt"));
+}
+
+#[test]
+#[should_panic(expected = "expected call never made: remove_file(\"a.txt\")")]
+fn test_unsatisfied_expectation_panics_on_drop() {
+    let fs = StrictFs::new();
+    fs.expect_remove_file("a.txt");
+}
+
+#[test]
+#[should_panic(expected = "expected call never made: create_dir(\"x\")")]
+fn test_verify_reports_unsatisfied_expectations() {
+    let fs = StrictFs::new();
+    fs.expect_create_dir("x");
+    fs.verify();
+}
+
+#[test]
+fn test_expectations_shared_with_clones() {
+    let mut fs = StrictFs::new();
+    fs.expect_create_dir("x");
+    let mut clone = fs.unsafe_clone_mut();
+    clone.create_dir(Path::new("x")).unwrap();
+    drop(clone);
+    fs.verify();
+}
+
+#[test]
+fn test_returning_error() {
+    let mut fs = StrictFs::new();
+    fs.expect_writer("a.txt")
+        .returning_error(XfsError::PermissionDenied {
+            path: "a.txt".into(),
+        });
+    fs.expect_metadata("missing")
+        .returning_error(XfsError::NotFound {
+            path: "missing".into(),
+        });
+
+    assert!(matches!(
+        fs.writer(Path::new("a.txt")),
+        Err(XfsError::PermissionDenied { .. })
+    ));
+    assert!(!fs.exists(Path::new("missing")));
+}
+
+#[test]
+fn test_metadata_and_read_dir() {
+    let fs = StrictFs::new();
+    fs.expect_metadata("d").returning_dir();
+    fs.expect_read_dir("d").returning_entries(["a.txt", "sub/"]);
+
+    assert!(fs.is_dir(Path::new("d")));
+    let entries: Vec<_> = fs
+        .read_dir(Path::new("d"))
+        .unwrap()
+        .map(|e| {
+            let e = e.unwrap();
+            (e.path(), e.metadata().unwrap().is_dir())
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        vec![("d/a.txt".into(), false), ("d/sub".into(), true)]
+    );
+}
+
+#[test]
+fn test_writer_contents_checked() {
+    let mut fs = StrictFs::new();
+    fs.expect_writer("a.txt").expecting_contents("hello\n");
+    writeln!(fs.writer(Path::new("a.txt")).unwrap(), "hello").unwrap();
+}
+
+#[test]
+#[should_panic(expected = "writer(\"a.txt\") wrote \"goodbye\\n\", expected \"hello\\n\"")]
+fn test_writer_contents_mismatch_panics() {
+    let mut fs = StrictFs::new();
+    fs.expect_writer("a.txt").expecting_contents("hello\n");
+    writeln!(fs.writer(Path::new("a.txt")).unwrap(), "goodbye").unwrap();
+}
+
+#[test]
+#[should_panic(expected = "returning_dir cannot be used with an expectation of remove_file")]
+fn test_mismatched_response_panics() {
+    let fs = StrictFs::new();
+    fs.expect_remove_file("a.txt").returning_dir();
+}
+
+#[test]
+fn test_copy_and_move_are_matched_as_single_calls() {
+    let mut fs = StrictFs::new();
+    fs.expect_copy_file("a.txt", "b.txt", Overwrite::Error);
+    fs.expect_copy_recursive("src", "dst", Overwrite::Merge);
+    fs.expect_move_recursive("old", "new", Overwrite::Replace)
+        .returning_error(XfsError::NotFound { path: "old".into() });
+
+    fs.copy_file(Path::new("a.txt"), Path::new("b.txt"), Overwrite::Error)
+        .unwrap();
+    fs.copy_recursive(Path::new("src"), Path::new("dst"), Overwrite::Merge)
+        .unwrap();
+    assert!(matches!(
+        fs.move_recursive(Path::new("old"), Path::new("new"), Overwrite::Replace),
+        Err(XfsError::NotFound { .. })
+    ));
+}
+
+#[test]
+#[should_panic(expected = "unexpected call to StrictFs: copy_file(\"a.txt\", \"b.txt\", Skip)")]
+fn test_copy_with_other_overwrite_panics() {
+    let mut fs = StrictFs::new();
+    fs.expect_copy_file("a.txt", "b.txt", Overwrite::Error);
+    let _ = fs.copy_file(Path::new("a.txt"), Path::new("b.txt"), Overwrite::Skip);
+}
@@ -0,0 +1,98 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::subfs::SubFs;
+use inscenerator_xfs::{Xfs, XfsError, XfsReadOnly};
+
+fn site() -> (Box<dyn Xfs>, SubFs<dyn Xfs>) {
+    let mut inner = MockFS::new();
+    inner
+        .add_file(Path::new("/output/site/index.html"), "home")
+        .unwrap();
+    inner.add_file(Path::new("/output/secret"), "key").unwrap();
+    let view = inner.unsafe_clone_mut();
+    let fs = SubFs::new(Box::new(inner) as Box<dyn Xfs>, Path::new("/output/site"));
+    (view, fs)
+}
+
+#[test]
+fn test_paths_are_relative_to_the_scope_root() {
+    let (view, mut fs) = site();
+    assert_eq!(
+        fs.read_all_lines(Path::new("/index.html")).unwrap(),
+        vec!["home"]
+    );
+    assert!(fs.is_file(Path::new("index.html")));
+    assert!(fs.is_dir(Path::new("/")));
+
+    fs.create_dir_all(Path::new("css")).unwrap();
+    fs.writer(Path::new("/css/style.css"))
+        .unwrap()
+        .write_all(b"body {}")
+        .unwrap();
+    assert_eq!(
+        view.read_all_lines(Path::new("/output/site/css/style.css"))
+            .unwrap(),
+        vec!["body {}"]
+    );
+}
+
+#[test]
+fn test_parent_dir_escapes_are_rejected() {
+    let (view, mut fs) = site();
+    assert!(matches!(
+        fs.reader(Path::new("../secret")),
+        Err(XfsError::PathOutsideSandbox { .. })
+    ));
+    assert!(matches!(
+        fs.remove_dir_all(Path::new("/a/../../output")),
+        Err(XfsError::PathOutsideSandbox { .. })
+    ));
+    assert!(matches!(
+        fs.rename(Path::new("index.html"), Path::new("../index.html")),
+        Err(XfsError::PathOutsideSandbox { .. })
+    ));
+    assert!(view.exists(Path::new("/output/site/index.html")));
+
+    // `..` that stays within the scope is resolved.
+    assert!(fs.is_file(Path::new("css/../index.html")));
+}
+
+#[test]
+fn test_read_dir_paths_are_scoped() {
+    let (_view, mut fs) = site();
+    fs.create_dir(Path::new("/blog")).unwrap();
+    let mut paths: Vec<PathBuf> = fs
+        .read_dir(Path::new("/"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![PathBuf::from("/blog"), PathBuf::from("/index.html")]
+    );
+}
+
+#[test]
+fn test_error_paths_are_scoped() {
+    let (_view, fs) = site();
+    match fs.reader(Path::new("/missing.html")) {
+        Err(XfsError::NotFound { path }) => assert_eq!(path, Path::new("/missing.html")),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn test_into_inner_returns_the_wrapped_filesystem() {
+    let (_view, fs) = site();
+    assert_eq!(fs.root(), Path::new("/output/site"));
+    assert_eq!(
+        fs.to_inner(Path::new("/a/./b")).unwrap(),
+        Path::new("/output/site/a/b")
+    );
+    let inner = fs.into_inner();
+    assert!(inner.is_file(Path::new("/output/secret")));
+}
@@ -0,0 +1,124 @@
+use inscenerator_xfs::clock::{FixedClock, ManualClock};
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::{OsFs, Xfs, XfsReadOnly};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+fn secs(n: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(n)
+}
+
+fn modified(fs: &dyn XfsReadOnly, p: &str) -> SystemTime {
+    fs.metadata(Path::new(p)).unwrap().modified().unwrap()
+}
+
+#[test]
+fn test_mockfs_fixed_clock() {
+    let mut fs = MockFS::with_clock(Arc::new(FixedClock(secs(100))));
+    fs.add_file(Path::new("dir/a.txt"), "a").unwrap();
+
+    let md = fs.metadata(Path::new("dir/a.txt")).unwrap();
+    assert_eq!(md.created(), Some(secs(100)));
+    assert_eq!(md.modified(), Some(secs(100)));
+    assert_eq!(md.accessed(), Some(secs(100)));
+    assert_eq!(modified(&fs, "dir"), secs(100));
+}
+
+#[test]
+fn test_mockfs_write_updates_modified() {
+    let clock = ManualClock::new(secs(0));
+    let mut fs = MockFS::with_clock(Arc::new(clock.clone()));
+    let mut w = fs.writer(Path::new("out.txt")).unwrap();
+
+    clock.advance(Duration::from_secs(10));
+    w.write_all(b"data").unwrap();
+    drop(w);
+
+    let md = fs.metadata(Path::new("out.txt")).unwrap();
+    assert_eq!(md.created(), Some(secs(0)));
+    assert_eq!(md.modified(), Some(secs(10)));
+}
+
+#[test]
+fn test_mockfs_read_updates_accessed() {
+    let clock = ManualClock::new(secs(0));
+    let mut fs = MockFS::with_clock(Arc::new(clock.clone()));
+    fs.add_file(Path::new("in.txt"), "data").unwrap();
+
+    clock.advance(Duration::from_secs(5));
+    let mut buf = String::new();
+    fs.reader(Path::new("in.txt"))
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+
+    let md = fs.metadata(Path::new("in.txt")).unwrap();
+    assert_eq!(md.accessed(), Some(secs(5)));
+    assert_eq!(md.modified(), Some(secs(0)));
+}
+
+#[test]
+fn test_mockfs_rename_updates_parents() {
+    let clock = ManualClock::new(secs(0));
+    let mut fs = MockFS::with_clock(Arc::new(clock.clone()));
+    fs.add_file(Path::new("from/a.txt"), "a").unwrap();
+    fs.create_dir(Path::new("to")).unwrap();
+
+    clock.advance(Duration::from_secs(3));
+    fs.rename(Path::new("from/a.txt"), Path::new("to/a.txt"))
+        .unwrap();
+
+    assert_eq!(modified(&fs, "from"), secs(3));
+    assert_eq!(modified(&fs, "to"), secs(3));
+    // The renamed file itself is not modified.
+    assert_eq!(modified(&fs, "to/a.txt"), secs(0));
+}
+
+#[test]
+fn test_mockfs_mtime_ordering() {
+    let clock = ManualClock::new(secs(0));
+    let mut fs = MockFS::with_clock(Arc::new(clock.clone()));
+    fs.add_file(Path::new("input.txt"), "v1").unwrap();
+    clock.advance(Duration::from_secs(1));
+    fs.add_file(Path::new("output.txt"), "built from v1")
+        .unwrap();
+    assert!(modified(&fs, "input.txt") < modified(&fs, "output.txt"));
+
+    clock.advance(Duration::from_secs(1));
+    fs.writer(Path::new("input.txt"))
+        .unwrap()
+        .write_all(b"v2")
+        .unwrap();
+    assert!(modified(&fs, "input.txt") > modified(&fs, "output.txt"));
+}
+
+#[test]
+fn test_mockfs_set_times() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("a.txt"), "a").unwrap();
+    fs.set_times(Path::new("a.txt"), Some(secs(1)), Some(secs(2)))
+        .unwrap();
+    fs.set_times(Path::new("a.txt"), None, Some(secs(3)))
+        .unwrap();
+
+    let md = fs.metadata(Path::new("a.txt")).unwrap();
+    assert_eq!(md.accessed(), Some(secs(1)));
+    assert_eq!(md.modified(), Some(secs(3)));
+}
+
+#[test]
+fn test_osfs_set_times() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut fs = OsFs {};
+    let path = temp_dir.path().join("test.txt");
+
+    fs.writer(&path).unwrap().write_all(b"hello").unwrap();
+    fs.set_times(&path, Some(secs(1000)), Some(secs(2000)))
+        .unwrap();
+
+    let md = fs.metadata(&path).unwrap();
+    assert_eq!(md.accessed(), Some(secs(1000)));
+    assert_eq!(md.modified(), Some(secs(2000)));
+}
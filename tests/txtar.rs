@@ -0,0 +1,117 @@
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::txtar::{Archive, ArchiveFile};
+use inscenerator_xfs::{OsFs, XfsError, XfsReadOnly};
+use std::path::Path;
+
+const FIXTURE: &str = "\
+Project fixture.
+-- README.md --
+# Hello
+-- src/lib.rs --
+pub fn f() {}
+
+-- src/empty.rs --
+-- target/ --
+";
+
+#[test]
+fn test_txtar_parse() {
+    let archive = Archive::parse(FIXTURE);
+    assert_eq!(archive.comment, "Project fixture.\n");
+    let names: Vec<&str> = archive.files.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec!["README.md", "src/lib.rs", "src/empty.rs", "target/"]
+    );
+    assert_eq!(archive.files[1].data, "pub fn f() {}\n\n");
+    assert_eq!(archive.files[2].data, "");
+    assert!(archive.files[3].is_dir());
+}
+
+#[test]
+fn test_mockfs_from_txtar() {
+    let fs = MockFS::from_txtar(FIXTURE).unwrap();
+    assert_eq!(fs.get_str(Path::new("README.md")).unwrap(), "# Hello\n");
+    assert_eq!(fs.get_str(Path::new("src/empty.rs")).unwrap(), "");
+    assert!(fs.is_dir(Path::new("target")));
+}
+
+#[test]
+fn test_mockfs_txtar_round_trip() {
+    let fs = MockFS::from_txtar(FIXTURE).unwrap();
+    let expected = "\
+-- README.md --
+# Hello
+-- src/empty.rs --
+-- src/lib.rs --
+pub fn f() {}
+
+-- target/ --
+";
+    assert_eq!(fs.to_txtar().unwrap(), expected);
+    assert_eq!(
+        MockFS::from_txtar(expected).unwrap().to_txtar().unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn test_txtar_adds_missing_newline() {
+    let archive = Archive {
+        comment: String::new(),
+        files: vec![ArchiveFile {
+            name: "a.txt".to_string(),
+            data: "no newline".to_string(),
+        }],
+    };
+    assert_eq!(archive.format(), "-- a.txt --\nno newline\n");
+}
+
+#[test]
+fn test_mockfs_to_txtar_binary_fails() {
+    let mut fs = MockFS::new();
+    fs.add_r(Path::new("bin"), vec![0xff, 0xfe]).unwrap();
+    assert!(matches!(fs.to_txtar(), Err(XfsError::InvalidUtf8 { .. })));
+}
+
+#[test]
+fn test_mockfs_to_txtar_rejects_header_lines() {
+    let mut fs = MockFS::new();
+    fs.add_file(Path::new("notes.md"), "intro\n-- not a file --\nmore\n")
+        .unwrap();
+    assert!(matches!(fs.to_txtar(), Err(XfsError::GeneralError { .. })));
+}
+
+#[test]
+fn test_txtar_with_osfs() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut fs = OsFs {};
+    Archive::parse(FIXTURE)
+        .write_to(&mut fs, temp_dir.path())
+        .unwrap();
+    assert!(fs.is_dir(&temp_dir.path().join("target")));
+
+    let archive = Archive::from_fs(&fs, temp_dir.path()).unwrap();
+    let mock = MockFS::from_txtar(FIXTURE).unwrap();
+    assert_eq!(archive, Archive::from_fs(&mock, Path::new("")).unwrap());
+}
+
+#[test]
+fn test_txtar_rejects_names_outside_root() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path().join("root");
+    let mut fs = OsFs {};
+    for name in ["../x", "a/../../x", "./x", "/abs/x"] {
+        let archive = Archive::parse(&format!("-- a.txt --\na\n-- {} --\nx\n", name));
+        assert!(
+            matches!(
+                archive.write_to(&mut fs, &root),
+                Err(XfsError::GeneralError { .. })
+            ),
+            "{}",
+            name
+        );
+    }
+    assert!(!fs.exists(&root));
+    assert!(!fs.exists(&temp_dir.path().join("x")));
+}
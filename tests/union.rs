@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+
+use inscenerator_xfs::mockfs::MockFS;
+use inscenerator_xfs::union::UnionFs;
+use inscenerator_xfs::{XfsError, XfsReadOnly};
+
+fn themes() -> UnionFs {
+    let mut project = MockFS::new();
+    project
+        .add_file(Path::new("/templates/page.html"), "project page")
+        .unwrap();
+    project
+        .add_file(Path::new("/style.css"), "project")
+        .unwrap();
+
+    let mut user = MockFS::new();
+    user.add_file(Path::new("/templates/post.html"), "user post")
+        .unwrap();
+    user.add_file(Path::new("/style.css/nested"), "").unwrap();
+
+    let mut builtin = MockFS::new();
+    builtin
+        .add_file(Path::new("/templates/page.html"), "builtin page")
+        .unwrap();
+    builtin
+        .add_file(Path::new("/templates/post.html"), "builtin post")
+        .unwrap();
+    builtin
+        .add_file(Path::new("/templates/list.html"), "builtin list")
+        .unwrap();
+
+    UnionFs::new(vec![Box::new(project), Box::new(user), Box::new(builtin)])
+}
+
+#[test]
+fn test_first_layer_containing_a_path_wins() {
+    let fs = themes();
+    let read = |p: &str| fs.read_all_lines(Path::new(p)).unwrap();
+    assert_eq!(read("/templates/page.html"), vec!["project page"]);
+    assert_eq!(read("/templates/post.html"), vec!["user post"]);
+    assert_eq!(read("/templates/list.html"), vec!["builtin list"]);
+
+    assert_eq!(fs.layer_of(Path::new("/templates/page.html")).unwrap(), 0);
+    assert_eq!(fs.layer_of(Path::new("/templates/post.html")).unwrap(), 1);
+    assert_eq!(fs.layer_of(Path::new("/templates/list.html")).unwrap(), 2);
+    assert_eq!(fs.layer_count(), 3);
+}
+
+#[test]
+fn test_read_dir_merges_layers() {
+    let fs = themes();
+    let mut paths: Vec<PathBuf> = fs
+        .read_dir(Path::new("/templates"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![
+            PathBuf::from("/templates/list.html"),
+            PathBuf::from("/templates/page.html"),
+            PathBuf::from("/templates/post.html"),
+        ]
+    );
+}
+
+#[test]
+fn test_earlier_files_hide_later_directories() {
+    let fs = themes();
+    assert!(fs.is_file(Path::new("/style.css")));
+    assert!(matches!(
+        fs.read_dir(Path::new("/style.css")),
+        Err(XfsError::NotADirectory { .. })
+    ));
+    let names: Vec<PathBuf> = fs
+        .read_dir(Path::new("/"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.ends_with("style.css"))
+        .collect();
+    assert_eq!(names.len(), 1);
+}
+
+#[test]
+fn test_missing_paths_are_not_found() {
+    let fs = themes();
+    assert!(!fs.exists(Path::new("/missing")));
+    assert!(matches!(
+        fs.layer_of(Path::new("/missing")),
+        Err(XfsError::NotFound { .. })
+    ));
+    assert!(matches!(
+        UnionFs::new(vec![]).metadata(Path::new("/")),
+        Err(XfsError::NotFound { .. })
+    ));
+}